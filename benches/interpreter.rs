@@ -0,0 +1,24 @@
+// Witness computation with a fresh interpreter per call and with the
+// shared one.
+//
+//     cargo bench --bench interpreter
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use harla_zk::artifacts::sample_request;
+use harla_zk::field::field_from_dec_str;
+use harla_zk::zk::{build_arguments, execute_witness, execute_witness_with};
+use zokrates_core::ir::Interpreter;
+
+fn interpreter(c: &mut Criterion) {
+    let arguments =
+        build_arguments(&sample_request(field_from_dec_str("7999").unwrap(), 18)).unwrap();
+    c.bench_function("witness fresh interpreter", |b| {
+        b.iter(|| execute_witness_with(&Interpreter::default(), &arguments).unwrap())
+    });
+    c.bench_function("witness shared interpreter", |b| {
+        b.iter(|| execute_witness(&arguments).unwrap())
+    });
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
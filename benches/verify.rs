@@ -0,0 +1,81 @@
+// Time and heap allocations of one verification, next to the ZoKrates
+// verifier.
+//
+//     cargo bench --bench verify
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use harla_zk::artifacts::sample_request;
+use harla_zk::field::field_from_dec_str;
+use harla_zk::zk::{generate_proof, to_zokrates_proof_json, verify_proof};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zokrates_core::proof_system::{
+    bellman::groth16::{ProofPoints, G16},
+    Proof, ProofSystem,
+};
+use zokrates_field::Bn128Field;
+
+type ZokratesKey = <G16 as ProofSystem<Bn128Field>>::VerificationKey;
+
+/// Counts the allocations of the whole process.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Allocations made by `f`.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn verify(c: &mut Criterion) {
+    let rq = sample_request(field_from_dec_str("7999").unwrap(), 18);
+    let chain = rq.chain.clone();
+    let qr = generate_proof(rq).unwrap();
+
+    // The baseline is the ZoKrates verifier, which takes the key and the
+    // proof by value with the inputs as hex strings.
+    let vk_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("zokrates/verification.key");
+    let vk: ZokratesKey = serde_json::from_slice(&fs::read(vk_path).unwrap()).unwrap();
+    let proof: Proof<ProofPoints> =
+        serde_json::from_str(&to_zokrates_proof_json(&qr, &chain).unwrap()).unwrap();
+    let zokrates = || <G16 as ProofSystem<Bn128Field>>::verify(vk.clone(), proof.clone());
+
+    // The first verification parses the verification key.
+    verify_proof(&qr, &chain).unwrap();
+    let ours = allocations(|| {
+        verify_proof(&qr, &chain).unwrap();
+    });
+    let baseline = allocations(|| assert!(zokrates()));
+    println!(
+        "allocations per verification: {}, ZoKrates verifier: {}",
+        ours, baseline
+    );
+
+    let mut group = c.benchmark_group("verify");
+    group.bench_function("verify_proof", |b| {
+        b.iter(|| verify_proof(&qr, &chain).unwrap())
+    });
+    group.bench_function("zokrates", |b| b.iter(zokrates));
+    group.finish();
+}
+
+criterion_group!(benches, verify);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+// Sends the spans of a proof and its verification to an OpenTelemetry
+// collector listening on the default OTLP port, e.g. a local Jaeger:
+//
+//     docker run -p 4317:4317 -p 16686:16686 jaegertracing/all-in-one
+//     cargo run --example otlp --features tracing
+//
+// The spans carry the relation, the day and the outcome; the secrets
+// are never recorded.
+
+use harla_zk::api::{age_to_delta, Private, PublicChain, PublicQr, QrRequest, Relation};
+use harla_zk::field::field_from_dec_str;
+use harla_zk::zk::{generate_proof, generate_prover_key, verify_proof};
+use tracing_subscriber::layer::SubscriberExt;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (tracer, _uninstall) = opentelemetry_otlp::new_pipeline()
+        .with_service_name("harla_zk")
+        .install()?;
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let private = Private {
+        birthday: 2451711,
+        nonce: field_from_dec_str("7999")?,
+    };
+    let photo_hash = field_from_dec_str("3")?;
+    let contract = field_from_dec_str("4")?;
+    let chain = PublicChain {
+        prover_key: generate_prover_key(&private, &contract, &photo_hash),
+        photo_hash,
+    };
+    let rq = QrRequest {
+        qr: PublicQr {
+            today: 2459231,
+            relation: Relation::Older,
+            delta: age_to_delta(private.birthday, 18, Relation::Older),
+            contract,
+            label: None,
+        },
+        chain: chain.clone(),
+        private,
+    };
+    let qr = generate_proof(rq)?;
+    verify_proof(&qr, &chain)?;
+    println!("proved and verified, see the collector for the spans");
+    Ok(())
+}
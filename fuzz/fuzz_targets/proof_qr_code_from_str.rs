@@ -0,0 +1,14 @@
+// QR payloads are attacker-controlled; parsing must fail cleanly.
+#![no_main]
+use harla_zk::api::ProofQrCode;
+use libfuzzer_sys::fuzz_target;
+use std::str::FromStr;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(qr) = ProofQrCode::from_str(s) {
+            // Anything accepted must survive a round trip.
+            ProofQrCode::from_str(&qr.to_string()).expect("round trip failed");
+        }
+    }
+});
@@ -0,0 +1,31 @@
+// Proofs are attacker-controlled; verification must return an error,
+// never panic.
+#![no_main]
+use harla_zk::api::{ProofQrCode, PublicChain, PublicQr, Relation};
+use harla_zk::zk::verify_proof;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (i32, bool, i32, Vec<u8>, Vec<u8>)| {
+    let (today, is_younger, delta, contract, proof) = input;
+    let qr = ProofQrCode {
+        public: PublicQr {
+            today,
+            relation: if is_younger {
+                Relation::Younger
+            } else {
+                Relation::Older
+            },
+            delta,
+            contract,
+            label: None,
+        },
+        proof,
+    };
+    let mut photo_hash = vec![0; 32];
+    photo_hash[0] = 3;
+    let chain = PublicChain {
+        photo_hash,
+        prover_key: vec![7; 32],
+    };
+    assert!(verify_proof(&qr, &chain).is_err());
+});
@@ -1,16 +1,112 @@
+use crate::base45;
+use crate::clock::Clock;
+use crate::error::ZkError;
+use crate::field::{canonical_field_bytes, field_from_str, field_to_bytes};
 use chrono::{Datelike, NaiveDate};
+use qrcode::{QrCode, Version};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::fmt;
 use std::str::FromStr;
+use zokrates_field::Bn128Field;
 
-/// The relation to be proved.
-#[derive(PartialEq, Debug, Copy, Clone)]
+/// The relation to be proved. See `age_to_delta_from_dates` for what
+/// each relation means on the day of the birthday.
+#[derive(PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Relation {
     Younger,
     Older,
+    /// Younger than the age or having the birthday today. The circuit
+    /// compares as for `Younger`, only the delta is larger.
+    #[serde(rename = "younger_or_equal")]
+    YoungerOrEqual,
+    /// Older than the age or having the birthday today. The circuit
+    /// compares as for `Older`, only the delta is smaller.
+    #[serde(rename = "older_or_equal")]
+    OlderOrEqual,
+}
+
+impl Relation {
+    /// Number representing the relation in the QR code.
+    pub fn to_code(self) -> u8 {
+        match self {
+            Relation::Older => 0,
+            Relation::Younger => 1,
+            Relation::YoungerOrEqual => 2,
+            Relation::OlderOrEqual => 3,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Relation> {
+        match code {
+            0 => Some(Relation::Older),
+            1 => Some(Relation::Younger),
+            2 => Some(Relation::YoungerOrEqual),
+            3 => Some(Relation::OlderOrEqual),
+            _ => None,
+        }
+    }
+
+    /// Whether the circuit compares for a younger user.
+    pub fn is_younger(self) -> bool {
+        match self {
+            Relation::Younger | Relation::YoungerOrEqual => true,
+            Relation::Older | Relation::OlderOrEqual => false,
+        }
+    }
+
+    /// The opposite statement about the same age: not older than N is
+    /// younger than or equal to N and so on. Because of the margin of a
+    /// day kept by the strict relations (see `age_to_delta_from_dates`)
+    /// a relation and its negation both fail on the day after or before
+    /// the birthday.
+    pub fn negate(self) -> Relation {
+        match self {
+            Relation::Older => Relation::YoungerOrEqual,
+            Relation::YoungerOrEqual => Relation::Older,
+            Relation::Younger => Relation::OlderOrEqual,
+            Relation::OlderOrEqual => Relation::Younger,
+        }
+    }
+
+    /// Whether `delta` is one that `age_to_delta` gives for the relation
+    /// and some age. The circuit takes only `is_younger`, so a proof of
+    /// `OlderOrEqual` relabelled as `Older` passes the pairing check, and
+    /// so does a `Younger` one relabelled as `YoungerOrEqual`. The delta
+    /// tells them apart: the days to the `age`-th birthday are
+    /// `365 * age + age / 4` or one more while every fourth year is a
+    /// leap year, i.e. for birthdays from March 1900 to February 2100,
+    /// and the two relations of a pair are one day above and one day
+    /// below. A true proof whose birthday and today span 1900 or 2100
+    /// may be refused.
+    pub fn fits_delta(self, delta: i32) -> bool {
+        let days = match self {
+            Relation::Older | Relation::YoungerOrEqual => i64::from(delta) - 1,
+            Relation::Younger | Relation::OlderOrEqual => i64::from(delta) + 1,
+        };
+        let approx = days / 365;
+        (approx - 1..=approx + 1)
+            .filter(|age| *age >= 1)
+            .any(|age| {
+                let leap_free = 365 * age + age / 4;
+                days == leap_free || days == leap_free + 1
+            })
+    }
+
+    /// Evaluates the comparison done by the circuit.
+    pub fn holds(self, birthday: i32, delta: i32, today: i32) -> bool {
+        if self.is_younger() {
+            birthday + delta > today
+        } else {
+            birthday + delta < today
+        }
+    }
 }
 
 /// Public part of the proof. The fields included in the QR code.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PublicQr {
     /// Today julian date.
     pub today: i32,
@@ -21,9 +117,35 @@ pub struct PublicQr {
     /// Minimal (maximal) difference between 'today' and 'birthday' in days.
     pub delta: i32,
 
-    /// Contract address on the blockchain. Big-endian encoded number in Field
-    /// range.
+    /// Contract address on the blockchain. Field element in the
+    /// little-endian encoding of `field::field_to_bytes`.
     pub contract: Vec<u8>,
+
+    /// Free text shown to the verifier, e.g. "BarXYZ age-gate". It is
+    /// neither a circuit input nor part of `ProofQrCode::fingerprint`,
+    /// so anyone holding the QR code may change it; never decide on it.
+    /// At most `MAX_LABEL_BYTES` bytes.
+    pub label: Option<String>,
+}
+
+/// Longest `PublicQr::label` in bytes. A byte of label costs a byte of
+/// the QR code, which the proof nearly fills already.
+pub const MAX_LABEL_BYTES: usize = 64;
+
+/// Rejects a label longer than `MAX_LABEL_BYTES` or with control
+/// characters, which a verifier would print as they are.
+pub fn check_label(label: &Option<String>) -> Result<(), ZkError> {
+    match label {
+        Some(l) if l.len() > MAX_LABEL_BYTES => Err(ZkError::InvalidRequest(format!(
+            "label has {} bytes, at most {} fit",
+            l.len(),
+            MAX_LABEL_BYTES
+        ))),
+        Some(l) if l.chars().any(char::is_control) => Err(ZkError::InvalidRequest(String::from(
+            "label contains control characters",
+        ))),
+        _ => Ok(()),
+    }
 }
 
 impl PublicQr {
@@ -34,6 +156,38 @@ impl PublicQr {
             relation: Relation::Younger,
             delta: 0,
             contract: Vec::new(),
+            label: None,
+        }
+    }
+
+    /// Public fields with `today` given as a date rather than a julian
+    /// day number.
+    pub fn on_date(today: NaiveDate, relation: Relation, delta: i32, contract: Vec<u8>) -> Self {
+        PublicQr {
+            today: JulianDay::from(today).into(),
+            relation,
+            delta,
+            contract,
+            label: None,
+        }
+    }
+
+    /// Public fields claiming `relation` to `age` on `today`. The delta is
+    /// computed from the prover's birthday, which stays private; only the
+    /// delta goes into the QR code.
+    pub fn for_age(
+        birthday: i32,
+        today: NaiveDate,
+        relation: Relation,
+        age: i32,
+        contract: Vec<u8>,
+    ) -> Self {
+        PublicQr {
+            today: naive_date_to_jd(today),
+            relation,
+            delta: age_to_delta(birthday, age, relation),
+            contract,
+            label: None,
         }
     }
 
@@ -42,7 +196,7 @@ impl PublicQr {
             r#""today": {}, "delta": {}, "relation":{}, "contract": 0x{}"#,
             self.today,
             self.delta,
-            self.relation.clone() as u8,
+            self.relation.to_code(),
             hex::encode(&self.contract)
         )
     }
@@ -51,8 +205,8 @@ impl PublicQr {
 /// Public part of the proof. The fields stored on-chain.
 #[derive(Debug, Clone)]
 pub struct PublicChain {
-    /// Digest of the photo. Big-endian encoded number in Field
-    /// range.
+    /// Digest of the photo. Field element in the little-endian
+    /// encoding of `field::field_to_bytes`.
     pub photo_hash: Vec<u8>,
 
     /// Prover key computed by a one-way function from the private part of the proof.
@@ -67,6 +221,43 @@ impl PublicChain {
             prover_key: Vec::new(),
         }
     }
+
+    /// Parses the chain values, each decimal or `0x` prefixed hex as
+    /// `field_from_str` accepts.
+    pub fn from_strs(photo_hash: &str, prover_key: &str) -> Result<Self, ZkError> {
+        Ok(PublicChain {
+            photo_hash: field_from_str(photo_hash)?,
+            prover_key: field_from_str(prover_key)?,
+        })
+    }
+}
+
+/// The prover's secret nonce as a field element. `Debug` does not show
+/// the value.
+#[derive(Clone, PartialEq)]
+pub struct Nonce(pub Bn128Field);
+
+impl Nonce {
+    /// Canonical 32 byte encoding, as in `Private::nonce`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        field_to_bytes(&self.0)
+    }
+
+    pub fn as_field(&self) -> &Bn128Field {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Nonce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Nonce(..)")
+    }
+}
+
+impl From<Nonce> for Vec<u8> {
+    fn from(nonce: Nonce) -> Self {
+        nonce.to_bytes()
+    }
 }
 
 /// Private part of the proof
@@ -77,7 +268,8 @@ pub struct Private {
 
     /// Private nonce known only to the prover and to the
     /// certifier. Prevents brute-force attacks using the limited number
-    /// of birthdays.  Big-endian encoded number in Field range.
+    /// of birthdays. Field element in the little-endian encoding of
+    /// `field::field_to_bytes`.
     pub nonce: Vec<u8>,
 }
 
@@ -93,24 +285,176 @@ impl Private {
 
 static COMMON_ERA_JD: i32 = 1721425;
 
+/// Julian day number of a date in the proleptic Gregorian calendar, i.e.
+/// the Gregorian rules apply before 1582 too. Years are astronomical:
+/// year 0 is 1 BCE and is a leap year.
+///
+/// Every `NaiveDate` converts without overflow: chrono's dates span about
+/// ±262,000 years which is ±96 million days while `i32` holds ±2,147
+/// million. Days before 4713 BCE have negative numbers; the circuit
+/// compares days as small positive numbers so such dates cannot be
+/// proved.
 pub fn naive_date_to_jd(nd: NaiveDate) -> i32 {
     nd.num_days_from_ce() + COMMON_ERA_JD
 }
 
+/// Julian day number, the unit of dates in the QR code and the circuit.
+/// Unlike a bare `i32` it cannot be mistaken for a year.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone, Hash)]
+pub struct JulianDay(pub i32);
+
+impl JulianDay {
+    /// `None` outside of the chrono range.
+    pub fn to_naive_date(self) -> Option<NaiveDate> {
+        jd_to_naive_date(self.0)
+    }
+}
+
+impl From<NaiveDate> for JulianDay {
+    fn from(date: NaiveDate) -> Self {
+        JulianDay(naive_date_to_jd(date))
+    }
+}
+
+impl From<JulianDay> for i32 {
+    fn from(jd: JulianDay) -> Self {
+        jd.0
+    }
+}
+
+/// Inverse of `naive_date_to_jd`. `None` outside of the chrono range.
+pub fn jd_to_naive_date(jd: i32) -> Option<NaiveDate> {
+    NaiveDate::from_num_days_from_ce_opt(jd.checked_sub(COMMON_ERA_JD)?)
+}
+
+/// Bits of the operands of a comparison of field elements in the
+/// circuit. ZoKrates decomposes both sides of `<` into two bits fewer
+/// than the 254 bits of the Bn128 field, so larger values compare wrong.
+pub const COMPARISON_BITS: u32 = 252;
+
+/// Bounds of the dates and ages that can be proved, see
+/// `supported_ranges`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct SupportedRanges {
+    /// Earliest julian day of the birthday and of `today`.
+    pub min_day: i32,
+    /// Latest julian day of the birthday and of `today`.
+    pub max_day: i32,
+    /// First and last year in which every birthday is within the days.
+    pub min_birth_year: i32,
+    pub max_birth_year: i32,
+    /// Largest age that can be proved, by someone born in
+    /// `min_birth_year`.
+    pub max_age: i32,
+    /// Bits of the circuit's comparison, `COMPARISON_BITS`.
+    pub comparison_bits: u32,
+}
+
+/// The circuit compares `birthday + delta` with `today`. Below
+/// `2^COMPARISON_BITS` the comparison is exact, which any sum of two
+/// `i32` is, so the bounds come from the encoding of the days instead:
+/// a negative day is a field element just below the modulus and there
+/// are no dates after chrono's last one. Day 0 is November 24, 4714 BCE.
+pub fn supported_ranges() -> SupportedRanges {
+    let max_date = chrono::naive::MAX_DATE;
+    let first_year = jd_to_naive_date(0).map_or(-4713, |d| d.year());
+    SupportedRanges {
+        min_day: 0,
+        max_day: naive_date_to_jd(max_date),
+        min_birth_year: first_year + 1,
+        max_birth_year: max_date.year() - 1,
+        max_age: max_date.year() - first_year - 1,
+        comparison_bits: COMPARISON_BITS,
+    }
+}
+
+impl SupportedRanges {
+    /// Checks the days and the age of a request, naming the first value
+    /// out of range.
+    pub fn check_request(&self, birthday: i32, today: i32, age: i32) -> Result<(), ZkError> {
+        let out_of_range = |name: &str, value: i32| {
+            ZkError::InvalidRequest(format!(
+                "{} {} is outside of the supported days {} to {}",
+                name, value, self.min_day, self.max_day
+            ))
+        };
+        if birthday < self.min_day || birthday > self.max_day {
+            return Err(out_of_range("birthday", birthday));
+        }
+        if today < self.min_day || today > self.max_day {
+            return Err(out_of_range("today", today));
+        }
+        let last = jd_to_naive_date(self.max_day).map_or(0, |d| d.year());
+        let birth_year = jd_to_naive_date(birthday).map_or(0, |d| d.year());
+        if age > self.max_age || birth_year + age > last {
+            return Err(ZkError::InvalidRequest(format!(
+                "age {} has its birthday after the latest supported year {}",
+                age, last
+            )));
+        }
+        Ok(())
+    }
+}
+
 pub fn age_to_delta(birthday: i32, age: i32, relation: Relation) -> i32 {
-    let dbirth = NaiveDate::from_num_days_from_ce(birthday - COMMON_ERA_JD);
-    let dtest =
-        NaiveDate::from_ymd_opt(dbirth.year() + age, dbirth.month(), dbirth.day()).unwrap_or(
-            NaiveDate::from_ymd(dbirth.year() + age, dbirth.month(), dbirth.day() - 1),
-        );
+    checked_age_to_delta(birthday, age, relation).expect("birthday or age out of range")
+}
+
+/// Delta for a calendar birthday, the same as `age_to_delta` gives for
+/// its julian day: the days to the `age`-th birthday plus one for
+/// `Older` and `YoungerOrEqual` and minus one for `Younger` and
+/// `OlderOrEqual`. A birthday on February 29 falls on February 28 in
+/// common years. The strict comparisons keep the margin of a day, so on
+/// the `age`-th birthday the user is neither older nor younger than
+/// `age`:
+///
+/// * `Older` holds from the second day after the birthday,
+/// * `Younger` holds until the second day before it,
+/// * `OlderOrEqual` holds from the birthday on,
+/// * `YoungerOrEqual` holds until and including the birthday.
+///
+/// So a user whose `age`-th birthday is `today` proves it with the
+/// inclusive relations; `Older` is first true the day after next. The
+/// delta depends on the birthday only, `today` merely rules out a
+/// birthday in the future: returns `None` when the birthday is after
+/// `today` or the dates are out of range.
+pub fn age_to_delta_from_dates(
+    birthday: NaiveDate,
+    today: NaiveDate,
+    relation: Relation,
+    age: i32,
+) -> Option<i32> {
+    if birthday > today {
+        return None;
+    }
+    checked_age_to_delta(naive_date_to_jd(birthday), age, relation)
+}
+
+fn checked_age_to_delta(birthday: i32, age: i32, relation: Relation) -> Option<i32> {
+    let dbirth = jd_to_naive_date(birthday)?;
+    let year = dbirth.year().checked_add(age)?;
+    let dtest = NaiveDate::from_ymd_opt(year, dbirth.month(), dbirth.day())
+        .or_else(|| NaiveDate::from_ymd_opt(year, dbirth.month(), dbirth.day() - 1))?;
     let delta = dtest.signed_duration_since(dbirth).num_days() as i32;
-    if relation == Relation::Older {
-        delta + 1
-    } else {
-        delta - 1
+    match relation {
+        Relation::Older | Relation::YoungerOrEqual => Some(delta + 1),
+        Relation::Younger | Relation::OlderOrEqual => Some(delta - 1),
     }
 }
 
+/// Finds the age (in whole years) for which `age_to_delta` would give
+/// `delta` under `relation`. Returns `None` when `delta` was not computed
+/// for this birthday and relation.
+fn delta_to_age(birthday: i32, delta: i32, relation: Relation) -> Option<i32> {
+    if delta <= 0 {
+        return None;
+    }
+    let approx = (f64::from(delta) / 365.2425).round() as i32;
+    (approx - 1..=approx + 1)
+        .filter(|age| *age >= 1)
+        .find(|age| checked_age_to_delta(birthday, *age, relation) == Some(delta))
+}
+
 /// Request for QR code generation from phone app.
 #[derive(Debug)]
 pub struct QrRequest {
@@ -129,6 +473,18 @@ impl QrRequest {
         }
     }
 
+    /// Sets `today` to the date, e.g. one from a trusted source rather
+    /// than the device's clock.
+    pub fn with_today(mut self, date: NaiveDate) -> Self {
+        self.qr.today = naive_date_to_jd(date);
+        self
+    }
+
+    /// Sets `today` to the date of the clock.
+    pub fn with_clock(self, clock: &dyn Clock) -> Self {
+        self.with_today(clock.today())
+    }
+
     pub fn to_qr_code_string() -> String {
         String::from("")
     }
@@ -137,12 +493,97 @@ impl QrRequest {
         QrRequest::new()
     }
 
+    /// Checks that `delta` was computed by `age_to_delta` for the same
+    /// birthday and relation. A delta computed for the opposite relation
+    /// is off by two days and would silently prove a different
+    /// statement.
+    pub fn is_delta_consistent(&self) -> bool {
+        delta_to_age(self.private.birthday, self.qr.delta, self.qr.relation).is_some()
+    }
+
+    /// Tells whether the requested statement is true. Only the prover
+    /// may learn this; see `ProofPolicy`.
     pub fn is_relation_valid(&self) -> bool {
-        match self.qr.relation {
-            Relation::Younger => self.private.birthday + self.qr.delta > self.qr.today,
-            Relation::Older => self.private.birthday + self.qr.delta < self.qr.today,
+        self.qr
+            .relation
+            .holds(self.private.birthday, self.qr.delta, self.qr.today)
+    }
+}
+
+/// What to do when the prover is asked to prove a false statement.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum ProofPolicy {
+    /// Generate a proof which fails the verification. It takes the same
+    /// time and looks like any other proof, so someone holding the phone
+    /// cannot learn the age by trying different statements. This is the
+    /// only safe choice when the request may come from anyone but the
+    /// owner.
+    #[default]
+    FailSilently,
+
+    /// Refuse with `ZkError::StatementFalse`. The caller learns that the
+    /// statement is false, so use this only in a trusted local app which
+    /// wants to warn its owner before showing a useless QR code.
+    ReturnError,
+}
+
+/// Whether the `age`-th birthday itself counts as having the age. The
+/// circuit has no notion of the hour, so `StrictlyAfter` makes the user
+/// wait until the day after, which is the behavior of `Older`.
+#[derive(PartialEq, Debug, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryPolicy {
+    /// The age is reached after the birthday; `OlderOrEqual` claims too
+    /// much.
+    #[default]
+    StrictlyAfter,
+    /// The age is reached on the birthday; `YoungerOrEqual` claims too
+    /// much.
+    OnOrAfter,
+}
+
+impl BoundaryPolicy {
+    /// The relation to prove for `older than` under the policy. Other
+    /// relations stay as they are.
+    pub fn relation(self, relation: Relation) -> Relation {
+        match (self, relation) {
+            (BoundaryPolicy::OnOrAfter, Relation::Older) => Relation::OlderOrEqual,
+            _ => relation,
         }
     }
+
+    /// Whether a verifier following the policy takes a proof of the
+    /// relation.
+    pub fn accepts(self, relation: Relation) -> bool {
+        match self {
+            BoundaryPolicy::StrictlyAfter => relation != Relation::OlderOrEqual,
+            BoundaryPolicy::OnOrAfter => relation != Relation::YoungerOrEqual,
+        }
+    }
+}
+
+/// Whether a request would give a verifiable proof, see
+/// `zk::precheck_request`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum RequestValidity {
+    /// The statement is true and the proof will verify.
+    Provable,
+    /// The statement is false; the proof would fail the verification.
+    StatementFalse,
+    /// The delta was not computed for the relation and the birthday.
+    DeltaMismatch,
+}
+
+/// Public inputs of a proof which passed the verification, decoded for
+/// audit records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedInputs {
+    pub today: NaiveDate,
+    pub relation: Relation,
+    pub delta: i32,
+    pub contract: Bn128Field,
+    pub photo_hash: Bn128Field,
+    pub prover_key: Bn128Field,
 }
 
 /// QR code containing the proof. Is generated by the prover and
@@ -155,30 +596,425 @@ pub struct ProofQrCode {
     pub proof: Vec<u8>,
 }
 
-#[derive(Debug, Clone)]
-pub struct QrError {}
+/// Version of the `canonical_bytes` layout.
+const CANONICAL_VERSION: u8 = 1;
+
+impl ProofQrCode {
+    /// Deterministic binary form for hashing and signing. Layout:
+    ///
+    /// | bytes | field                                            |
+    /// |-------|--------------------------------------------------|
+    /// | 1     | layout version, currently 1                      |
+    /// | 4     | today, big-endian i32                            |
+    /// | 1     | relation code                                    |
+    /// | 4     | delta, big-endian i32                            |
+    /// | 1     | n, length of the contract, 32 for valid proofs   |
+    /// | n     | contract, big-endian, left-padded to 32 bytes    |
+    /// | 2     | m, length of the proof, big-endian u16           |
+    /// | m     | proof bytes                                      |
+    ///
+    /// The label is left out: editing it does not make another proof.
+    ///
+    /// Panics if the contract does not fit in 255 bytes or the proof in
+    /// 65535. Parsed proofs always fit, see `check_lengths`.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let contract = self.canonical_contract();
+        let mut out = Vec::with_capacity(13 + contract.len() + self.proof.len());
+        out.push(CANONICAL_VERSION);
+        out.extend_from_slice(&self.public.today.to_be_bytes());
+        out.push(self.public.relation.to_code());
+        out.extend_from_slice(&self.public.delta.to_be_bytes());
+        out.push(u8::try_from(contract.len()).expect("contract too long"));
+        out.extend_from_slice(&contract);
+        let proof_len = u16::try_from(self.proof.len()).expect("proof too long");
+        out.extend_from_slice(&proof_len.to_be_bytes());
+        out.extend_from_slice(&self.proof);
+        out
+    }
+
+    /// Refuses a contract or a proof longer than `canonical_bytes` can
+    /// write, so that a parsed proof can always be hashed.
+    fn check_lengths(self) -> Result<Self, QrError> {
+        if self.canonical_contract().len() > usize::from(u8::MAX)
+            || self.proof.len() > usize::from(u16::MAX)
+        {
+            return Err(QrError::Malformed);
+        }
+        Ok(self)
+    }
+
+    /// Reads back the output of `canonical_bytes`. The contract comes
+    /// back as 32 bytes.
+    pub fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, QrError> {
+        match ProofQrCode::read_canonical(bytes)? {
+            (qr, []) => Ok(qr),
+            _ => Err(QrError::Malformed),
+        }
+    }
+
+    /// Reads `canonical_bytes` from the front of `bytes` and returns the
+    /// bytes after them.
+    fn read_canonical(bytes: &[u8]) -> Result<(Self, &[u8]), QrError> {
+        let mut rest = bytes;
+        let mut take = |n: usize| split_off(&mut rest, n);
+        let i32_be = |b: &[u8]| i32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+
+        let version = take(1)?[0];
+        if version > CANONICAL_VERSION {
+            return Err(QrError::UnsupportedVersion {
+                found: u64::from(version),
+                supported: CANONICAL_VERSION,
+            });
+        }
+        let today = i32_be(take(4)?);
+        let relation = Relation::from_code(take(1)?[0]).ok_or(QrError::Malformed)?;
+        let delta = i32_be(take(4)?);
+        let n = take(1)?[0] as usize;
+        let contract = take(n)?.iter().rev().copied().collect();
+        let m = take(2)?;
+        let m = u16::from_be_bytes([m[0], m[1]]) as usize;
+        let proof = take(m)?.to_vec();
+        let qr = ProofQrCode {
+            public: PublicQr {
+                today,
+                relation,
+                delta,
+                contract,
+                label: None,
+            },
+            proof,
+        };
+        Ok((qr, rest))
+    }
+
+    /// First bytes of the fingerprint, written into the payload so that a
+    /// payload damaged in printing or scanning is told apart from an
+    /// invalid proof. Anyone can recompute it, so it does not detect
+    /// deliberate edits; the proof itself does.
+    pub fn checksum(&self) -> [u8; CHECKSUM_BYTES] {
+        let mut checksum = [0; CHECKSUM_BYTES];
+        checksum.copy_from_slice(&self.fingerprint()[..CHECKSUM_BYTES]);
+        checksum
+    }
+
+    fn check(self, checksum: &[u8]) -> Result<Self, QrError> {
+        if checksum == self.checksum() {
+            Ok(self)
+        } else {
+            Err(QrError::Corrupt)
+        }
+    }
+
+    /// Base45 of `canonical_bytes` and `checksum` after `BASE45_PREFIX`,
+    /// followed by the length and the UTF-8 bytes of the label if there is
+    /// one. Uses only the QR alphanumeric characters and therefore needs
+    /// a smaller QR version than the JSON payload.
+    ///
+    /// Panics if the label fails `check_label`. Proofs from
+    /// `zk::generate_proof` and from parsing always pass it.
+    pub fn to_base45(&self) -> String {
+        check_label(&self.public.label).expect("invalid label");
+        let mut bytes = self.canonical_bytes();
+        bytes.extend_from_slice(&self.checksum());
+        if let Some(label) = &self.public.label {
+            bytes.push(label.len() as u8);
+            bytes.extend_from_slice(label.as_bytes());
+        }
+        String::from(BASE45_PREFIX) + &base45::encode(&bytes)
+    }
+
+    /// Reads `to_base45`, also if written without the checksum.
+    pub fn from_base45(s: &str) -> Result<Self, QrError> {
+        let body = s.strip_prefix(BASE45_PREFIX).ok_or(QrError::Malformed)?;
+        let bytes = base45::decode(body).ok_or(QrError::Corrupt)?;
+        match ProofQrCode::read_canonical(&bytes) {
+            Ok((qr, [])) => Ok(qr),
+            Ok((mut qr, rest)) if rest.len() >= CHECKSUM_BYTES => {
+                let (checksum, label) = rest.split_at(CHECKSUM_BYTES);
+                qr.public.label = read_label(label)?;
+                qr.check(checksum)
+            }
+            Ok(_) | Err(QrError::Malformed) => Err(QrError::Corrupt),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reassembles a payload split into the parts of a multi-part QR
+    /// code, see `split_payload`. The parts may come in any order, as
+    /// they were scanned; each must be present exactly once.
+    pub fn from_parts<S: AsRef<str>>(parts: &[S]) -> Result<Self, QrError> {
+        let mut chunks: Vec<Option<&str>> = Vec::new();
+        for part in parts {
+            let (index, total, chunk) = parse_part(part.as_ref()).ok_or(QrError::Malformed)?;
+            if chunks.is_empty() {
+                chunks.resize(total, None);
+            }
+            if total != chunks.len() || chunks[index - 1].replace(chunk).is_some() {
+                return Err(QrError::Malformed);
+            }
+        }
+        let payload: Option<String> = chunks.into_iter().collect();
+        ProofQrCode::from_str(&payload.ok_or(QrError::Malformed)?)
+    }
+
+    /// The payload in the given encoding.
+    pub fn encode(&self, encoding: QrEncoding) -> String {
+        match encoding {
+            QrEncoding::Json => self.to_string(),
+            QrEncoding::Base45 => self.to_base45(),
+            QrEncoding::Auto => self.encode(self.auto_encoding()),
+        }
+    }
+
+    /// The encoding `QrEncoding::Auto` picks for this proof. A proof
+    /// consists of random curve points, so no compression would make it
+    /// smaller than its binary form; Base45 is the densest encoding.
+    pub fn auto_encoding(&self) -> QrEncoding {
+        let max_width = Version::Normal(AUTO_MAX_QR_VERSION).width();
+        match QrCode::new(self.to_string()) {
+            Ok(code) if code.version().width() <= max_width => QrEncoding::Json,
+            _ => QrEncoding::Base45,
+        }
+    }
+
+    /// Indented JSON for logs and `harla inspect`, with the proof in hex
+    /// and the date of `today` next to its julian day. Not a QR payload;
+    /// `from_pretty_json` reads it back.
+    pub fn to_pretty_json(&self) -> String {
+        let js = PrettyJson {
+            v: QR_VERSION,
+            today: self.public.today,
+            today_date: jd_to_naive_date(self.public.today).map(|d| d.to_string()),
+            relation: self.public.relation,
+            delta: self.public.delta,
+            contract: String::from("0x") + &hex::encode(&self.public.contract),
+            proof: hex::encode(&self.proof),
+            label: self.public.label.clone(),
+        };
+        serde_json::to_string_pretty(&js).unwrap()
+    }
 
+    /// Reads the output of `to_pretty_json`, maybe edited by hand. The
+    /// date of `today` and unknown fields are ignored and the `0x` of
+    /// hex strings is optional.
+    pub fn from_pretty_json(s: &str) -> Result<Self, QrError> {
+        let js: PrettyJson = serde_json::from_str(s).map_err(|_| QrError::Malformed)?;
+        if js.v > u64::from(QR_VERSION) {
+            return Err(QrError::UnsupportedVersion {
+                found: js.v,
+                supported: QR_VERSION,
+            });
+        }
+        let unhex =
+            |h: &str| hex::decode(h.trim_start_matches("0x")).map_err(|_| QrError::Malformed);
+        ProofQrCode {
+            public: PublicQr {
+                today: js.today,
+                relation: js.relation,
+                delta: js.delta,
+                contract: unhex(&js.contract)?,
+                label: js.label,
+            },
+            proof: unhex(&js.proof)?,
+        }
+        .check_lengths()
+    }
+
+    /// The contract big-endian and left-padded to 32 bytes, so that
+    /// encodings of the same number compare equal.
+    fn canonical_contract(&self) -> Vec<u8> {
+        let mut contract = canonical_field_bytes(&self.public.contract);
+        contract.reverse();
+        contract
+    }
+
+    /// SHA-256 of `canonical_bytes`. Identifies this very proof; two
+    /// proofs of the same statement have different fingerprints.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&Sha256::digest(&self.canonical_bytes()));
+        hash
+    }
+
+    /// Tells whether both proofs claim the same: equal today, relation,
+    /// delta and contract. The proof bytes are random and the label is
+    /// not part of the claim, so both are ignored. Compare fingerprints
+    /// to tell whether it is the very same proof.
+    ///
+    /// The claim does not name the prover: the prover key is on the
+    /// chain, not in the payload, and two people of the same age can
+    /// state the same on the same day. Do not deduplicate on this alone;
+    /// key on the verified chain record too.
+    pub fn same_statement(&self, other: &ProofQrCode) -> bool {
+        self.public.today == other.public.today
+            && self.public.relation == other.public.relation
+            && self.public.delta == other.public.delta
+            && self.canonical_contract() == other.canonical_contract()
+    }
+}
+
+/// The label after the checksum of a Base45 payload, `None` if there is
+/// none. Bytes which do not make a label are damage, a label which fails
+/// `check_label` is `Malformed` as in the JSON payload.
+fn read_label(bytes: &[u8]) -> Result<Option<String>, QrError> {
+    let label = match bytes {
+        [] => return Ok(None),
+        [n, label @ ..] if label.len() == *n as usize => {
+            String::from_utf8(label.to_vec()).map_err(|_| QrError::Corrupt)?
+        }
+        _ => return Err(QrError::Corrupt),
+    };
+    let label = Some(label);
+    check_label(&label).map_err(|_| QrError::Malformed)?;
+    Ok(label)
+}
+
+/// Splits `n` bytes off the front of `rest`.
+fn split_off<'a>(rest: &mut &'a [u8], n: usize) -> Result<&'a [u8], QrError> {
+    if rest.len() < n {
+        return Err(QrError::Malformed);
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(head)
+}
+
+/// Starts each part of a multi-part QR code. The whole part reads
+/// `HZM:{index}/{total}:{chunk}` with `index` counting from 1; joining
+/// the chunks in the order of their indexes gives the payload.
+pub const PART_PREFIX: &str = "HZM:";
+
+/// Most parts of a multi-part QR code, as in the structured append mode
+/// of the QR standard.
+pub const MAX_PARTS: usize = 16;
+
+/// Splits the payload into `total` parts of nearly equal length.
+pub fn split_payload(payload: &str, total: usize) -> Vec<String> {
+    let chars: Vec<char> = payload.chars().collect();
+    let n = chars.len();
+    (0..total)
+        .map(|i| {
+            let chunk: String = chars[i * n / total..(i + 1) * n / total].iter().collect();
+            format!("{}{}/{}:{}", PART_PREFIX, i + 1, total, chunk)
+        })
+        .collect()
+}
+
+fn parse_part(part: &str) -> Option<(usize, usize, &str)> {
+    let rest = part.strip_prefix(PART_PREFIX)?;
+    let (header, chunk) = rest.split_at(rest.find(':')?);
+    let (index, total) = header.split_at(header.find('/')?);
+    let index: usize = index.parse().ok()?;
+    let total: usize = total[1..].parse().ok()?;
+    if index == 0 || index > total || total > MAX_PARTS {
+        return None;
+    }
+    Some((index, total, &chunk[1..]))
+}
+
+/// Starts a Base45 payload. JSON payloads start with `{`.
+pub const BASE45_PREFIX: &str = "HZ1:";
+
+/// Text encoding of the QR code payload.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum QrEncoding {
+    /// Readable JSON document.
+    #[default]
+    Json,
+    /// Binary form in Base45, see `ProofQrCode::to_base45`.
+    Base45,
+    /// JSON while its QR code is at most `AUTO_MAX_QR_VERSION`, Base45
+    /// for larger proofs.
+    Auto,
+}
+
+/// Largest QR code version `QrEncoding::Auto` writes JSON in. Phone
+/// cameras read larger codes only from close up.
+pub const AUTO_MAX_QR_VERSION: i16 = 15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QrError {
+    /// The payload is not a proof.
+    Malformed,
+
+    /// The payload does not match its checksum, it was damaged, e.g.
+    /// while scanning.
+    Corrupt,
+
+    /// The payload was written in a newer format.
+    UnsupportedVersion { found: u64, supported: u8 },
+
+    /// The JSON payload lacks a field of `QrJson`, e.g. it was cut off
+    /// or written by hand.
+    MissingField(&'static str),
+}
+
+/// Length of `ProofQrCode::checksum`.
+pub const CHECKSUM_BYTES: usize = 4;
+
+/// Format version written into the QR code. Payloads without a version
+/// predate it and count as version 0, which has the same fields.
+pub const QR_VERSION: u8 = 1;
+
+/// The JSON QR code payload.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct QrJson {
+    /// Format version, `QR_VERSION`; 0 when absent.
+    #[serde(default)]
+    pub v: u8,
+    /// Julian day of the proof.
     pub today: i32,
+    /// Code of the relation: 0 older, 1 younger, 2 younger or equal,
+    /// 3 older or equal.
     pub relation: u8,
+    /// Days from the birthday compared with `today`.
     pub delta: i32,
+    /// `0x` and the hex of the contract field element.
+    #[cfg_attr(feature = "schema", schemars(regex(pattern = r"^0x[0-9a-f]*$")))]
     pub contract: String,
+    /// Base58 of the proof.
     pub proof: String,
+    /// Hex of `ProofQrCode::checksum`; absent in older payloads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub c: Option<String>,
+    /// `PublicQr::label`, absent when there is none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(length(max = 64)))]
+    pub label: Option<String>,
+}
+
+/// Fields of `QrJson` every payload has, in the order a reader names the
+/// first one missing.
+const QR_JSON_FIELDS: [&str; 5] = ["today", "relation", "delta", "contract", "proof"];
+
+/// Layout of `ProofQrCode::to_pretty_json`.
+#[derive(Debug, Serialize, Deserialize)]
+struct PrettyJson {
+    #[serde(default)]
+    v: u64,
+    today: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    today_date: Option<String>,
+    relation: Relation,
+    delta: i32,
+    contract: String,
+    proof: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
 }
 
 impl ToString for ProofQrCode {
     fn to_string(&self) -> String {
         let js = QrJson {
+            v: QR_VERSION,
             today: self.public.today,
-            relation: if self.public.relation == Relation::Younger {
-                1
-            } else {
-                0
-            },
+            relation: self.public.relation.to_code(),
             delta: self.public.delta,
             contract: String::from("0x") + &hex::encode(self.public.contract.clone()),
             proof: bs58::encode(&self.proof).into_string(),
+            c: Some(hex::encode(self.checksum())),
+            label: self.public.label.clone(),
         };
         serde_json::to_string(&js).unwrap()
     }
@@ -188,23 +1024,531 @@ impl FromStr for ProofQrCode {
     type Err = QrError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let p: QrJson = serde_json::from_str(s).map_err(|_| QrError {})?;
+        if s.starts_with(BASE45_PREFIX) {
+            return ProofQrCode::from_base45(s);
+        }
+        // The version is read first; a newer payload may not even have
+        // the fields known here. Any number is a version, so one past
+        // `u8` is newer rather than malformed.
+        #[derive(Deserialize)]
+        struct Version {
+            #[serde(default)]
+            v: u64,
+        }
+        let value: serde_json::Value = serde_json::from_str(s).map_err(|_| QrError::Malformed)?;
+        if !value.is_object() {
+            return Err(QrError::Malformed);
+        }
+        let version = Version::deserialize(&value).map_err(|_| QrError::Malformed)?;
+        if version.v > u64::from(QR_VERSION) {
+            return Err(QrError::UnsupportedVersion {
+                found: version.v,
+                supported: QR_VERSION,
+            });
+        }
+        if let Some(name) = QR_JSON_FIELDS.iter().find(|f| value.get(**f).is_none()) {
+            return Err(QrError::MissingField(name));
+        }
+
+        let p = QrJson::deserialize(&value).map_err(|_| QrError::Malformed)?;
+        check_label(&p.label).map_err(|_| QrError::Malformed)?;
         let mut contract = p.contract;
         if contract.starts_with("0x") {
             contract = String::from(&contract[2..]);
         }
-        Ok(ProofQrCode {
+        let qr = ProofQrCode {
             public: PublicQr {
                 today: p.today,
-                relation: if p.relation == 0 {
-                    Relation::Older
-                } else {
-                    Relation::Younger
-                },
+                relation: Relation::from_code(p.relation).ok_or(QrError::Malformed)?,
                 delta: p.delta,
-                contract: hex::decode(contract).map_err(|_| QrError {})?,
+                contract: hex::decode(contract).map_err(|_| QrError::Malformed)?,
+                label: p.label,
             },
-            proof: bs58::decode(&p.proof).into_vec().map_err(|_| QrError {})?,
-        })
+            proof: bs58::decode(&p.proof)
+                .into_vec()
+                .map_err(|_| QrError::Malformed)?,
+        }
+        .check_lengths()?;
+        match p.c {
+            Some(c) => qr.check(&hex::decode(c).map_err(|_| QrError::Corrupt)?),
+            None => Ok(qr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ProofQrCode {
+        ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 2923,
+                contract: vec![0x5d, 0xe5, 0x35, 0x37, 0, 0],
+                label: None,
+            },
+            proof: (0..128).collect(),
+        }
+    }
+
+    #[test]
+    fn jd_round_trip() {
+        let date = NaiveDate::from_ymd(2021, 1, 16);
+        assert_eq!(Some(date), jd_to_naive_date(naive_date_to_jd(date)));
+        assert_eq!(None, jd_to_naive_date(i32::MIN));
+    }
+
+    #[test]
+    fn jd_of_historical_dates() {
+        assert_eq!(2440588, naive_date_to_jd(NaiveDate::from_ymd(1970, 1, 1)));
+        assert_eq!(2299161, naive_date_to_jd(NaiveDate::from_ymd(1582, 10, 15)));
+        assert_eq!(1721426, naive_date_to_jd(NaiveDate::from_ymd(1, 1, 1)));
+        // 1 BCE, a leap year.
+        assert_eq!(1721060, naive_date_to_jd(NaiveDate::from_ymd(0, 1, 1)));
+        assert_eq!(
+            366,
+            naive_date_to_jd(NaiveDate::from_ymd(1, 1, 1)) - 1721060
+        );
+        assert_eq!(0, naive_date_to_jd(NaiveDate::from_ymd(-4713, 11, 24)));
+    }
+
+    #[test]
+    fn jd_of_extreme_dates() {
+        assert_eq!(5373484, naive_date_to_jd(NaiveDate::from_ymd(9999, 12, 31)));
+        for date in &[chrono::naive::MIN_DATE, chrono::naive::MAX_DATE] {
+            let jd = naive_date_to_jd(*date);
+            assert_eq!(Some(*date), jd_to_naive_date(jd));
+        }
+        assert_eq!(
+            None,
+            jd_to_naive_date(naive_date_to_jd(chrono::naive::MAX_DATE) + 1)
+        );
+        assert_eq!(None, jd_to_naive_date(i32::MAX));
+    }
+
+    #[test]
+    fn read_untagged_payload() {
+        let old = r#"{"today":2459231,"relation":1,"delta":2923,"contract":"0x12","proof":"2"}"#;
+        let qr = ProofQrCode::from_str(old).unwrap();
+        assert_eq!(Relation::Younger, qr.public.relation);
+        assert_eq!(vec![0x12], qr.public.contract);
+        assert_eq!(vec![1], qr.proof);
+        assert!(sample().to_string().starts_with(r#"{"v":1,"#));
+    }
+
+    #[test]
+    fn reject_newer_payload() {
+        let new = r#"{"v":2,"statement":"something else"}"#;
+        assert_eq!(
+            Err(QrError::UnsupportedVersion {
+                found: 2,
+                supported: 1
+            }),
+            ProofQrCode::from_str(new).map(|_| ())
+        );
+        let far = r#"{"v":300,"statement":"something else"}"#;
+        assert_eq!(
+            Err(QrError::UnsupportedVersion {
+                found: 300,
+                supported: 1
+            }),
+            ProofQrCode::from_str(far).map(|_| ())
+        );
+        let pretty = sample()
+            .to_pretty_json()
+            .replacen(r#""v": 1"#, r#""v": 300"#, 1);
+        assert!(matches!(
+            ProofQrCode::from_pretty_json(&pretty),
+            Err(QrError::UnsupportedVersion { found: 300, .. })
+        ));
+    }
+
+    #[test]
+    fn for_age_matches_manual_delta() {
+        let today = NaiveDate::from_ymd(2021, 1, 16);
+        let birthdays = [
+            naive_date_to_jd(NaiveDate::from_ymd(2000, 6, 15)),
+            naive_date_to_jd(NaiveDate::from_ymd(2004, 2, 29)),
+        ];
+        for &birthday in &birthdays {
+            for &relation in &[Relation::Older, Relation::Younger] {
+                for &age in &[1, 15, 18, 21, 65] {
+                    let qr = PublicQr::for_age(birthday, today, relation, age, vec![4]);
+                    assert_eq!(age_to_delta(birthday, age, relation), qr.delta);
+                    assert_eq!(2459231, qr.today);
+                    assert_eq!(relation, qr.relation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canonical_layout() {
+        let bytes = sample().canonical_bytes();
+        assert_eq!(1 + 4 + 1 + 4 + 1 + 32 + 2 + 128, bytes.len());
+        assert_eq!(&[1, 0, 0x25, 0x86, 0x5f, 0], &bytes[..6]);
+        assert_eq!(32, bytes[10]);
+        assert_eq!(&[0x37, 0x35, 0xe5, 0x5d], &bytes[39..43]);
+        assert_eq!(&[0, 128], &bytes[43..45]);
+    }
+
+    #[test]
+    fn canonical_bytes_survive_reparsing() {
+        let qr = sample();
+        let parsed = ProofQrCode::from_str(&qr.to_string()).unwrap();
+        assert_eq!(qr.canonical_bytes(), parsed.canonical_bytes());
+        assert_eq!(qr.fingerprint(), parsed.fingerprint());
+
+        let mut padded = qr.clone();
+        padded.public.contract.resize(32, 0);
+        assert_eq!(qr.canonical_bytes(), padded.canonical_bytes());
+    }
+
+    #[test]
+    fn reject_fields_too_long_to_hash() {
+        let json: serde_json::Value = serde_json::from_str(&sample().to_string()).unwrap();
+        let mut long_proof = json.clone();
+        // Base58 of 65536 zero bytes.
+        long_proof["proof"] = serde_json::json!("1".repeat(65536));
+        let mut long_contract = json;
+        long_contract["contract"] = serde_json::json!(hex::encode(vec![1; 256]));
+        for bad in &[long_proof, long_contract] {
+            assert_eq!(
+                Err(QrError::Malformed),
+                ProofQrCode::from_str(&bad.to_string()).map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn base45_round_trip() {
+        let qr = sample();
+        let payload = qr.to_base45();
+        assert!(payload.starts_with(BASE45_PREFIX));
+        assert!(payload
+            .bytes()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase() || b" $%*+-./:".contains(&c)));
+
+        let parsed = ProofQrCode::from_str(&payload).unwrap();
+        assert_eq!(qr.canonical_bytes(), parsed.canonical_bytes());
+        assert_eq!(32, parsed.public.contract.len());
+
+        assert_eq!(
+            Err(QrError::Corrupt),
+            ProofQrCode::from_str(&payload[..payload.len() - 3]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn delta_from_dates_around_birthday() {
+        let birthday = NaiveDate::from_ymd(2003, 1, 16);
+        let eighteenth = NaiveDate::from_ymd(2021, 1, 16);
+        let jd = naive_date_to_jd;
+        let holds = |relation: Relation, today: NaiveDate| {
+            let delta = age_to_delta_from_dates(birthday, today, relation, 18).unwrap();
+            assert_eq!(age_to_delta(jd(birthday), 18, relation), delta);
+            relation.holds(jd(birthday), delta, jd(today))
+        };
+        let day = chrono::Duration::days(1);
+
+        for today in &[eighteenth - day, eighteenth, eighteenth + day] {
+            assert!(!holds(Relation::Older, *today), "older on {}", today);
+            assert!(!holds(Relation::Younger, *today), "younger on {}", today);
+        }
+        assert!(holds(Relation::Older, eighteenth + day + day));
+        assert!(holds(Relation::Younger, eighteenth - day - day));
+
+        // On the birthday itself only the inclusive relations hold.
+        assert!(holds(Relation::OlderOrEqual, eighteenth));
+        assert!(!holds(Relation::OlderOrEqual, eighteenth - day));
+        assert!(holds(Relation::YoungerOrEqual, eighteenth));
+        assert!(!holds(Relation::YoungerOrEqual, eighteenth + day));
+
+        assert_eq!(
+            None,
+            age_to_delta_from_dates(eighteenth, birthday, Relation::Older, 18)
+        );
+    }
+
+    #[test]
+    fn delta_from_dates_of_leap_day() {
+        let birthday = NaiveDate::from_ymd(2004, 2, 29);
+        let today = NaiveDate::from_ymd(2023, 1, 1);
+        let delta = age_to_delta_from_dates(birthday, today, Relation::Older, 1).unwrap();
+        assert_eq!(365 + 1, delta);
+    }
+
+    #[test]
+    fn pretty_json_reads_back() {
+        let qr = sample();
+        let pretty = qr.to_pretty_json();
+        assert!(pretty.contains("\n  \"today_date\": \"2021-01-16\""));
+        assert!(pretty.contains(&hex::encode(&qr.proof)));
+
+        let parsed = ProofQrCode::from_pretty_json(&pretty).unwrap();
+        assert_eq!(qr.public, parsed.public);
+        assert_eq!(qr.proof, parsed.proof);
+
+        let edited = r#"{"today": 2459231, "relation": "older", "delta": 2923,
+            "contract": "5de535370000", "proof": "0x0001", "note": "by hand"}"#;
+        let parsed = ProofQrCode::from_pretty_json(edited).unwrap();
+        assert_eq!(qr.public, parsed.public);
+        assert_eq!(vec![0, 1], parsed.proof);
+    }
+
+    #[test]
+    fn public_qr_on_date() {
+        let today = NaiveDate::from_ymd(2021, 1, 16);
+        let qr = PublicQr::on_date(today, Relation::Older, 2923, vec![4]);
+        assert_eq!(naive_date_to_jd(today), qr.today);
+        assert_eq!(JulianDay(2459231), JulianDay::from(today));
+        assert_eq!(Some(today), JulianDay(qr.today).to_naive_date());
+    }
+
+    #[test]
+    fn parts_reassemble_in_any_order() {
+        let payload = sample().to_string();
+        let mut parts = split_payload(&payload, 3);
+        assert_eq!(3, parts.len());
+        assert!(parts[1].starts_with("HZM:2/3:"));
+        parts.swap(0, 2);
+        let qr = ProofQrCode::from_parts(&parts).unwrap();
+        assert_eq!(sample().canonical_bytes(), qr.canonical_bytes());
+
+        let missing = &parts[..2];
+        assert_eq!(
+            Err(QrError::Malformed),
+            ProofQrCode::from_parts(missing).map(|_| ())
+        );
+        let twice = vec![parts[0].clone(), parts[0].clone(), parts[1].clone()];
+        assert_eq!(
+            Err(QrError::Malformed),
+            ProofQrCode::from_parts(&twice).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_named() {
+        let payload: serde_json::Value = serde_json::from_str(&sample().to_string()).unwrap();
+        for name in &QR_JSON_FIELDS {
+            let mut cut = payload.clone();
+            cut.as_object_mut().unwrap().remove(*name);
+            match ProofQrCode::from_str(&cut.to_string()) {
+                Err(QrError::MissingField(missing)) => assert_eq!(name, &missing),
+                other => panic!("{}: expected MissingField, got {:?}", name, other),
+            }
+        }
+        let error = ZkError::from(QrError::MissingField("proof"));
+        assert_eq!("QR code payload has no proof field", error.to_string());
+        assert!(matches!(
+            ProofQrCode::from_str("[1, 2]"),
+            Err(QrError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn label_round_trip() {
+        let mut qr = sample();
+        let fingerprint = qr.fingerprint();
+        qr.public.label = Some(String::from("BarXYZ age-gate"));
+        assert_eq!(fingerprint, qr.fingerprint());
+
+        let parsed = vec![
+            ProofQrCode::from_str(&qr.to_string()).unwrap(),
+            ProofQrCode::from_str(&qr.to_base45()).unwrap(),
+            ProofQrCode::from_pretty_json(&qr.to_pretty_json()).unwrap(),
+        ];
+        for p in parsed {
+            assert_eq!(qr.public, p.public);
+        }
+        let unlabelled = ProofQrCode::from_str(&sample().to_base45()).unwrap();
+        assert_eq!(None, unlabelled.public.label);
+
+        for bad in &[
+            "x".repeat(MAX_LABEL_BYTES + 1),
+            String::from("gate\u{1b}[2J"),
+        ] {
+            qr.public.label = Some(bad.clone());
+            assert!(check_label(&qr.public.label).is_err());
+            assert!(matches!(
+                ProofQrCode::from_str(&qr.to_string()),
+                Err(QrError::Malformed)
+            ));
+            let mut bytes = qr.canonical_bytes();
+            bytes.extend_from_slice(&qr.checksum());
+            bytes.push(bad.len() as u8);
+            bytes.extend_from_slice(bad.as_bytes());
+            assert!(matches!(
+                ProofQrCode::from_base45(&(String::from(BASE45_PREFIX) + &base45::encode(&bytes))),
+                Err(QrError::Malformed)
+            ));
+        }
+
+        // A label cut off in scanning is damage.
+        let mut bytes = qr.canonical_bytes();
+        bytes.extend_from_slice(&qr.checksum());
+        bytes.extend_from_slice(&[5, b'g', b'a']);
+        assert!(matches!(
+            ProofQrCode::from_base45(&(String::from(BASE45_PREFIX) + &base45::encode(&bytes))),
+            Err(QrError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn checksum_detects_damage() {
+        let qr = sample();
+        let json = qr.to_string();
+        assert!(json.contains(&format!(r#""c":"{}""#, hex::encode(qr.checksum()))));
+        let bumped = json.replace("2459231", "2459232");
+        assert_eq!(
+            Err(QrError::Corrupt),
+            ProofQrCode::from_str(&bumped).map(|_| ())
+        );
+
+        let base45 = qr.to_base45();
+        let mut flipped = base45.clone().into_bytes();
+        let i = BASE45_PREFIX.len() + 3;
+        flipped[i] = if flipped[i] == b'A' { b'B' } else { b'A' };
+        assert_eq!(
+            Err(QrError::Corrupt),
+            ProofQrCode::from_str(&String::from_utf8(flipped).unwrap()).map(|_| ())
+        );
+
+        let unchecked = String::from(BASE45_PREFIX) + &base45::encode(&qr.canonical_bytes());
+        assert!(ProofQrCode::from_str(&unchecked).is_ok());
+    }
+
+    #[test]
+    fn delta_tells_inclusive_relations_apart() {
+        let first = naive_date_to_jd(NaiveDate::from_ymd(1900, 3, 1));
+        let last = naive_date_to_jd(NaiveDate::from_ymd(2100, 2, 28));
+        for birthday in (first..last).step_by(37) {
+            for age in 1..100 {
+                if checked_age_to_delta(birthday, age, Relation::Older).unwrap() + birthday > last {
+                    break;
+                }
+                for &(relation, partner) in &[
+                    (Relation::Older, Relation::OlderOrEqual),
+                    (Relation::OlderOrEqual, Relation::Older),
+                    (Relation::Younger, Relation::YoungerOrEqual),
+                    (Relation::YoungerOrEqual, Relation::Younger),
+                ] {
+                    let delta = age_to_delta(birthday, age, relation);
+                    assert!(
+                        relation.fits_delta(delta),
+                        "{} {} {:?}",
+                        birthday,
+                        age,
+                        relation
+                    );
+                    assert!(
+                        !partner.fits_delta(delta),
+                        "{} {} {:?}",
+                        birthday,
+                        age,
+                        relation
+                    );
+                }
+            }
+        }
+        assert!(!Relation::Older.fits_delta(i32::MAX));
+        assert!(!Relation::Younger.fits_delta(i32::MIN));
+    }
+
+    #[test]
+    fn negate_each_relation() {
+        let all = [
+            Relation::Older,
+            Relation::Younger,
+            Relation::OlderOrEqual,
+            Relation::YoungerOrEqual,
+        ];
+        for &relation in &all {
+            assert_eq!(relation, relation.negate().negate());
+            assert_ne!(relation.is_younger(), relation.negate().is_younger());
+            assert_eq!(Some(relation), Relation::from_code(relation.to_code()));
+        }
+        assert_eq!(Relation::YoungerOrEqual, Relation::Older.negate());
+        assert_eq!(Relation::OlderOrEqual, Relation::Younger.negate());
+
+        // Born 2003-01-16, 18 on 2021-01-16.
+        let birthday = naive_date_to_jd(NaiveDate::from_ymd(2003, 1, 16));
+        let eighteenth = naive_date_to_jd(NaiveDate::from_ymd(2021, 1, 16));
+        for &relation in &all {
+            let holds =
+                |r: Relation, today| r.holds(birthday, age_to_delta(birthday, 18, r), today);
+            for today in eighteenth - 3..=eighteenth + 3 {
+                assert!(!(holds(relation, today) && holds(relation.negate(), today)));
+            }
+        }
+    }
+
+    #[test]
+    fn chain_from_decimal_and_hex() {
+        let decimal = PublicChain::from_strs("70573743172686605492515124569", "3").unwrap();
+        let hex = PublicChain::from_strs("0xe4093b6b69bd2a9528c23559", "0x3").unwrap();
+        assert_eq!(decimal.photo_hash, hex.photo_hash);
+        assert_eq!(decimal.prover_key, hex.prover_key);
+        assert!(PublicChain::from_strs("abc", "3").is_err());
+    }
+
+    #[test]
+    fn documented_ranges() {
+        let ranges = supported_ranges();
+        assert_eq!(0, ranges.min_day);
+        assert_eq!(
+            Some(NaiveDate::from_ymd(-4713, 11, 24)),
+            jd_to_naive_date(0)
+        );
+        assert_eq!(-4712, ranges.min_birth_year);
+        assert_eq!(262142, ranges.max_birth_year);
+        assert_eq!(266855, ranges.max_age);
+        assert!(ranges.max_day < 1 << 30 && ranges.comparison_bits > 32);
+
+        assert!(ranges.check_request(0, ranges.max_day, 18).is_ok());
+        assert!(ranges.check_request(-1, 2459231, 18).is_err());
+        assert!(ranges
+            .check_request(2451711, ranges.max_day + 1, 18)
+            .is_err());
+        assert!(ranges
+            .check_request(2451711, 2459231, ranges.max_age)
+            .is_err());
+    }
+
+    #[test]
+    fn request_with_fixed_today() {
+        let date = NaiveDate::from_ymd(2021, 1, 16);
+        assert_eq!(2459231, QrRequest::new().with_today(date).qr.today);
+        let clock = crate::clock::FixedClock(NaiveDate::from_ymd(2020, 2, 22));
+        let rq = QrRequest::new().with_clock(&clock);
+        assert_eq!(2458902, rq.qr.today);
+        assert_eq!(Some(clock.0), jd_to_naive_date(rq.qr.today));
+    }
+
+    #[test]
+    fn auto_encoding_by_size() {
+        let mut qr = ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 2923,
+                contract: vec![4; 32],
+                label: None,
+            },
+            proof: vec![7; 128],
+        };
+        assert_eq!(QrEncoding::Json, qr.auto_encoding());
+        assert_eq!(qr.to_string(), qr.encode(QrEncoding::Auto));
+        let back = ProofQrCode::from_str(&qr.encode(QrEncoding::Auto)).unwrap();
+        assert_eq!(qr.canonical_bytes(), back.canonical_bytes());
+
+        qr.proof = (0..400).map(|i| (i * 13) as u8).collect();
+        assert_eq!(QrEncoding::Base45, qr.auto_encoding());
+        let payload = qr.encode(QrEncoding::Auto);
+        assert!(payload.starts_with(BASE45_PREFIX));
+        let back = ProofQrCode::from_str(&payload).unwrap();
+        assert_eq!(qr.canonical_bytes(), back.canonical_bytes());
     }
 }
@@ -0,0 +1,397 @@
+// Circuit, keys and ABI produced by the ZoKrates setup in 'zokrates/'.
+//
+// They are compiled in. Each of them may be replaced at runtime by the
+// file named in its environment variable, see `active_artifacts`, so a
+// deployment can switch circuits without a rebuild. With the
+// `no-embedded-vk` feature the verification key is left out and comes
+// only from `HARLA_VERIFICATION_KEY` or from the caller of
+// `zk::verify_proof_with_key`.
+
+use crate::api::{
+    age_to_delta, Private, ProofQrCode, PublicChain, PublicQr, QrRequest, Relation, VerifiedInputs,
+};
+use crate::error::ZkError;
+use crate::field::field_to_bytes;
+use crate::zk::{
+    build_arguments, execute_witness, g1_from_json, g2_from_json, generate_prover_key,
+    prove_witness, verify_proof,
+};
+use bellman_ce::groth16::Parameters;
+use bellman_ce::pairing::bn256::Bn256;
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs;
+use zokrates_core::ir::ProgEnum;
+use zokrates_field::Bn128Field;
+
+pub(crate) static PROGRAM: &[u8] = include_bytes!("../zokrates/out");
+pub(crate) static ABI: &[u8] = include_bytes!("../zokrates/abi.json");
+pub(crate) static PROVING_KEY: &[u8] = include_bytes!("../zokrates/proving.key");
+#[cfg(any(test, not(feature = "no-embedded-vk")))]
+pub(crate) static VERIFICATION_KEY: &[u8] = include_bytes!("../zokrates/verification.key");
+
+#[cfg(not(feature = "no-embedded-vk"))]
+fn embedded_verification_key() -> Option<&'static [u8]> {
+    Some(VERIFICATION_KEY)
+}
+
+#[cfg(feature = "no-embedded-vk")]
+fn embedded_verification_key() -> Option<&'static [u8]> {
+    None
+}
+
+/// Environment variables naming the files which replace the embedded
+/// artifacts.
+pub const PROGRAM_VAR: &str = "HARLA_PROGRAM";
+pub const ABI_VAR: &str = "HARLA_ABI";
+pub const PROVING_KEY_VAR: &str = "HARLA_PROVING_KEY";
+pub const VERIFICATION_KEY_VAR: &str = "HARLA_VERIFICATION_KEY";
+
+/// The artifacts used for proving and verification.
+pub(crate) struct Artifacts {
+    pub program: Cow<'static, [u8]>,
+    pub abi: Cow<'static, [u8]>,
+    pub proving_key: Cow<'static, [u8]>,
+    /// None if there is neither an embedded nor a supplied key.
+    pub verification_key: Option<Cow<'static, [u8]>>,
+}
+
+impl Artifacts {
+    /// Reads the files named by `var` and takes the embedded artifact
+    /// where it names none. The set is validated, replacing only some of
+    /// the artifacts must still give a consistent setup.
+    fn load<F: Fn(&str) -> Option<OsString>>(var: F) -> Result<Self, ZkError> {
+        let read = |name: &str, embedded: &'static [u8]| -> Result<Cow<'static, [u8]>, ZkError> {
+            match var(name) {
+                Some(path) => Ok(Cow::Owned(fs::read(path)?)),
+                None => Ok(Cow::Borrowed(embedded)),
+            }
+        };
+        let artifacts = Artifacts {
+            program: read(PROGRAM_VAR, PROGRAM)?,
+            abi: read(ABI_VAR, ABI)?,
+            proving_key: read(PROVING_KEY_VAR, PROVING_KEY)?,
+            verification_key: match var(VERIFICATION_KEY_VAR) {
+                Some(path) => Some(Cow::Owned(fs::read(path)?)),
+                None => embedded_verification_key().map(Cow::Borrowed),
+            },
+        };
+        check_artifacts(
+            &artifacts.program,
+            &artifacts.abi,
+            &artifacts.proving_key,
+            artifacts.verification_key.as_deref(),
+        )?;
+        Ok(artifacts)
+    }
+}
+
+static ACTIVE_ARTIFACTS: OnceCell<Artifacts> = OnceCell::new();
+
+/// The artifacts in use: the embedded ones or the files named by the
+/// `HARLA_*` variables. Loaded and validated once per process, so the
+/// variables must be set before the first proof or verification.
+pub(crate) fn active_artifacts() -> Result<&'static Artifacts, ZkError> {
+    ACTIVE_ARTIFACTS.get_or_try_init(|| Artifacts::load(|name| std::env::var_os(name)))
+}
+
+/// Identifies the artifacts in use, the embedded ones or those named by
+/// the `HARLA_*` variables. Two parties whose fingerprints differ cannot
+/// verify each other's proofs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArtifactInfo {
+    /// Version of this crate.
+    pub version: &'static str,
+
+    /// Hex encoded SHA-256 digests of the artifacts.
+    pub program: String,
+    pub abi: String,
+    pub proving_key: String,
+    /// None if there is neither an embedded nor a supplied key.
+    pub verification_key: Option<String>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+impl Artifacts {
+    fn fingerprints(&self) -> ArtifactInfo {
+        ArtifactInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            program: sha256_hex(&self.program),
+            abi: sha256_hex(&self.abi),
+            proving_key: sha256_hex(&self.proving_key),
+            verification_key: self.verification_key.as_deref().map(sha256_hex),
+        }
+    }
+}
+
+/// Fails like `validate_artifacts` if the artifacts in use cannot be
+/// loaded.
+pub fn artifact_fingerprints() -> Result<ArtifactInfo, ZkError> {
+    active_artifacts().map(Artifacts::fingerprints)
+}
+
+/// Checks that the artifacts in use come from the same ZoKrates setup.
+/// Cheap compared to a proof: the verification key must match the one
+/// inside the proving key and the number of public inputs must match the
+/// ABI.
+pub fn validate_artifacts() -> Result<(), ZkError> {
+    active_artifacts().map(|_| ())
+}
+
+/// Like `validate_artifacts` and then proves and verifies a fixed true
+/// statement, which takes as long as a real proof. Catches artifacts
+/// which are consistent but do not work, e.g. a program compiled from a
+/// different circuit than the keys. The error names the failing stage.
+pub fn validate_artifacts_thorough() -> Result<(), ZkError> {
+    validate_artifacts()?;
+    self_check(|qr, chain| verify_proof(qr, chain))
+}
+
+/// The statement of the self-check: someone born on 2000-06-15, with
+/// contract 4 and photo hash 3, claims to be older than `age` on
+/// 2021-01-16. True for ages up to 20. The chain record is derived from
+/// `nonce`, so requests with the same nonce share it.
+pub fn sample_request(nonce: Vec<u8>, age: i32) -> QrRequest {
+    let private = Private {
+        birthday: 2451711,
+        nonce,
+    };
+    let contract = field_to_bytes(&Bn128Field::from(4));
+    let photo_hash = field_to_bytes(&Bn128Field::from(3));
+    let prover_key = generate_prover_key(&private, &contract, &photo_hash);
+    QrRequest {
+        qr: PublicQr {
+            today: 2459231,
+            relation: Relation::Older,
+            delta: age_to_delta(private.birthday, age, Relation::Older),
+            contract,
+            label: None,
+        },
+        chain: PublicChain {
+            photo_hash,
+            prover_key,
+        },
+        private,
+    }
+}
+
+fn self_check<F>(verify: F) -> Result<(), ZkError>
+where
+    F: Fn(&ProofQrCode, &PublicChain) -> Result<VerifiedInputs, ZkError>,
+{
+    let failed = |stage: &str| {
+        let stage = stage.to_string();
+        move |e: ZkError| ZkError::ArtifactMismatch(format!("self-check {}: {}", stage, e))
+    };
+
+    let rq = sample_request(field_to_bytes(&Bn128Field::from(7999)), 18);
+    let arguments = build_arguments(&rq).map_err(failed("arguments"))?;
+    let witness = execute_witness(&arguments).map_err(failed("witness"))?;
+    let qr = prove_witness(witness, rq.qr.clone()).map_err(failed("proving"))?;
+    verify(&qr, &rq.chain).map_err(failed("verification"))?;
+    Ok(())
+}
+
+fn check_artifacts(
+    program: &[u8],
+    abi: &[u8],
+    proving_key: &[u8],
+    verification_key: Option<&[u8]>,
+) -> Result<(), ZkError> {
+    match ProgEnum::deserialize(&mut &program[..]).map_err(ZkError::Program)? {
+        ProgEnum::Bn128Program(_) => {}
+        _ => return Err(ZkError::Program(String::from("invalid program type"))),
+    }
+
+    let abi: Value = serde_json::from_slice(abi)?;
+    let public_inputs = abi["inputs"]
+        .as_array()
+        .map(|inputs| inputs.iter().filter(|i| i["public"] == true).count())
+        .unwrap_or(0)
+        + abi["outputs"].as_array().map(Vec::len).unwrap_or(0);
+
+    let params = Parameters::<Bn256>::read(&mut &proving_key[..], false)?;
+    let pk = &params.vk;
+    if pk.ic.len() != public_inputs + 1 {
+        return Err(ZkError::ArtifactMismatch(format!(
+            "proving key has {} input points, abi needs {}",
+            pk.ic.len(),
+            public_inputs + 1
+        )));
+    }
+    let verification_key = match verification_key {
+        Some(vk) => vk,
+        None => return Ok(()),
+    };
+
+    let vk: Value = serde_json::from_slice(verification_key).map_err(ZkError::KeyDeserialize)?;
+    let gamma_abc = vk["gamma_abc"].as_array().cloned().unwrap_or_default();
+    if gamma_abc.len() != public_inputs + 1 {
+        return Err(ZkError::ArtifactMismatch(format!(
+            "verification key has {} input points, abi needs {}",
+            gamma_abc.len(),
+            public_inputs + 1
+        )));
+    }
+
+    let same = g1_from_json(&vk["alpha"]) == Some(pk.alpha_g1)
+        && g2_from_json(&vk["beta"]) == Some(pk.beta_g2)
+        && g2_from_json(&vk["gamma"]) == Some(pk.gamma_g2)
+        && g2_from_json(&vk["delta"]) == Some(pk.delta_g2)
+        && pk.ic.len() == gamma_abc.len()
+        && pk
+            .ic
+            .iter()
+            .zip(gamma_abc.iter())
+            .all(|(p, v)| g1_from_json(v) == Some(*p));
+    if !same {
+        return Err(ZkError::ArtifactMismatch(String::from(
+            "verification key does not belong to the proving key",
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_artifacts_are_consistent() {
+        validate_artifacts().unwrap();
+    }
+
+    #[test]
+    fn detect_tampered_verification_key() {
+        let vk = String::from_utf8(VERIFICATION_KEY.to_vec()).unwrap();
+        // First digit of the alpha x coordinate.
+        let tampered = vk.replacen("0x1936c2", "0x1936c3", 1);
+        assert_ne!(vk, tampered);
+        match check_artifacts(PROGRAM, ABI, PROVING_KEY, Some(tampered.as_bytes())) {
+            Err(ZkError::ArtifactMismatch(_)) => {}
+            other => panic!("expected ArtifactMismatch, got {:?}", other),
+        }
+    }
+
+    /// The points are compared as field elements, not as text.
+    #[test]
+    fn compare_coordinates_as_numbers() {
+        let vk = String::from_utf8(VERIFICATION_KEY.to_vec()).unwrap();
+        let alpha_x = "0x1936c240636390dc823e3a728e94b208eb53c6756d81da57ec3425e05d43ac10";
+        let upper = vk.replacen(alpha_x, &alpha_x.to_uppercase().replacen("0X", "0x", 1), 1);
+        assert_ne!(vk, upper);
+        check_artifacts(PROGRAM, ABI, PROVING_KEY, Some(upper.as_bytes())).unwrap();
+
+        // A prefix of the coordinate is another number.
+        let prefix = vk.replacen(alpha_x, &alpha_x[..8], 1);
+        match check_artifacts(PROGRAM, ABI, PROVING_KEY, Some(prefix.as_bytes())) {
+            Err(ZkError::ArtifactMismatch(_)) => {}
+            other => panic!("expected ArtifactMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detect_foreign_abi() {
+        let abi = String::from_utf8(ABI.to_vec()).unwrap().replacen(
+            r#""public": false"#,
+            r#""public": true"#,
+            1,
+        );
+        match check_artifacts(PROGRAM, abi.as_bytes(), PROVING_KEY, Some(VERIFICATION_KEY)) {
+            Err(ZkError::ArtifactMismatch(_)) => {}
+            other => panic!("expected ArtifactMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detect_truncated_proving_key() {
+        let pk = &PROVING_KEY[..PROVING_KEY.len() / 2];
+        assert!(check_artifacts(PROGRAM, ABI, pk, Some(VERIFICATION_KEY)).is_err());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn thorough_check() {
+        validate_artifacts_thorough().unwrap();
+
+        let mut vk: Value = serde_json::from_slice(VERIFICATION_KEY).unwrap();
+        vk["gamma_abc"].as_array_mut().unwrap().swap(1, 2);
+        let vk = serde_json::to_vec(&vk).unwrap();
+        match self_check(|qr, chain| crate::zk::verify_proof_with_key(qr, chain, &vk)) {
+            Err(ZkError::ArtifactMismatch(why)) => {
+                assert!(why.starts_with("self-check verification"))
+            }
+            other => panic!("expected ArtifactMismatch, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn load_overrides() {
+        let dir = std::env::temp_dir().join(format!("harla-artifacts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // The same key reformatted, consistent but with other bytes.
+        let compact: Value = serde_json::from_slice(VERIFICATION_KEY).unwrap();
+        let vk = dir.join("verification.key");
+        fs::write(&vk, serde_json::to_vec(&compact).unwrap()).unwrap();
+        let broken = dir.join("broken.key");
+        fs::write(&broken, &VERIFICATION_KEY[..100]).unwrap();
+
+        let loaded = Artifacts::load(|name| match name {
+            VERIFICATION_KEY_VAR => Some(vk.clone().into()),
+            _ => None,
+        })
+        .unwrap();
+        assert!(matches!(loaded.verification_key, Some(Cow::Owned(_))));
+        assert!(matches!(loaded.program, Cow::Borrowed(_)));
+        let info = loaded.fingerprints();
+        assert_eq!(artifact_fingerprints().unwrap().program, info.program);
+        assert_ne!(
+            artifact_fingerprints().unwrap().verification_key,
+            info.verification_key
+        );
+
+        assert!(Artifacts::load(|name| match name {
+            VERIFICATION_KEY_VAR => Some(broken.clone().into()),
+            _ => None,
+        })
+        .is_err());
+        match Artifacts::load(|name| match name {
+            ABI_VAR => Some(dir.join("missing.json").into()),
+            _ => None,
+        }) {
+            Err(ZkError::Io(_)) => {}
+            other => panic!("expected Io, got {:?}", other.map(|_| ())),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn fingerprints_are_stable() {
+        // Update together with the files in 'zokrates/'.
+        let info = artifact_fingerprints().unwrap();
+        assert_eq!(
+            info.program,
+            "f3d8ab3eea427a1a04834228d1a32da692e7838183dcf6b8c3794fdbea158b63"
+        );
+        assert_eq!(
+            info.abi,
+            "7d640c35b46c2dc99c4304b13b21286d3fdb4ed46944267b3504463f1a8f6de1"
+        );
+        assert_eq!(
+            info.proving_key,
+            "18ccd112ad388a7cfe2aa096c516c9b2996bbc6c765478f916edf6d8f1674471"
+        );
+        assert_eq!(
+            info.verification_key.as_deref(),
+            Some("3ac19289d31e0243b055560976345302eae89dd507d6d3f0d81c75e37a11cc85")
+        );
+        assert_eq!(info, artifact_fingerprints().unwrap());
+    }
+}
@@ -0,0 +1,75 @@
+// Base45 encoding (RFC 9285). Its alphabet is the alphanumeric mode of
+// QR codes, which stores 5.5 bits per character instead of 8.
+
+const ALPHABET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Encodes each pair of bytes as three characters and a trailing byte
+/// as two.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() / 2 * 3 + 2);
+    for chunk in bytes.chunks(2) {
+        let (mut n, digits) = match chunk {
+            [a, b] => (*a as usize * 256 + *b as usize, 3),
+            [a] => (*a as usize, 2),
+            _ => unreachable!(),
+        };
+        for _ in 0..digits {
+            out.push(ALPHABET[n % 45] as char);
+            n /= 45;
+        }
+    }
+    out
+}
+
+/// Decodes a Base45 string, or returns `None` if it has characters
+/// outside the alphabet, a dangling character or a value above 16 bits.
+pub fn decode(s: &str) -> Option<Vec<u8>> {
+    let digits = s
+        .bytes()
+        .map(|c| ALPHABET.iter().position(|a| *a == c))
+        .collect::<Option<Vec<usize>>>()?;
+    let mut out = Vec::with_capacity(digits.len() / 3 * 2 + 1);
+    for chunk in digits.chunks(3) {
+        match chunk {
+            [c, d, e] => {
+                let n = c + d * 45 + e * 45 * 45;
+                if n > 0xffff {
+                    return None;
+                }
+                out.push((n >> 8) as u8);
+                out.push(n as u8);
+            }
+            [c, d] => {
+                let n = c + d * 45;
+                if n > 0xff {
+                    return None;
+                }
+                out.push(n as u8);
+            }
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_examples() {
+        assert_eq!("BB8", encode(b"AB"));
+        assert_eq!("%69 VD92EX0", encode(b"Hello!!"));
+        assert_eq!("UJCLQE7W581", encode(b"base-45"));
+        assert_eq!(Some(b"ietf!".to_vec()), decode("QED8WEX0"));
+    }
+
+    #[test]
+    fn round_trip_and_rejects() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(Some(bytes.clone()), decode(&encode(&bytes)));
+        assert_eq!(None, decode("GGW"));
+        assert_eq!(None, decode("a0"));
+        assert_eq!(None, decode("BB8B"));
+    }
+}
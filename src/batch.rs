@@ -0,0 +1,215 @@
+// Verification of many proofs at once.
+
+use crate::api::{ProofQrCode, PublicChain, VerifiedInputs};
+use crate::error::ZkError;
+use crate::zk::verify_proof;
+use rayon::prelude::*;
+
+/// Outcome of one proof in a batch. `index` is the position of the
+/// proof in the input.
+#[derive(Debug)]
+pub struct BatchVerifyResult {
+    pub index: usize,
+    pub outcome: Result<VerifiedInputs, ZkError>,
+}
+
+/// Verifies the proofs in parallel. The results are sorted by `index`
+/// regardless of the order in which the verifications finished.
+pub fn verify_proofs(items: &[(ProofQrCode, PublicChain)]) -> Vec<BatchVerifyResult> {
+    verify_proofs_with_progress(items, || {})
+}
+
+/// Like `verify_proofs` but calls `done` after each verification, e.g.
+/// to advance a progress bar. `done` is called from the worker threads.
+pub fn verify_proofs_with_progress<F>(
+    items: &[(ProofQrCode, PublicChain)],
+    done: F,
+) -> Vec<BatchVerifyResult>
+where
+    F: Fn() + Sync,
+{
+    let results = items
+        .par_iter()
+        .enumerate()
+        .map(|(index, (qr, chain))| {
+            let outcome = verify_proof(qr, chain);
+            done();
+            BatchVerifyResult { index, outcome }
+        })
+        .collect();
+    sorted_by_index(results)
+}
+
+/// Verifies the proofs one by one as the iterator is consumed, so an
+/// archive of any size, e.g. read line by line from newline-delimited
+/// JSON, is checked in constant memory. Runs on the caller's thread.
+pub fn verify_stream<I>(items: I) -> impl Iterator<Item = Result<VerifiedInputs, ZkError>>
+where
+    I: IntoIterator<Item = (ProofQrCode, PublicChain)>,
+{
+    items
+        .into_iter()
+        .map(|(qr, chain)| verify_proof(&qr, &chain))
+}
+
+/// Counts of the outcomes of a batch.
+#[derive(Debug, Default, PartialEq)]
+pub struct BatchSummary {
+    /// The proof verified.
+    pub valid: usize,
+
+    /// The proof was well formed but did not verify.
+    pub invalid: usize,
+
+    /// The proof could not be checked at all, e.g. it was malformed.
+    pub errored: usize,
+}
+
+impl BatchSummary {
+    pub fn of(results: &[BatchVerifyResult]) -> Self {
+        let mut summary = BatchSummary::default();
+        for r in results {
+            match r.outcome {
+                Ok(_) => summary.valid += 1,
+                Err(ZkError::InvalidProof) => summary.invalid += 1,
+                Err(_) => summary.errored += 1,
+            }
+        }
+        summary
+    }
+}
+
+fn sorted_by_index(mut results: Vec<BatchVerifyResult>) -> Vec<BatchVerifyResult> {
+    results.sort_by_key(|r| r.index);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::api::{PublicQr, Relation};
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::testutil::bart_request;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::zk::generate_proof;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    fn malformed(proof_len: usize) -> (ProofQrCode, PublicChain) {
+        let qr = ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 2923,
+                contract: vec![0; 32],
+                label: None,
+            },
+            proof: vec![0; proof_len],
+        };
+        let chain = PublicChain {
+            photo_hash: vec![0; 32],
+            prover_key: vec![0; 32],
+        };
+        (qr, chain)
+    }
+
+    /// Valid, invalid and malformed proofs interleaved, so that a result
+    /// out of place has the wrong outcome.
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn results_follow_input_order() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let valid = generate_proof(rq).unwrap();
+        let mut invalid = valid.clone();
+        invalid.public.delta += 365;
+
+        let items: Vec<_> = (0..24)
+            .map(|i| match i % 3 {
+                0 => (valid.clone(), chain.clone()),
+                1 => (invalid.clone(), chain.clone()),
+                _ => malformed(i),
+            })
+            .collect();
+        let results = verify_proofs(&items);
+        assert_eq!(24, results.len());
+        for (i, r) in results.iter().enumerate() {
+            assert_eq!(i, r.index);
+            match (i % 3, &r.outcome) {
+                (0, Ok(inputs)) => assert_eq!(valid.public.delta, inputs.delta),
+                (1, Err(ZkError::InvalidProof)) | (2, Err(ZkError::MalformedProof)) => {}
+                (_, other) => panic!("unexpected outcome of proof {}: {:?}", i, other),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn summary_counts() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let valid = generate_proof(rq).unwrap();
+        let mut invalid = valid.clone();
+        invalid.public.delta += 365;
+
+        let items = vec![
+            (valid.clone(), chain.clone()),
+            (invalid, chain.clone()),
+            malformed(3),
+            (valid, chain),
+        ];
+        let done = AtomicUsize::new(0);
+        let results = verify_proofs_with_progress(&items, || {
+            done.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(4, done.load(Ordering::SeqCst));
+        assert_eq!(
+            BatchSummary {
+                valid: 2,
+                invalid: 1,
+                errored: 1
+            },
+            BatchSummary::of(&results)
+        );
+    }
+
+    #[test]
+    fn shuffled_completion_is_sorted() {
+        let shuffled = [3, 0, 4, 1, 2]
+            .iter()
+            .map(|&index| BatchVerifyResult {
+                index,
+                outcome: Err(ZkError::InvalidProof),
+            })
+            .collect();
+        let indices: Vec<usize> = sorted_by_index(shuffled).iter().map(|r| r.index).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4], indices);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn stream_is_lazy() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let valid = generate_proof(rq).unwrap();
+
+        let pulled = AtomicUsize::new(0);
+        let items = vec![(valid.clone(), chain.clone()), malformed(3), (valid, chain)];
+        let mut results = verify_stream(items.into_iter().inspect(|_| {
+            pulled.fetch_add(1, Ordering::SeqCst);
+        }));
+        assert_eq!(0, pulled.load(Ordering::SeqCst));
+
+        assert!(results.next().unwrap().is_ok());
+        assert_eq!(1, pulled.load(Ordering::SeqCst));
+        match results.next() {
+            Some(Err(ZkError::MalformedProof)) => {}
+            other => panic!("expected MalformedProof, got {:?}", other),
+        }
+        assert!(results.next().unwrap().is_ok());
+        assert!(results.next().is_none());
+        assert_eq!(3, pulled.load(Ordering::SeqCst));
+    }
+}
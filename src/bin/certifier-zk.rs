@@ -1,8 +1,13 @@
 /// Command line utility to be calles from 'LegalAge' certifier.
 /// Generates a random nonce and computes the proverKey.
-use harla_zk::api::Private;
-use harla_zk::zk::{generate_prover_key, generate_random_private_key};
-use std::env;
+use chrono::Datelike;
+use clap::{App, Arg};
+use harla_zk::api::{jd_to_naive_date, Nonce, Private};
+use harla_zk::clock::{Clock, SystemClock};
+use harla_zk::field::{field_from_dec_str, field_to_bytes, field_to_dec_string};
+use harla_zk::zk::{generate_nonce, generate_prover_key};
+use rand::{ChaChaRng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use zokrates_field::{Bn128Field, Field};
 
@@ -10,15 +15,99 @@ fn bn128(s: &str) -> Bn128Field {
     Bn128Field::try_from_dec_str(s).unwrap()
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 4 {
-        panic!("required 3 arguments");
+/// Earliest year of birth accepted by the certifier.
+const MIN_BIRTH_YEAR: i32 = 1900;
+
+fn validate_birthday(s: String) -> Result<(), String> {
+    let jd = i32::from_str(&s).map_err(|_| format!("'{}' is not a julian day", s))?;
+    let date = jd_to_naive_date(jd).ok_or_else(|| format!("'{}' is not a julian day", s))?;
+    if date.year() < MIN_BIRTH_YEAR || jd > SystemClock.today_jd() {
+        return Err(format!(
+            "'{}' is {}, expected a birthday between {} and today",
+            s, date, MIN_BIRTH_YEAR
+        ));
     }
-    let birthday = i32::from_str(&args[1]).unwrap();
-    let photo_hash = bn128(&args[2]);
-    let contract = bn128(&args[3]);
-    let nonce = generate_random_private_key();
+    Ok(())
+}
+
+fn validate_field(s: String) -> Result<(), String> {
+    field_from_dec_str(&s)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn validate_seed(s: String) -> Result<(), String> {
+    seeded_nonce(&s)
+        .map(|_| ())
+        .ok_or_else(|| format!("'{}' is not hex digits", s))
+}
+
+/// The certifier's output in the format of `prover-db.json`.
+#[derive(Serialize, Deserialize, Debug)]
+struct CertifierOutput {
+    pub nonce: String,
+    pub prover_key: String,
+}
+
+fn main() {
+    let matches = App::new("certifier-zk")
+        .version("0.1")
+        .author("Ladislav Sladecek <ladislav.sladecek@gmail.com>")
+        .about("Generates a random nonce and computes the prover key.")
+        .arg(
+            Arg::with_name("BIRTHDAY")
+                .help("Julian day of the birth.")
+                .required(true)
+                .validator(validate_birthday)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("PHOTO_HASH")
+                .help("Decimal field element.")
+                .required(true)
+                .validator(validate_field)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("CONTRACT")
+                .help("Decimal field element.")
+                .required(true)
+                .validator(validate_field)
+                .index(3),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints {\"nonce\", \"prover_key\"} as decimal strings.")
+                .conflicts_with("hex"),
+        )
+        .arg(
+            Arg::with_name("hex")
+                .long("hex")
+                .help("Prints the nonce and the prover key as big-endian hex."),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("HEX")
+                .help("Seeds the nonce generator, for tests only: the nonce becomes predictable.")
+                .validator(validate_seed)
+                .takes_value(true),
+        )
+        .get_matches();
+
+    // The values were validated by clap.
+    let birthday = i32::from_str(matches.value_of("BIRTHDAY").unwrap()).unwrap();
+    let photo_hash = bn128(matches.value_of("PHOTO_HASH").unwrap());
+    let contract = bn128(matches.value_of("CONTRACT").unwrap());
+    let seeded = matches.value_of("seed").and_then(seeded_nonce);
+    let nonce = match seeded {
+        Some(nonce) => {
+            eprintln!("warning: --seed makes the nonce predictable, do not use it in production");
+            nonce
+        }
+        None => Nonce::random().to_bytes(),
+    };
 
     let private = Private {
         birthday,
@@ -26,13 +115,113 @@ fn main() {
     };
     let prover_key = generate_prover_key(
         &private,
-        &contract.into_byte_vector(),
-        &photo_hash.into_byte_vector(),
+        &field_to_bytes(&contract),
+        &field_to_bytes(&photo_hash),
     );
 
-    println!(
-        "{:?} {:?}",
-        Bn128Field::from_byte_vector(nonce),
-        Bn128Field::from_byte_vector(prover_key)
-    );
+    if matches.is_present("json") {
+        println!("{}", to_json(&nonce, &prover_key));
+    } else if matches.is_present("hex") {
+        println!("{} {}", to_hex(&nonce), to_hex(&prover_key));
+    } else {
+        println!(
+            "{:?} {:?}",
+            Bn128Field::from_byte_vector(nonce),
+            Bn128Field::from_byte_vector(prover_key)
+        );
+    }
+}
+
+/// Draws the nonce from a generator seeded by the hex string.
+fn seeded_nonce(seed: &str) -> Option<Vec<u8>> {
+    let bytes = hex::decode(seed.trim_start_matches("0x")).ok()?;
+    let words: Vec<u32> = bytes
+        .chunks(4)
+        .map(|c| {
+            let mut w = [0; 4];
+            w[..c.len()].copy_from_slice(c);
+            u32::from_le_bytes(w)
+        })
+        .collect();
+    Some(generate_nonce(&mut ChaChaRng::from_seed(&words)))
+}
+
+fn to_json(nonce: &[u8], prover_key: &[u8]) -> String {
+    let out = CertifierOutput {
+        nonce: field_to_dec_string(nonce),
+        prover_key: field_to_dec_string(prover_key),
+    };
+    serde_json::to_string(&out).unwrap()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let be: Vec<u8> = bytes.iter().rev().copied().collect();
+    String::from("0x") + &hex::encode(be)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_parses_back() {
+        let nonce = field_to_bytes(&Bn128Field::from(7999));
+        let prover_key = field_to_bytes(&bn128(
+            "10046037004840239707202533642544953578314335199439499999912878067091298310375",
+        ));
+        let out: CertifierOutput = serde_json::from_str(&to_json(&nonce, &prover_key)).unwrap();
+        assert_eq!("7999", out.nonce);
+        assert_eq!(nonce, field_from_dec_str(&out.nonce).unwrap());
+        assert_eq!(prover_key, field_from_dec_str(&out.prover_key).unwrap());
+    }
+
+    #[test]
+    fn hex_is_big_endian() {
+        assert_eq!(
+            "0x0000000000000000000000000000000000000000000000000000000000001f3f",
+            to_hex(&field_to_bytes(&Bn128Field::from(7999)))
+        );
+    }
+
+    #[test]
+    fn seed_is_reproducible() {
+        let key = |nonce: Vec<u8>| {
+            let private = Private {
+                birthday: 2451711,
+                nonce,
+            };
+            generate_prover_key(
+                &private,
+                &field_to_bytes(&bn128("4")),
+                &field_to_bytes(&bn128("3")),
+            )
+        };
+        let a = seeded_nonce("0x00c0ffee").unwrap();
+        let b = seeded_nonce("00c0ffee").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(key(a.clone()), key(b));
+        assert_ne!(a, seeded_nonce("00c0ffef").unwrap());
+        assert_eq!(None, seeded_nonce("xyz"));
+        assert!(validate_seed(String::from("0x00c0ffee")).is_ok());
+        assert!(validate_seed(String::from("c0ffee!")).is_err());
+    }
+
+    #[test]
+    fn reject_bad_birthday() {
+        assert!(validate_birthday(String::from("2451711")).is_ok());
+        assert!(validate_birthday(String::from("2000-06-11")).is_err());
+        assert!(validate_birthday(String::from("12")).is_err());
+        let tomorrow = SystemClock.today_jd() + 1;
+        assert!(validate_birthday(tomorrow.to_string()).is_err());
+    }
+
+    #[test]
+    fn reject_bad_photo_hash_and_contract() {
+        assert!(validate_field(String::from("3")).is_ok());
+        assert!(validate_field(String::from("0x03")).is_err());
+        assert!(validate_field(String::from("abc")).is_err());
+        let modulus =
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+        assert!(validate_field(String::from(modulus)).is_err());
+    }
 }
@@ -0,0 +1,842 @@
+/// Command line utility collecting maintenance tools for 'LegalAge'.
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use harla_zk::api::{jd_to_naive_date, Nonce, Private, ProofQrCode, PublicChain, QrRequest};
+use harla_zk::artifacts::{artifact_fingerprints, sample_request};
+use harla_zk::batch::{
+    verify_proofs, verify_proofs_with_progress, BatchSummary, BatchVerifyResult,
+};
+use harla_zk::certify::{certify_batch, CertifyRecord};
+use harla_zk::error::ZkError;
+use harla_zk::field::{
+    field_from_be_bytes, field_from_dec_str, field_from_hex_str, field_from_le_bytes,
+    field_to_be_bytes, field_to_dec_string, field_to_le_bytes,
+};
+use harla_zk::request::VerifyRequest;
+use harla_zk::zk::{generate_proof, generate_prover_key, verify_proof, warm_up};
+use indicatif::ProgressBar;
+use rand::{ChaChaRng, OsRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::fs;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let app = App::new("harla")
+        .version("0.1")
+        .author("Ladislav Sladecek <ladislav.sladecek@gmail.com>")
+        .about("Maintenance tools for 'LegalAge'.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("info").about("Prints fingerprints of the artifacts in use."),
+        )
+        .subcommand(
+            SubCommand::with_name("selftest")
+                .about("Proves and verifies a canned statement with the embedded artifacts."),
+        )
+        .subcommand(
+            SubCommand::with_name("certify")
+                .about("Generates nonces and prover keys for many users at once.")
+                .arg(
+                    Arg::with_name("batch")
+                        .long("batch")
+                        .value_name("FILE")
+                        .help("Input .json array of {birthday, photo_hash, contract}.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Output .json array of {nonce, prover_key, birthday}.")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check-key")
+                .about("Recomputes the prover key from the secrets and compares it.")
+                .arg(
+                    Arg::with_name("birthday")
+                        .long("birthday")
+                        .value_name("JD")
+                        .help("Birthday as a julian day number.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("nonce")
+                        .long("nonce")
+                        .value_name("DEC")
+                        .help("The prover's nonce.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("photo-hash")
+                        .long("photo-hash")
+                        .value_name("DEC")
+                        .help("Hash of the prover's photo.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("contract")
+                        .long("contract")
+                        .value_name("DEC")
+                        .help("The contract.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("expected")
+                        .long("expected")
+                        .value_name("DEC")
+                        .help("Prover key recorded on the chain.")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("field")
+                .about("Converts a field element between its representations.")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("FORMAT")
+                        .help("Representation of VALUE.")
+                        .possible_values(&FIELD_FORMATS)
+                        .default_value("dec")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("FORMAT")
+                        .help("Representation to print.")
+                        .possible_values(&FIELD_FORMATS)
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("VALUE")
+                        .help("dec: decimal, hex: 0x and big-endian hex digits, le and be: 32 bytes in hex.")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Prints the public fields of a proof without verifying it.")
+                .arg(
+                    Arg::with_name("PROOF")
+                        .help("File with the QR code payload, e.g. proof.json.")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Prints the whole proof as indented JSON."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Verifies many proofs in parallel.")
+                .arg(
+                    Arg::with_name("batch")
+                        .long("batch")
+                        .value_name("FILE")
+                        .help("Input .json array of {qr, photo_hash, prover_key}.")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Output .json array with the result of each proof.")
+                        .default_value("results.json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .value_name("N")
+                        .help("Number of verifier threads, all cores by default.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("progress")
+                        .long("progress")
+                        .help("Shows a progress bar."),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench-verify")
+                .about("Measures the verification throughput of this machine.")
+                .arg(
+                    Arg::with_name("proofs")
+                        .long("proofs")
+                        .value_name("N")
+                        .help("Number of proofs to generate and verify.")
+                        .default_value("100")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .value_name("J")
+                        .help("Number of verifier threads, all cores by default.")
+                        .takes_value(true),
+                ),
+        );
+    #[cfg(feature = "schema")]
+    let app = app.subcommand(
+        SubCommand::with_name("schema")
+            .about("Prints the JSON Schema of prover-db.json or of the QR code payload.")
+            .arg(
+                Arg::with_name("NAME")
+                    .help("Document to describe.")
+                    .possible_values(&harla_zk::request::SCHEMA_NAMES)
+                    .required(true)
+                    .index(1),
+            ),
+    );
+    #[cfg(feature = "server")]
+    let app = app.subcommand(
+        SubCommand::with_name("serve")
+            .about("Runs the HTTP verification service.")
+            .arg(
+                Arg::with_name("addr")
+                    .long("addr")
+                    .value_name("HOST:PORT")
+                    .help("Address to listen on.")
+                    .default_value("127.0.0.1:8080")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("rate-limit")
+                    .long("rate-limit")
+                    .value_name("N")
+                    .help("Allows each client at most N requests per minute.")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("client-header")
+                    .long("client-header")
+                    .value_name("NAME")
+                    .help(
+                        "Identifies clients by the last value of this header instead of the \
+                         source IP on connections from a trusted proxy.",
+                    )
+                    .requires_all(&["rate-limit", "trusted-proxy"])
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("trusted-proxy")
+                    .long("trusted-proxy")
+                    .value_name("IP")
+                    .help("Address of a proxy which sets --client-header. May be repeated.")
+                    .requires("client-header")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .takes_value(true),
+            ),
+    );
+    let matches = app.get_matches();
+
+    let ok = match matches.subcommand() {
+        ("info", _) => info(),
+        ("selftest", _) => selftest(),
+        ("certify", Some(m)) => certify(m),
+        ("check-key", Some(m)) => check_key(m),
+        ("field", Some(m)) => field(m),
+        ("inspect", Some(m)) => inspect(m),
+        ("verify", Some(m)) => verify(m),
+        ("bench-verify", Some(m)) => bench_verify(m),
+        #[cfg(feature = "schema")]
+        ("schema", Some(m)) => schema(m),
+        #[cfg(feature = "server")]
+        ("serve", Some(m)) => serve(m),
+        _ => unreachable!(),
+    };
+    if !ok {
+        process::exit(1);
+    }
+}
+
+fn certify(matches: &ArgMatches) -> bool {
+    match certify_file(
+        matches.value_of("batch").unwrap(),
+        matches.value_of("out").unwrap(),
+    ) {
+        Ok(n) => {
+            println!("certified {} records", n);
+            true
+        }
+        Err(e) => {
+            eprintln!("certify failed: {}", e);
+            false
+        }
+    }
+}
+
+fn certify_file(input: &str, output: &str) -> Result<usize, ZkError> {
+    let records: Vec<CertifyRecord> = serde_json::from_str(&fs::read_to_string(input)?)?;
+    let mut rng = ChaChaRng::from_seed(&OsRng::new()?.gen::<[u32; 8]>());
+    let keys = certify_batch(&records, &mut rng)?;
+    fs::write(output, serde_json::to_string_pretty(&keys)?)?;
+    Ok(keys.len())
+}
+
+fn check_key(matches: &ArgMatches) -> bool {
+    let computed = matches
+        .value_of("birthday")
+        .unwrap()
+        .parse::<i32>()
+        .map_err(|_| ZkError::InvalidDecimal(matches.value_of("birthday").unwrap().to_string()))
+        .and_then(|birthday| {
+            compute_prover_key(
+                birthday,
+                matches.value_of("nonce").unwrap(),
+                matches.value_of("photo-hash").unwrap(),
+                matches.value_of("contract").unwrap(),
+            )
+        });
+    match computed {
+        Ok(key) if key_matches(&key, matches.value_of("expected").unwrap()) => {
+            println!("match {}", key);
+            true
+        }
+        Ok(key) => {
+            println!("mismatch, computed {}", key);
+            false
+        }
+        Err(e) => {
+            eprintln!("check-key failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Returns the prover key in decimal.
+fn compute_prover_key(
+    birthday: i32,
+    nonce: &str,
+    photo_hash: &str,
+    contract: &str,
+) -> Result<String, ZkError> {
+    let private = Private {
+        birthday,
+        nonce: field_from_dec_str(nonce)?,
+    };
+    let key = generate_prover_key(
+        &private,
+        &field_from_dec_str(contract)?,
+        &field_from_dec_str(photo_hash)?,
+    );
+    Ok(field_to_dec_string(&key))
+}
+
+fn key_matches(computed: &str, expected: &str) -> bool {
+    match field_from_dec_str(expected) {
+        Ok(expected) => field_to_dec_string(&expected) == computed,
+        Err(_) => false,
+    }
+}
+
+/// Representations of a field element `harla field` converts between.
+const FIELD_FORMATS: [&str; 4] = ["dec", "hex", "le", "be"];
+
+fn field(matches: &ArgMatches) -> bool {
+    match convert_field(
+        matches.value_of("VALUE").unwrap(),
+        matches.value_of("from").unwrap(),
+        matches.value_of("to").unwrap(),
+    ) {
+        Ok(value) => {
+            println!("{}", value);
+            true
+        }
+        Err(e) => {
+            eprintln!("field failed: {}", e);
+            false
+        }
+    }
+}
+
+fn convert_field(value: &str, from: &str, to: &str) -> Result<String, ZkError> {
+    let bytes = |s: &str| hex::decode(s).map_err(|_| ZkError::InvalidHex(s.to_string()));
+    let field = match from {
+        "dec" => field_from_le_bytes(&field_from_dec_str(value)?)?,
+        "hex" => field_from_le_bytes(&field_from_hex_str(value)?)?,
+        "le" => field_from_le_bytes(&bytes(value)?)?,
+        "be" => field_from_be_bytes(&bytes(value)?)?,
+        _ => unreachable!(),
+    };
+    Ok(match to {
+        "dec" => field_to_dec_string(&field_to_le_bytes(&field)),
+        "hex" => {
+            let be = hex::encode(field_to_be_bytes(&field));
+            match be.trim_start_matches('0') {
+                "" => String::from("0x0"),
+                digits => format!("0x{}", digits),
+            }
+        }
+        "le" => hex::encode(field_to_le_bytes(&field)),
+        "be" => hex::encode(field_to_be_bytes(&field)),
+        _ => unreachable!(),
+    })
+}
+
+fn inspect(matches: &ArgMatches) -> bool {
+    let qr = fs::read_to_string(matches.value_of("PROOF").unwrap())
+        .map_err(ZkError::from)
+        .and_then(|s| ProofQrCode::from_str(s.trim()).map_err(ZkError::from));
+    match qr {
+        Ok(qr) if matches.is_present("json") => {
+            println!("{}", qr.to_pretty_json());
+            true
+        }
+        Ok(qr) => {
+            for (name, value) in describe(&qr) {
+                println!("{:<12}{}", name, value);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("inspect failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Lists the fields `inspect` prints. Each field is picked explicitly
+/// so that nothing else which may be added to the QR code gets out.
+fn describe(qr: &ProofQrCode) -> Vec<(&'static str, String)> {
+    let today = match jd_to_naive_date(qr.public.today) {
+        Some(d) => format!("{} (jd {})", d.format("%Y-%m-%d"), qr.public.today),
+        None => format!("jd {}", qr.public.today),
+    };
+    let mut fields = vec![
+        ("today", today),
+        ("relation", format!("{:?}", qr.public.relation)),
+        ("delta", qr.public.delta.to_string()),
+        (
+            "contract",
+            String::from("0x") + &hex::encode(&qr.public.contract),
+        ),
+        ("proof", format!("{} bytes", qr.proof.len())),
+    ];
+    if let Some(label) = &qr.public.label {
+        fields.push(("label", format!("{} (unverified)", label)));
+    }
+    fields
+}
+
+#[derive(Serialize, Debug)]
+struct BatchOutcome {
+    pub index: usize,
+    pub result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Thread pool of the size given by `--jobs`, all cores without it.
+fn verifier_pool(matches: &ArgMatches) -> Option<rayon::ThreadPool> {
+    let jobs = match matches.value_of("jobs").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(n)) => n,
+        Some(Err(e)) => {
+            eprintln!("invalid --jobs: {}", e);
+            return None;
+        }
+    };
+    match rayon::ThreadPoolBuilder::new().num_threads(jobs).build() {
+        Ok(pool) => Some(pool),
+        Err(e) => {
+            eprintln!("cannot start verifier threads: {}", e);
+            None
+        }
+    }
+}
+
+fn verify(matches: &ArgMatches) -> bool {
+    let pool = match verifier_pool(matches) {
+        Some(pool) => pool,
+        None => return false,
+    };
+    let summary = pool.install(|| {
+        verify_file(
+            matches.value_of("batch").unwrap(),
+            matches.value_of("out").unwrap(),
+            matches.is_present("progress"),
+        )
+    });
+    match summary {
+        Ok(summary) => {
+            println!("valid    {}", summary.valid);
+            println!("invalid  {}", summary.invalid);
+            println!("errored  {}", summary.errored);
+            true
+        }
+        Err(e) => {
+            eprintln!("verify failed: {}", e);
+            false
+        }
+    }
+}
+
+fn verify_file(input: &str, output: &str, progress: bool) -> Result<BatchSummary, ZkError> {
+    let entries: Vec<VerifyRequest> = serde_json::from_str(&fs::read_to_string(input)?)?;
+
+    // Entries which cannot be parsed are reported without verification.
+    let mut outcomes: Vec<BatchVerifyResult> = Vec::new();
+    let mut items = Vec::new();
+    let mut positions = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        match entry.parse() {
+            Ok(item) => {
+                items.push(item);
+                positions.push(index);
+            }
+            Err(e) => outcomes.push(BatchVerifyResult {
+                index,
+                outcome: Err(e),
+            }),
+        }
+    }
+
+    let bar = if progress {
+        ProgressBar::new(items.len() as u64)
+    } else {
+        ProgressBar::hidden()
+    };
+    for r in verify_proofs_with_progress(&items, || bar.inc(1)) {
+        outcomes.push(BatchVerifyResult {
+            index: positions[r.index],
+            outcome: r.outcome,
+        });
+    }
+    bar.finish_and_clear();
+    outcomes.sort_by_key(|r| r.index);
+
+    let report: Vec<BatchOutcome> = outcomes
+        .iter()
+        .map(|r| BatchOutcome {
+            index: r.index,
+            result: match r.outcome {
+                Ok(_) => "valid",
+                Err(ZkError::InvalidProof) => "invalid",
+                Err(_) => "error",
+            },
+            error: r.outcome.as_ref().err().map(|e| e.to_string()),
+        })
+        .collect();
+    fs::write(output, serde_json::to_string_pretty(&report)?)?;
+    Ok(BatchSummary::of(&outcomes))
+}
+
+/// Timings of `bench-verify`.
+#[derive(Debug)]
+struct BenchReport {
+    proofs: usize,
+    jobs: usize,
+    /// Proofs per second verified one by one on a single thread.
+    serial: f64,
+    /// Proofs per second verified by the batch API on `jobs` threads.
+    parallel: f64,
+    /// Latencies of the single thread verifications.
+    p50: Duration,
+    p99: Duration,
+}
+
+fn bench_verify(matches: &ArgMatches) -> bool {
+    let proofs = match matches.value_of("proofs").unwrap().parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            eprintln!("invalid --proofs, expected a positive number");
+            return false;
+        }
+    };
+    let pool = match verifier_pool(matches) {
+        Some(pool) => pool,
+        None => return false,
+    };
+    match run_bench(proofs, &pool) {
+        Ok(report) => {
+            println!("proofs            {}", report.proofs);
+            println!("serial            {:.1} proofs/s", report.serial);
+            println!(
+                "parallel          {:.1} proofs/s ({} jobs)",
+                report.parallel, report.jobs
+            );
+            println!("p50               {:.2} ms", millis(report.p50));
+            println!("p99               {:.2} ms", millis(report.p99));
+            true
+        }
+        Err(e) => {
+            eprintln!("bench-verify failed: {}", e);
+            false
+        }
+    }
+}
+
+/// Generates the proofs up front so that only the verification is
+/// timed. The caches are warmed first, as a long running verifier has
+/// them warm too.
+fn run_bench(proofs: usize, pool: &rayon::ThreadPool) -> Result<BenchReport, ZkError> {
+    warm_up()?;
+    let items = bench_proofs(proofs)?;
+
+    let mut latencies = Vec::with_capacity(items.len());
+    for (qr, chain) in &items {
+        let start = Instant::now();
+        verify_proof(qr, chain)?;
+        latencies.push(start.elapsed());
+    }
+    let serial: Duration = latencies.iter().sum();
+
+    let start = Instant::now();
+    let results = pool.install(|| verify_proofs(&items));
+    let parallel = start.elapsed();
+    if let Some(r) = results.into_iter().find(|r| r.outcome.is_err()) {
+        return Err(r.outcome.unwrap_err());
+    }
+
+    latencies.sort();
+    Ok(BenchReport {
+        proofs,
+        jobs: pool.current_num_threads(),
+        serial: per_second(proofs, serial),
+        parallel: per_second(proofs, parallel),
+        p50: percentile(&latencies, 50),
+        p99: percentile(&latencies, 99),
+    })
+}
+
+/// Proofs of one true statement. Each has its own randomness, so no two
+/// are equal.
+fn bench_proofs(n: usize) -> Result<Vec<(ProofQrCode, PublicChain)>, ZkError> {
+    let nonce = Nonce::random().to_bytes();
+    (0..n)
+        .map(|_| {
+            let rq = sample_request(nonce.clone(), 18);
+            let chain = rq.chain.clone();
+            Ok((generate_proof(rq)?, chain))
+        })
+        .collect()
+}
+
+/// Nearest rank percentile of sorted values.
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (sorted.len() * p + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+fn per_second(n: usize, elapsed: Duration) -> f64 {
+    n as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+#[cfg(feature = "schema")]
+fn schema(matches: &ArgMatches) -> bool {
+    let schema = harla_zk::request::json_schema(matches.value_of("NAME").unwrap()).unwrap();
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+    true
+}
+
+#[cfg(feature = "server")]
+fn serve(matches: &ArgMatches) -> bool {
+    use harla_zk::server::{serve, ClientKey, Service};
+    use std::net::IpAddr;
+
+    let addr = matches.value_of("addr").unwrap();
+    let mut service = Service::new();
+    if let Some(n) = matches.value_of("rate-limit") {
+        match n.parse::<u32>() {
+            Ok(n) => service = service.with_rate_limit(n),
+            Err(e) => {
+                eprintln!("invalid --rate-limit: {}", e);
+                return false;
+            }
+        }
+    }
+    let client_key = match matches.value_of("client-header") {
+        Some(name) => {
+            let proxies: Result<Vec<IpAddr>, _> = matches
+                .values_of("trusted-proxy")
+                .into_iter()
+                .flatten()
+                .map(str::parse)
+                .collect();
+            match proxies {
+                Ok(proxies) => ClientKey::Header {
+                    name: name.to_string(),
+                    proxies,
+                },
+                Err(e) => {
+                    eprintln!("invalid --trusted-proxy: {}", e);
+                    return false;
+                }
+            }
+        }
+        None => ClientKey::SourceIp,
+    };
+    println!("listening on {}", addr);
+    match serve(addr, service, client_key) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("serve failed: {}", e);
+            false
+        }
+    }
+}
+
+fn info() -> bool {
+    let info = match artifact_fingerprints() {
+        Ok(info) => info,
+        Err(e) => {
+            eprintln!("info failed: {}", e);
+            return false;
+        }
+    };
+    println!("version           {}", info.version);
+    println!("program           {}", info.program);
+    println!("abi               {}", info.abi);
+    println!("proving key       {}", info.proving_key);
+    match info.verification_key {
+        Some(vk) => println!("verification key  {}", vk),
+        None => println!("verification key  none"),
+    }
+    true
+}
+
+/// Runs the whole certify-prove-verify flow in-process. A true
+/// statement must verify and a false one must not.
+fn selftest() -> bool {
+    let nonce = Nonce::random().to_bytes();
+    let mut ok = report(
+        "older than 18 verifies",
+        prove_and_verify(sample_request(nonce.clone(), 18)),
+        true,
+    );
+    ok &= report(
+        "older than 30 is refused",
+        prove_and_verify(sample_request(nonce, 30)),
+        false,
+    );
+    println!("{}", if ok { "PASS" } else { "FAIL" });
+    ok
+}
+
+fn prove_and_verify(rq: QrRequest) -> Result<bool, ZkError> {
+    let chain = rq.chain.clone();
+    let proof = generate_proof(rq)?;
+    match verify_proof(&proof, &chain) {
+        Ok(_) => Ok(true),
+        Err(ZkError::InvalidProof) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn report(step: &str, outcome: Result<bool, ZkError>, expected: bool) -> bool {
+    match outcome {
+        Ok(verified) if verified == expected => {
+            println!("PASS {}", step);
+            true
+        }
+        Ok(_) => {
+            println!("FAIL {}", step);
+            false
+        }
+        Err(e) => {
+            println!("FAIL {}: {}", step, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use harla_zk::api::{PublicQr, Relation};
+
+    const M1: &str =
+        "10046037004840239707202533642544953578314335199439499999912878067091298310375";
+
+    #[test]
+    fn check_key_m1() {
+        let key = compute_prover_key(2001, "7999", "3", "4").unwrap();
+        assert_eq!(M1, key);
+        assert!(key_matches(&key, M1));
+        assert!(!key_matches(&key, "7999"));
+    }
+
+    #[test]
+    fn field_representations() {
+        let forms = [
+            ("dec", M1),
+            (
+                "hex",
+                "0x1635dafe7bdecf9c2012aa518a3cf71aacdc54a27947d33d0e1e1dfa8b6bd8e7",
+            ),
+            (
+                "le",
+                "e7d86b8bfa1d1e0e3dd34779a254dcac1af73c8a51aa12209ccfde7bfeda3516",
+            ),
+            (
+                "be",
+                "1635dafe7bdecf9c2012aa518a3cf71aacdc54a27947d33d0e1e1dfa8b6bd8e7",
+            ),
+        ];
+        for (from, value) in &forms {
+            for (to, expected) in &forms {
+                assert_eq!(*expected, convert_field(value, from, to).unwrap());
+            }
+        }
+        assert_eq!("0x1f3f", convert_field("7999", "dec", "hex").unwrap());
+        assert!(convert_field("0x1f3f", "dec", "hex").is_err());
+        assert!(convert_field("1f3f", "le", "dec").is_err());
+    }
+
+    #[test]
+    fn nearest_rank_percentile() {
+        let sorted: Vec<Duration> = (1..=200).map(Duration::from_millis).collect();
+        assert_eq!(Duration::from_millis(100), percentile(&sorted, 50));
+        assert_eq!(Duration::from_millis(198), percentile(&sorted, 99));
+        assert_eq!(Duration::from_millis(7), percentile(&sorted[6..7], 99));
+    }
+
+    #[test]
+    fn inspect_renders_date() {
+        let qr = ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 2923,
+                contract: vec![0x12],
+                label: None,
+            },
+            proof: vec![0; 128],
+        };
+        let fields = describe(&qr);
+        assert_eq!(("today", "2021-01-16 (jd 2459231)".to_string()), fields[0]);
+        assert_eq!(("proof", "128 bytes".to_string()), fields[4]);
+        assert_eq!(5, fields.len());
+
+        let mut labelled = qr;
+        labelled.public.label = Some(String::from("BarXYZ age-gate"));
+        assert_eq!(
+            ("label", "BarXYZ age-gate (unverified)".to_string()),
+            describe(&labelled)[5]
+        );
+    }
+}
@@ -1,79 +1,118 @@
 /// Command line utility to simulate a 'LegalAge' prover.
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::NaiveDate;
 use clap::{App, Arg};
-use harla_zk::api::{
-    age_to_delta, naive_date_to_jd, Private, PublicChain, PublicQr, QrRequest, Relation,
-};
-use harla_zk::zk::{generate_proof, generate_prover_key};
-use image::Luma;
-use qrcode::QrCode;
-use serde::{Deserialize, Serialize};
+use harla_zk::api::{BoundaryPolicy, QrEncoding, Relation};
+use harla_zk::clock::{Clock, FixedClock, SystemClock};
+use harla_zk::error::ZkError;
+use harla_zk::qr::{render_terminal_encoded, save_qr_images_encoded, TerminalStyle};
+use harla_zk::request::{ProofFile, ProveRequest, ProverDb, VerificationBundle};
+use harla_zk::zk::{generate_proof_with_metrics, ProofMetrics};
 use serde_json;
 use std::fs;
-use zokrates_field::{Bn128Field, Field};
+use std::io::{self, Read};
+
+/// Content of the `--proof` file.
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum ProofFormat {
+    /// The QR code payload as is.
+    Raw,
+    /// The payload in a `ProofFile` document.
+    Json,
+    /// The payload and the chain record in a `VerificationBundle`.
+    Bundle,
+}
 
 #[derive(Debug, PartialEq, Clone)]
 struct Parameters {
     pub prover_db: String,
     pub today: i32,
     pub relation: Relation,
-    pub age: i32,
+    /// None in the --stdin mode, where the age comes with the request.
+    pub age: Option<i32>,
     pub proof: String,
-    pub qr: String,
+    pub qr: Vec<String>,
+    pub terminal_qr: Option<TerminalStyle>,
+    pub verbose: bool,
+    pub stdin: bool,
+    pub encoding: QrEncoding,
+    pub proof_format: ProofFormat,
 }
 
-fn main() {
-    let p = parse_arguments();
-    let pdb: ProverDb = serde_json::from_str(&fs::read_to_string(&p.prover_db).unwrap()).unwrap();
-    let nonce = Bn128Field::try_from_dec_str(&pdb.nonce)
-        .expect("cannot decode 'nonce' in the proverDb file")
-        .into_byte_vector();
-    let contract = Bn128Field::try_from_dec_str(&pdb.contract)
-        .expect("cannot decode 'contract' in the proverDb file")
-        .into_byte_vector();
-    let photo_hash = Bn128Field::try_from_dec_str(&pdb.photo_hash)
-        .expect("cannot decode 'photo_hash' in the proverDb file")
-        .into_byte_vector();
+fn main() -> Result<(), ZkError> {
+    let p = parse_arguments()?;
+    if p.stdin {
+        return prove_stdin(p.verbose);
+    }
+    let age = p
+        .age
+        .ok_or_else(|| ZkError::InvalidRequest(String::from("--older or --younger is required")))?;
+    let pdb: ProverDb = serde_json::from_str(&fs::read_to_string(&p.prover_db)?)?;
 
-    let delta = age_to_delta(pdb.birthday, p.age, p.relation);
-    let private = Private {
+    // Validated like a request on stdin, which checks the ranges of the
+    // dates and of the age.
+    let request = ProveRequest {
         birthday: pdb.birthday,
-        nonce: nonce,
+        nonce: pdb.nonce,
+        contract: pdb.contract,
+        photo_hash: pdb.photo_hash,
+        today: p.today,
+        relation: p.relation,
+        age,
+        boundary: BoundaryPolicy::default(),
+        label: None,
     };
-    let prover_key = generate_prover_key(&private.clone(), &contract, &photo_hash);
+    let rq = request.to_qr_request()?;
+    let chain = rq.chain.clone();
+    let (proof, metrics) = generate_proof_with_metrics(rq)?;
+    if p.verbose {
+        print_metrics(&metrics);
+    }
+    let payload = proof.encode(p.encoding);
+    match p.proof_format {
+        ProofFormat::Raw => fs::write(p.proof, payload)?,
+        ProofFormat::Json => {
+            fs::write(p.proof, serde_json::to_string(&ProofFile { qr: payload })?)?
+        }
+        ProofFormat::Bundle => {
+            let bundle = VerificationBundle {
+                qr: payload,
+                ..VerificationBundle::bundle(&proof, &chain)
+            };
+            fs::write(p.proof, serde_json::to_string(&bundle)?)?
+        }
+    }
 
-    let rq = QrRequest {
-        qr: PublicQr {
-            today: p.today,
-            contract: contract,
-            delta,
-            relation: p.relation,
-        },
-        chain: PublicChain {
-            photo_hash: photo_hash,
-            prover_key,
-        },
-        private,
-    };
-    let proof = generate_proof(rq).unwrap();
-    let ps = proof.to_string();
-    let qrf = QrFile { qr: ps.clone() };
-    //    let json: String = serde_json::to_string(&qrf).unwrap();
-    fs::write(p.proof, ps).unwrap();
-    //    fs::write(p.proof, json).unwrap();
+    save_qr_images_encoded(&proof, &p.qr, p.encoding)?;
+    if let Some(style) = p.terminal_qr {
+        println!("{}", render_terminal_encoded(&proof, style, p.encoding)?);
+    }
+    Ok(())
+}
+
+fn print_metrics(metrics: &ProofMetrics) {
+    eprintln!("proof bytes    {}", metrics.proof_len);
+    eprintln!("payload bytes  {}", metrics.payload_len);
+    match (metrics.qr_version, metrics.qr_ec_level) {
+        (Some(v), Some(ec)) => eprintln!("qr version     {} ({:?})", v, ec),
+        _ => eprintln!("qr version     payload too long"),
+    }
+}
 
-    let code = QrCode::new(qrf.qr).unwrap();
-    let image = code.render::<Luma<u8>>().build();
-    image.save(p.qr).unwrap();
-    let string = code
-        .render()
-        .light_color('\u{2b1c}')
-        .dark_color('\u{2b1b}')
-        .build();
-    println!("{}", string);
+/// Reads a `ProveRequest` document from stdin and writes the QR code
+/// payload to stdout.
+fn prove_stdin(verbose: bool) -> Result<(), ZkError> {
+    let mut s = String::new();
+    io::stdin().read_to_string(&mut s)?;
+    let request: ProveRequest = serde_json::from_str(&s)?;
+    let (proof, metrics) = generate_proof_with_metrics(request.to_qr_request()?)?;
+    if verbose {
+        print_metrics(&metrics);
+    }
+    println!("{}", proof.to_string());
+    Ok(())
 }
 
-fn parse_arguments() -> Parameters {
+fn parse_arguments() -> Result<Parameters, ZkError> {
     let matches = App::new("prove")
         .version("0.1")
         .author("Ladislav Sladecek <ladislav.sladecek@gmail.com>")
@@ -84,7 +123,7 @@ fn parse_arguments() -> Parameters {
                 .value_name("YEARS")
                 .help("Generates the proof that the user is older than YEARS.")
                 .conflicts_with("younger")
-                .required_unless("younger")
+                .required_unless_one(&["younger", "stdin"])
                 .takes_value(true),
         )
         .arg(
@@ -113,55 +152,154 @@ fn parse_arguments() -> Parameters {
             Arg::with_name("proof")
                 .long("proof")
                 .value_name("FILE")
-                .help("Defines output .json file for the generated proof.")
+                .help("Defines output file for the QR code payload, read by verifier-zk.")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("qr")
                 .long("qr")
                 .value_name("FILE")
-                .help("Defines output file for the QR code.")
+                .help("Defines output file for the QR code, may be repeated; png, svg or jpg.")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("qr-style")
+                .long("qr-style")
+                .value_name("PRESET")
+                .help("Characters of the terminal QR code.")
+                .possible_values(&["blocks", "ascii"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("qr-dark")
+                .long("qr-dark")
+                .value_name("CHAR")
+                .help("Overrides the character of dark terminal QR modules.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("qr-light")
+                .long("qr-light")
+                .value_name("CHAR")
+                .help("Overrides the character of light terminal QR modules.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-terminal-qr")
+                .long("no-terminal-qr")
+                .help("Does not print the QR code to the terminal.")
+                .conflicts_with_all(&["qr-style", "qr-dark", "qr-light"]),
+        )
+        .arg(
+            Arg::with_name("base45")
+                .long("base45")
+                .help("Writes the proof and the QR code in the denser Base45 encoding."),
+        )
+        .arg(
+            Arg::with_name("auto-encoding")
+                .long("auto-encoding")
+                .help("Uses Base45 only if the JSON QR code would be too large to scan.")
+                .conflicts_with("base45"),
+        )
+        .arg(
+            Arg::with_name("proof-format")
+                .long("proof-format")
+                .value_name("FORMAT")
+                .help("Writes the proof file as the bare payload, wrapped in JSON or bundled with the chain record.")
+                .possible_values(&["raw", "json", "bundle"])
+                .default_value("raw")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .help("Prints the size of the proof and of the QR code."),
+        )
+        .arg(
+            Arg::with_name("stdin")
+                .long("stdin")
+                .help("Reads a JSON request from stdin and writes the QR code payload to stdout.")
+                .conflicts_with_all(&["older", "younger", "today", "prover-db", "proof", "qr"]),
+        )
         .get_matches();
 
     let mut relation = Relation::Older;
-    let today = naive_date_today();
-    let age = if matches.is_present("older") {
-        matches.value_of("older").unwrap()
-    } else {
-        relation = Relation::Younger;
-        matches.value_of("younger").unwrap()
+    let clock: Box<dyn Clock> = match matches.value_of("today") {
+        Some(d) => Box::new(FixedClock(
+            NaiveDate::parse_from_str(d, "%Y-%m-%d").map_err(|_| {
+                ZkError::InvalidRequest(format!("invalid --today {}, expected YYYY-MM-DD", d))
+            })?,
+        )),
+        None => Box::new(SystemClock),
+    };
+    let age = match (matches.value_of("older"), matches.value_of("younger")) {
+        (Some(age), _) => Some(age),
+        (None, Some(age)) => {
+            relation = Relation::Younger;
+            Some(age)
+        }
+        (None, None) => None,
     }
-    .parse::<i32>()
-    .unwrap();
+    .map(|age| {
+        age.parse::<i32>()
+            .map_err(|_| ZkError::InvalidRequest(format!("invalid age {}", age)))
+    })
+    .transpose()?;
 
-    let p = Parameters {
+    let terminal_qr = if matches.is_present("no-terminal-qr") {
+        None
+    } else {
+        let mut style = matches
+            .value_of("qr-style")
+            .and_then(TerminalStyle::preset)
+            .unwrap_or_default();
+        if let Some(c) = matches.value_of("qr-dark") {
+            style.dark = single_char("--qr-dark", c)?;
+        }
+        if let Some(c) = matches.value_of("qr-light") {
+            style.light = single_char("--qr-light", c)?;
+        }
+        Some(style)
+    };
+
+    Ok(Parameters {
         age,
         relation,
-        today: naive_date_to_jd(today),
+        today: clock.today_jd(),
         prover_db: String::from(matches.value_of("prover-db").unwrap_or("prover-db.json")),
         proof: String::from(matches.value_of("proof").unwrap_or("proof.json")),
-        qr: String::from(matches.value_of("qr").unwrap_or("proof-qr.jpg")),
-    };
-
-    p
-}
-
-#[derive(Deserialize, Debug)]
-struct ProverDb {
-    pub birthday: i32,
-    pub nonce: String,
-    pub contract: String,
-    pub photo_hash: String,
-}
-
-#[derive(Serialize, Debug)]
-struct QrFile {
-    pub qr: String,
+        qr: match matches.values_of("qr") {
+            Some(files) => files.map(String::from).collect(),
+            None => vec![String::from("proof-qr.jpg")],
+        },
+        terminal_qr,
+        verbose: matches.is_present("verbose"),
+        stdin: matches.is_present("stdin"),
+        encoding: if matches.is_present("base45") {
+            QrEncoding::Base45
+        } else if matches.is_present("auto-encoding") {
+            QrEncoding::Auto
+        } else {
+            QrEncoding::Json
+        },
+        proof_format: match matches.value_of("proof-format") {
+            Some("json") => ProofFormat::Json,
+            Some("bundle") => ProofFormat::Bundle,
+            _ => ProofFormat::Raw,
+        },
+    })
 }
 
-fn naive_date_today() -> NaiveDate {
-    let l = Local::now();
-    NaiveDate::from_ymd(l.year(), l.month(), l.day())
+fn single_char(name: &str, s: &str) -> Result<char, ZkError> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(ZkError::InvalidRequest(format!(
+            "invalid {} {}, expected a single character",
+            name, s
+        ))),
+    }
 }
@@ -1,33 +1,296 @@
 /// Command line utility to be called from 'LegalAge' verifier.
 /// Verifies a proof.
-use harla_zk::api::{ProofQrCode, PublicChain};
-use harla_zk::zk::verify_proof;
+///
+/// Usage: `verifier-zk QR_FILE PHOTO_HASH PROVER_KEY`, where `QR_FILE`
+/// may be `-` to read the QR code payload from stdin. `PHOTO_HASH` and
+/// `PROVER_KEY` are decimal or `0x` prefixed hexadecimal. Prints `1` for a
+/// valid proof and `0` otherwise. Exit status:
+///
+/// * 0 - the proof is valid,
+/// * 1 - the proof is invalid,
+/// * 2 - the arguments or the payload cannot be read.
+///
+/// With `--json` it prints a JSON object instead of the digit: the
+/// decoded public inputs of a valid proof or the reason of the failure.
+///
+/// `verifier-zk BUNDLE_FILE` reads the proof and the chain record from
+/// one `VerificationBundle` file instead, `-` for stdin.
+///
+/// `verifier-zk --stdin` reads a JSON `VerifyRequest` document from stdin
+/// instead and writes a `VerifyResponse`, with the same exit status.
+///
+/// `--vk FILE` verifies against the ZoKrates verification key in `FILE`
+/// instead of the embedded one. Builds with the `no-embedded-vk` feature
+/// have none and require it.
+///
+/// Every verification is logged to stderr, as text or with
+/// `--log-format json` as one JSON object per line for a SIEM. The log
+/// has the public fields and the proof's fingerprint, never secrets.
+use chrono::{SecondsFormat, Utc};
+use harla_zk::api::{ProofQrCode, PublicChain, Relation, VerifiedInputs};
+use harla_zk::error::ZkError;
+use harla_zk::request::{ProofFile, VerificationBundle, VerifyRequest, VerifyResponse};
+use harla_zk::zk::{parse_proof, verify_proof, verify_proof_with_key};
+use serde::Serialize;
 use std::env;
 use std::fs;
-use std::str::FromStr;
-use zokrates_field::{Bn128Field, Field};
+use std::io::{self, Read};
+use std::process;
+use zokrates_field::Field;
 
-fn bn128(s: &str) -> Bn128Field {
-    Bn128Field::try_from_dec_str(s).unwrap()
+const EXIT_INVALID: i32 = 1;
+const EXIT_BAD_INPUT: i32 = 2;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+enum LogFormat {
+    Text,
+    Json,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 4 {
-        panic!("required 3 arguments");
+/// Audit record of one verification.
+#[derive(Serialize, Debug)]
+struct AuditLine {
+    timestamp: String,
+    outcome: &'static str,
+    relation: Relation,
+    today: i32,
+    fingerprint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl AuditLine {
+    fn of(qr: &ProofQrCode, result: &Result<VerifiedInputs, ZkError>) -> Self {
+        AuditLine {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            outcome: if result.is_ok() { "valid" } else { "invalid" },
+            relation: qr.public.relation,
+            today: qr.public.today,
+            fingerprint: hex::encode(qr.fingerprint()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+
+    fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Json => serde_json::to_string(self).unwrap(),
+            LogFormat::Text => format!(
+                "{} {} {:?} today={} fingerprint={}{}",
+                self.timestamp,
+                self.outcome,
+                self.relation,
+                self.today,
+                self.fingerprint,
+                match &self.error {
+                    Some(e) => format!(" error=\"{}\"", e),
+                    None => String::new(),
+                }
+            ),
+        }
+    }
+}
+
+/// Output of `--json`. Field elements are decimal strings.
+#[derive(Serialize, Debug)]
+struct JsonOutcome {
+    valid: bool,
+    /// The proof's label as written, whether or not it verified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inputs: Option<JsonInputs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct JsonInputs {
+    today: String,
+    relation: Relation,
+    delta: i32,
+    contract: String,
+    photo_hash: String,
+    prover_key: String,
+}
+
+impl JsonOutcome {
+    fn of(qr: &ProofQrCode, result: &Result<VerifiedInputs, ZkError>) -> Self {
+        let label = qr.public.label.clone();
+        match result {
+            Ok(inputs) => JsonOutcome {
+                valid: true,
+                label,
+                inputs: Some(JsonInputs {
+                    today: inputs.today.format("%Y-%m-%d").to_string(),
+                    relation: inputs.relation,
+                    delta: inputs.delta,
+                    contract: inputs.contract.to_dec_string(),
+                    photo_hash: inputs.photo_hash.to_dec_string(),
+                    prover_key: inputs.prover_key.to_dec_string(),
+                }),
+                error: None,
+            },
+            Err(e) => JsonOutcome {
+                valid: false,
+                label,
+                inputs: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Removes `flag` from the arguments and tells whether it was there.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|a| a != flag);
+    args.len() != before
+}
+
+/// Removes `--log-format FORMAT` from the arguments.
+fn take_log_format(args: &mut Vec<String>) -> Result<LogFormat, String> {
+    let i = match args.iter().position(|a| a == "--log-format") {
+        Some(i) => i,
+        None => return Ok(LogFormat::Text),
+    };
+    let format = match args.get(i + 1).map(String::as_str) {
+        Some("text") => LogFormat::Text,
+        Some("json") => LogFormat::Json,
+        _ => return Err(String::from("--log-format expects text or json")),
+    };
+    args.drain(i..i + 2);
+    Ok(format)
+}
+
+/// Removes `--vk FILE` from the arguments and reads the key.
+fn take_vk(args: &mut Vec<String>) -> Result<Option<Vec<u8>>, String> {
+    let i = match args.iter().position(|a| a == "--vk") {
+        Some(i) => i,
+        None if cfg!(feature = "no-embedded-vk") => {
+            return Err(String::from(
+                "--vk is required, this build has no embedded verification key",
+            ))
+        }
+        None => return Ok(None),
+    };
+    let path = args
+        .get(i + 1)
+        .cloned()
+        .ok_or_else(|| String::from("--vk expects a file"))?;
+    let vk = fs::read(&path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    args.drain(i..i + 2);
+    Ok(Some(vk))
+}
+
+fn verify_and_log(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    vk: Option<&[u8]>,
+    log: LogFormat,
+) -> Result<VerifiedInputs, ZkError> {
+    let result = match vk {
+        Some(vk) => verify_proof_with_key(qr, chain, vk),
+        None => verify_proof(qr, chain),
+    };
+    eprintln!("{}", AuditLine::of(qr, &result).format(log));
+    result
+}
+
+/// Reads the payload from a proof file of `prove --proof-format` raw or
+/// json.
+fn read_payload(path: &str) -> Result<String, ZkError> {
+    Ok(ProofFile::payload(&read_input(path)?))
+}
+
+/// The contents of a file, or of stdin for `-`.
+fn read_input(path: &str) -> Result<String, ZkError> {
+    if path == "-" {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s)?;
+        Ok(s)
+    } else {
+        Ok(fs::read_to_string(path)?)
+    }
+}
+
+fn parse_arguments(args: &[String]) -> Result<(ProofQrCode, PublicChain), ZkError> {
+    if args.len() == 2 {
+        let bundle: VerificationBundle = serde_json::from_str(&read_input(&args[1])?)?;
+        return bundle.parse();
     }
+    let qr = parse_proof(&read_payload(&args[1])?)?;
+    let chain_data = PublicChain::from_strs(&args[2], &args[3])?;
+    Ok((qr, chain_data))
+}
 
-    let qr_json = fs::read_to_string(&args[1]).unwrap();
-    let qr = ProofQrCode::from_str(&qr_json).unwrap();
-    let photo_hash = bn128(&args[2]);
-    let prover_key = bn128(&args[3]);
+fn verify_stdin(vk: Option<&[u8]>, log: LogFormat) -> ! {
+    let mut s = String::new();
+    let request = io::stdin()
+        .read_to_string(&mut s)
+        .map_err(ZkError::from)
+        .and_then(|_| serde_json::from_str::<VerifyRequest>(&s).map_err(ZkError::from))
+        .and_then(|r| r.parse());
+    let (qr, chain_data) = match request {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_BAD_INPUT);
+        }
+    };
+    let result = verify_and_log(&qr, &chain_data, vk, log);
+    println!(
+        "{}",
+        serde_json::to_string(&VerifyResponse::of(&result)).unwrap()
+    );
+    process::exit(if result.is_ok() { 0 } else { EXIT_INVALID });
+}
 
-    let chain_data = PublicChain {
-        photo_hash: photo_hash.into_byte_vector(),
-        prover_key: prover_key.into_byte_vector(),
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let log = match take_log_format(&mut args) {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_BAD_INPUT);
+        }
+    };
+    let vk = match take_vk(&mut args) {
+        Ok(vk) => vk,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_BAD_INPUT);
+        }
     };
-    //    println!("{}", qr.to_string());
-    //    println!("{:?}", chain_data);
-    let result = verify_proof(&qr, &chain_data).is_ok();
-    println!("{}", if result { 1 } else { 0 });
+    let vk = vk.as_deref();
+    if args.len() == 2 && args[1] == "--stdin" {
+        verify_stdin(vk, log);
+    }
+    let json = take_flag(&mut args, "--json");
+    if args.len() != 2 && args.len() != 4 {
+        eprintln!(
+            "usage: verifier-zk [--log-format text|json] [--vk FILE] [--json] QR_FILE|- PHOTO_HASH PROVER_KEY"
+        );
+        eprintln!("       verifier-zk [--log-format text|json] [--vk FILE] [--json] BUNDLE_FILE|-");
+        process::exit(EXIT_BAD_INPUT);
+    }
+    let (qr, chain_data) = match parse_arguments(&args) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_BAD_INPUT);
+        }
+    };
+
+    let result = verify_and_log(&qr, &chain_data, vk, log);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&JsonOutcome::of(&qr, &result)).unwrap()
+        );
+    } else {
+        println!("{}", if result.is_ok() { 1 } else { 0 });
+    }
+    if result.is_err() {
+        process::exit(EXIT_INVALID);
+    }
 }
@@ -0,0 +1,355 @@
+// Coarse age classes composed from the older/younger comparisons.
+//
+// The circuit proves a single comparison and has no output which could
+// carry a label. A bucket is therefore proved by one proof per bound and
+// the verifier derives the label from the set of comparisons which
+// verified. Nothing but the bucket is revealed.
+//
+// An age outside of a range is the other way around: one of two
+// comparisons holds. A proof of the false one would not verify, so only
+// the true one is proved and the verifier learns which side of the
+// range the user is on.
+
+use crate::api::{age_to_delta, Private, ProofQrCode, PublicChain, PublicQr, QrRequest, Relation};
+use crate::error::ZkError;
+use crate::zk::{generate_proof, verify_proof};
+
+pub const ADULT_AGE: i32 = 18;
+pub const SENIOR_AGE: i32 = 65;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum AgeBucket {
+    /// Younger than 18.
+    Minor,
+    /// At least 18 and younger than 65.
+    Adult,
+    /// At least 65.
+    Senior,
+}
+
+const BUCKETS: [AgeBucket; 3] = [AgeBucket::Minor, AgeBucket::Adult, AgeBucket::Senior];
+
+impl AgeBucket {
+    pub fn label(self) -> &'static str {
+        match self {
+            AgeBucket::Minor => "<18",
+            AgeBucket::Adult => "18..65",
+            AgeBucket::Senior => ">=65",
+        }
+    }
+
+    /// Comparisons which together mean membership in the bucket.
+    fn bounds(self) -> &'static [(Relation, i32)] {
+        match self {
+            AgeBucket::Minor => &[(Relation::Younger, ADULT_AGE)],
+            AgeBucket::Adult => &[
+                (Relation::Older, ADULT_AGE),
+                (Relation::Younger, SENIOR_AGE),
+            ],
+            AgeBucket::Senior => &[(Relation::Older, SENIOR_AGE)],
+        }
+    }
+
+    /// The bucket of someone born on `birthday` as of `today` (both
+    /// julian dates). `None` within a day or two of the 18th and 65th
+    /// birthday, where `age_to_delta` refuses both comparisons.
+    pub fn of(birthday: i32, today: i32) -> Option<AgeBucket> {
+        BUCKETS.iter().copied().find(|bucket| {
+            bucket.bounds().iter().all(|&(relation, age)| {
+                relation.holds(birthday, age_to_delta(birthday, age, relation), today)
+            })
+        })
+    }
+}
+
+/// Proofs of all bounds of a bucket, in the order of `AgeBucket::bounds`.
+#[derive(Debug, Clone)]
+pub struct BucketProof {
+    pub bucket: AgeBucket,
+    pub proofs: Vec<ProofQrCode>,
+}
+
+pub fn prove_bucket(
+    private: &Private,
+    chain: &PublicChain,
+    contract: &[u8],
+    today: i32,
+) -> Result<BucketProof, ZkError> {
+    let bucket = AgeBucket::of(private.birthday, today).ok_or(ZkError::StatementFalse)?;
+    let proofs = prove_bounds(private, chain, contract, today, bucket.bounds())?;
+    Ok(BucketProof { bucket, proofs })
+}
+
+/// One proof per comparison, all of the same day and contract.
+fn prove_bounds(
+    private: &Private,
+    chain: &PublicChain,
+    contract: &[u8],
+    today: i32,
+    bounds: &[(Relation, i32)],
+) -> Result<Vec<ProofQrCode>, ZkError> {
+    bounds
+        .iter()
+        .map(|&(relation, age)| {
+            generate_proof(QrRequest {
+                qr: PublicQr {
+                    today,
+                    relation,
+                    delta: age_to_delta(private.birthday, age, relation),
+                    contract: contract.to_vec(),
+                    label: None,
+                },
+                chain: chain.clone(),
+                private: private.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Tells whether `delta` can be the boundary of `age` years for some
+/// birthday. The number of leap days in the span depends on the
+/// birthday, which the verifier does not know.
+fn delta_fits_age(delta: i32, age: i32) -> bool {
+    let days = 365 * age + age / 4;
+    (days - 2..=days + 3).contains(&delta)
+}
+
+/// Verifies all proofs of the bucket and returns the bucket.
+pub fn verify_bucket(bp: &BucketProof, chain: &PublicChain) -> Result<AgeBucket, ZkError> {
+    verify_bounds(&bp.proofs, bp.bucket.bounds(), chain)?;
+    Ok(bp.bucket)
+}
+
+/// Verifies that the proofs are those of the comparisons.
+fn verify_bounds(
+    proofs: &[ProofQrCode],
+    bounds: &[(Relation, i32)],
+    chain: &PublicChain,
+) -> Result<(), ZkError> {
+    if proofs.len() != bounds.len() {
+        return Err(ZkError::BucketMismatch);
+    }
+    let first = &proofs[0].public;
+    for (proof, &(relation, age)) in proofs.iter().zip(bounds) {
+        let public = &proof.public;
+        if public.relation != relation
+            || !delta_fits_age(public.delta, age)
+            || public.today != first.today
+            || public.contract != first.contract
+        {
+            return Err(ZkError::BucketMismatch);
+        }
+        verify_proof(proof, chain)?;
+    }
+    Ok(())
+}
+
+/// Younger than `min` or older than `max` years, e.g. outside of the
+/// working age.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Outside {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Outside {
+    pub fn new(min: i32, max: i32) -> Result<Self, ZkError> {
+        if min <= 0 || min >= max {
+            return Err(ZkError::InvalidRequest(format!(
+                "expected 0 < min < max, got {}..{}",
+                min, max
+            )));
+        }
+        Ok(Outside { min, max })
+    }
+
+    /// The two comparisons, either of which means being outside.
+    fn sides(self) -> [(Relation, i32); 2] {
+        [(Relation::Younger, self.min), (Relation::Older, self.max)]
+    }
+
+    /// The comparison which holds for someone born on `birthday` as of
+    /// `today`. `None` inside the range, which includes the day before
+    /// and after the `min`-th and `max`-th birthday.
+    pub fn side(self, birthday: i32, today: i32) -> Option<(Relation, i32)> {
+        self.sides().iter().copied().find(|&(relation, age)| {
+            relation.holds(birthday, age_to_delta(birthday, age, relation), today)
+        })
+    }
+}
+
+/// The proof of the side of the range which holds.
+#[derive(Debug, Clone)]
+pub struct OutsideProof {
+    pub range: Outside,
+    pub proof: ProofQrCode,
+}
+
+pub fn prove_outside(
+    private: &Private,
+    chain: &PublicChain,
+    contract: &[u8],
+    today: i32,
+    range: Outside,
+) -> Result<OutsideProof, ZkError> {
+    let side = range
+        .side(private.birthday, today)
+        .ok_or(ZkError::StatementFalse)?;
+    let mut proofs = prove_bounds(private, chain, contract, today, &[side])?;
+    Ok(OutsideProof {
+        range,
+        proof: proofs.remove(0),
+    })
+}
+
+/// Verifies the proof against the verifier's `range` and returns the
+/// relation proved: `Younger` than `min` or `Older` than `max`. The
+/// range of the bundle is the prover's claim and must be the same.
+pub fn verify_outside(
+    op: &OutsideProof,
+    range: Outside,
+    chain: &PublicChain,
+) -> Result<Relation, ZkError> {
+    if op.range != range {
+        return Err(ZkError::BucketMismatch);
+    }
+    let side = range
+        .sides()
+        .iter()
+        .copied()
+        .find(|&(relation, _)| relation == op.proof.public.relation)
+        .ok_or(ZkError::BucketMismatch)?;
+    verify_bounds(std::slice::from_ref(&op.proof), &[side], chain)?;
+    Ok(side.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::naive_date_to_jd;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::testutil::bart_request;
+    use chrono::NaiveDate;
+
+    fn jd(y: i32, m: u32, d: u32) -> i32 {
+        naive_date_to_jd(NaiveDate::from_ymd(y, m, d))
+    }
+
+    #[test]
+    fn bucket_boundaries() {
+        let birthday = jd(2000, 6, 15);
+        assert_eq!(
+            Some(AgeBucket::Minor),
+            AgeBucket::of(birthday, jd(2018, 6, 13))
+        );
+        assert_eq!(None, AgeBucket::of(birthday, jd(2018, 6, 15)));
+        assert_eq!(
+            Some(AgeBucket::Adult),
+            AgeBucket::of(birthday, jd(2018, 6, 17))
+        );
+        assert_eq!(
+            Some(AgeBucket::Adult),
+            AgeBucket::of(birthday, jd(2065, 6, 13))
+        );
+        assert_eq!(None, AgeBucket::of(birthday, jd(2065, 6, 15)));
+        assert_eq!(
+            Some(AgeBucket::Senior),
+            AgeBucket::of(birthday, jd(2065, 6, 17))
+        );
+    }
+
+    #[test]
+    fn delta_window_covers_all_birthdays() {
+        for birthday in jd(1999, 1, 1)..jd(2003, 1, 1) {
+            for &age in &[ADULT_AGE, SENIOR_AGE] {
+                for &relation in &[Relation::Older, Relation::Younger] {
+                    assert!(delta_fits_age(age_to_delta(birthday, age, relation), age));
+                }
+            }
+        }
+        assert!(!delta_fits_age(
+            age_to_delta(jd(2000, 1, 1), 17, Relation::Older),
+            18
+        ));
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn outside_boundaries() {
+        let rq = bart_request();
+        let birthday = rq.private.birthday;
+        let outside = Outside::new(18, 65).unwrap();
+        assert!(Outside::new(65, 18).is_err());
+
+        let before_18th = jd(2028, 2, 20);
+        let after_65th = jd(2075, 2, 24);
+        assert_eq!(
+            Some((Relation::Younger, 18)),
+            outside.side(birthday, before_18th)
+        );
+        assert_eq!(None, outside.side(birthday, before_18th + 1));
+        assert_eq!(None, outside.side(birthday, after_65th - 1));
+        assert_eq!(
+            Some((Relation::Older, 65)),
+            outside.side(birthday, after_65th)
+        );
+
+        for &(today, relation) in &[
+            (before_18th, Relation::Younger),
+            (after_65th, Relation::Older),
+        ] {
+            let op =
+                prove_outside(&rq.private, &rq.chain, &rq.qr.contract, today, outside).unwrap();
+            assert_eq!(relation, verify_outside(&op, outside, &rq.chain).unwrap());
+        }
+        match prove_outside(
+            &rq.private,
+            &rq.chain,
+            &rq.qr.contract,
+            jd(2040, 1, 1),
+            outside,
+        ) {
+            Err(ZkError::StatementFalse) => {}
+            other => panic!("expected StatementFalse, got {:?}", other),
+        }
+
+        // A proof of another bound does not pass for the range, whether
+        // or not the bundle names the verifier's range.
+        let op = prove_outside(
+            &rq.private,
+            &rq.chain,
+            &rq.qr.contract,
+            before_18th,
+            outside,
+        )
+        .unwrap();
+        let from_21 = Outside::new(21, 65).unwrap();
+        let relabelled = OutsideProof {
+            range: from_21,
+            ..op.clone()
+        };
+        for (op, range) in &[(op, from_21), (relabelled, from_21)] {
+            match verify_outside(op, *range, &rq.chain) {
+                Err(ZkError::BucketMismatch) => {}
+                other => panic!("expected BucketMismatch, got {:?}", other),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn prove_and_verify_minor() {
+        // Bart is 10.
+        let rq = bart_request();
+        let bp = prove_bucket(&rq.private, &rq.chain, &rq.qr.contract, rq.qr.today).unwrap();
+        assert_eq!(AgeBucket::Minor, bp.bucket);
+        assert_eq!(AgeBucket::Minor, verify_bucket(&bp, &rq.chain).unwrap());
+
+        let mut forged = bp;
+        forged.bucket = AgeBucket::Senior;
+        match verify_bucket(&forged, &rq.chain) {
+            Err(ZkError::BucketMismatch) => {}
+            other => panic!("expected BucketMismatch, got {:?}", other),
+        }
+    }
+}
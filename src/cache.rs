@@ -0,0 +1,258 @@
+// Reuse of already generated proofs and witnesses.
+//
+// Proofs are randomized: proving the same request twice gives two
+// different, equally valid proofs. A cache returns the first one again,
+// so a phone showing the same QR code repeatedly does not pay for a new
+// proof every time.
+//
+// A witness cache keeps the result of running the circuit instead and
+// proves it anew, so the proofs stay unlinkable and only the execution
+// is saved. The witness cannot be shared between statements: the
+// interpreter runs the whole program from all seven arguments, and the
+// witness does not tell which of its variables depend on the identity
+// alone. "Older than 18" and "older than 21" differ in the delta and so
+// in the witness; only a repeated statement is a hit. A hit is faster
+// than a miss, so the key is the requested statement and the secrets,
+// not the circuit arguments: a false statement runs with substituted
+// arguments which are the same for every false statement of one
+// identity on one day, and keying on them would make each false probe
+// after the first a hit. Keyed on the request, two different false
+// statements miss like two different true ones, and the timing tells
+// repetition, not truth.
+
+use crate::api::{check_label, ProofPolicy, ProofQrCode, QrRequest};
+use crate::error::ZkError;
+use crate::zk::{execute_witness, generate_proof, prove_stages};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use zokrates_core::ir;
+use zokrates_field::Bn128Field;
+
+type RequestHash = [u8; 32];
+
+/// Least recently used entries by a hash.
+pub(crate) struct Lru<V> {
+    capacity: usize,
+    entries: Mutex<VecDeque<(RequestHash, V)>>,
+}
+
+impl<V: Clone> Lru<V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &RequestHash) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        let i = entries.iter().position(|(k, _)| k == key)?;
+        let entry = entries.remove(i)?;
+        let value = entry.1.clone();
+        entries.push_back(entry);
+        Some(value)
+    }
+
+    pub(crate) fn insert(&self, key: RequestHash, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(k, _)| *k != key);
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((key, value));
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+/// Least recently used cache of proofs keyed by the request.
+pub struct ProofCache {
+    entries: Lru<ProofQrCode>,
+}
+
+impl ProofCache {
+    pub fn new(capacity: usize) -> Self {
+        ProofCache {
+            entries: Lru::new(capacity),
+        }
+    }
+
+    /// Returns the cached proof of `rq` or generates and caches a new
+    /// one. Errors are not cached.
+    pub fn generate(&self, rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+        check_label(&rq.qr.label)?;
+        let key = request_hash(&rq);
+        if let Some(proof) = self.entries.get(&key) {
+            return Ok(proof);
+        }
+        let proof = generate_proof(rq)?;
+        self.entries.insert(key, proof.clone());
+        Ok(proof)
+    }
+}
+
+/// Least recently used cache of circuit witnesses keyed by the request.
+/// The witness holds the nonce, so it must stay in memory like the
+/// request itself, and it is only valid for the circuit it was executed
+/// by, the one of this process.
+pub struct WitnessCache {
+    entries: Lru<ir::Witness<Bn128Field>>,
+}
+
+impl WitnessCache {
+    pub fn new(capacity: usize) -> Self {
+        WitnessCache {
+            entries: Lru::new(capacity),
+        }
+    }
+
+    /// Like `generate_proof`, with its checks and a fresh proof every
+    /// time, but the circuit runs only for requests not seen recently.
+    pub fn generate(&self, rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+        let key = request_hash(&rq);
+        prove_stages(
+            rq,
+            ProofPolicy::FailSilently,
+            None,
+            |arguments| match self.entries.get(&key) {
+                Some(witness) => Ok(witness),
+                None => {
+                    let witness = execute_witness(arguments)?;
+                    self.entries.insert(key, witness.clone());
+                    Ok(witness)
+                }
+            },
+        )
+    }
+}
+
+/// Digest of everything the proof depends on, secrets and the label
+/// included. It stays in memory only.
+fn request_hash(rq: &QrRequest) -> RequestHash {
+    let mut h = Sha256::new();
+    h.update(rq.qr.today.to_be_bytes());
+    h.update([rq.qr.relation as u8]);
+    h.update(rq.qr.delta.to_be_bytes());
+    // No label and an empty one differ.
+    let label = rq.qr.label.as_ref().map(String::as_bytes);
+    h.update([label.is_some() as u8]);
+    for bytes in &[
+        &rq.qr.contract[..],
+        &rq.chain.photo_hash[..],
+        &rq.chain.prover_key[..],
+        &rq.private.nonce[..],
+        label.unwrap_or_default(),
+    ] {
+        h.update((bytes.len() as u32).to_be_bytes());
+        h.update(bytes);
+    }
+    h.update(rq.private.birthday.to_be_bytes());
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&h.finalize());
+    hash
+}
+
+static DEFAULT_CACHE: Lazy<ProofCache> = Lazy::new(|| ProofCache::new(16));
+
+/// Like `generate_proof` but returns the same proof for the same
+/// request as long as it stays in a small process-wide cache.
+pub fn generate_proof_cached(rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+    DEFAULT_CACHE.generate(rq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{age_to_delta, Relation};
+    use crate::testutil::bart_request;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::zk::verify_proof;
+
+    #[test]
+    fn same_request_same_proof() {
+        let cache = ProofCache::new(2);
+        let first = cache.generate(bart_request()).unwrap();
+        let second = cache.generate(bart_request()).unwrap();
+        assert_eq!(first.proof, second.proof);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn witness_reused_for_same_statement() {
+        let cache = WitnessCache::new(2);
+        let chain = bart_request().chain;
+        let uncached = generate_proof(bart_request()).unwrap();
+        let first = cache.generate(bart_request()).unwrap();
+        let second = cache.generate(bart_request()).unwrap();
+        assert_eq!(1, cache.entries.len());
+        assert_ne!(first.proof, second.proof);
+        for proof in &[uncached, first, second] {
+            assert!(verify_proof(proof, &chain).is_ok());
+        }
+
+        let mut other = bart_request();
+        other.qr.delta = age_to_delta(other.private.birthday, 9, Relation::Older);
+        assert!(verify_proof(&cache.generate(other).unwrap(), &chain).is_ok());
+        assert_eq!(2, cache.entries.len());
+    }
+
+    /// False statements are proven with the same substituted arguments;
+    /// a hit for the second one would tell that both are false.
+    #[test]
+    fn false_statements_miss() {
+        let cache = WitnessCache::new(4);
+        for age in &[12, 13] {
+            let mut rq = bart_request();
+            rq.qr.delta = age_to_delta(rq.private.birthday, *age, Relation::Older);
+            assert!(!rq.is_relation_valid());
+            cache.generate(rq).unwrap();
+        }
+        assert_eq!(2, cache.entries.len());
+    }
+
+    #[test]
+    fn request_hash_covers_secrets() {
+        let rq = bart_request();
+        let mut other = bart_request();
+        other.private.nonce[0] ^= 1;
+        assert_eq!(request_hash(&rq), request_hash(&bart_request()));
+        assert_ne!(request_hash(&rq), request_hash(&other));
+    }
+
+    #[test]
+    fn label_is_cached_and_checked() {
+        let cache = ProofCache::new(2);
+        let mut labelled = bart_request();
+        labelled.qr.label = Some(String::from("gate 1"));
+        let mut empty = bart_request();
+        empty.qr.label = Some(String::new());
+        assert_ne!(request_hash(&bart_request()), request_hash(&empty));
+        assert_ne!(request_hash(&labelled), request_hash(&empty));
+
+        cache.generate(bart_request()).unwrap();
+        let proof = cache.generate(labelled).unwrap();
+        assert_eq!(Some(String::from("gate 1")), proof.public.label);
+
+        let bad = || {
+            let mut rq = bart_request();
+            rq.qr.label = Some(String::from("gate\u{1b}[2J"));
+            rq
+        };
+        assert!(matches!(
+            cache.generate(bad()),
+            Err(ZkError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            WitnessCache::new(2).generate(bad()),
+            Err(ZkError::InvalidRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+// Enrollment of users by the certifier.
+//
+// For every user the certifier draws a secret nonce and computes the
+// prover key which goes to the chain. The nonce and the birthday are
+// handed to the user's device.
+//
+// A user whose nonce leaked, or whose device was lost, is re-issued a
+// fresh nonce and with it a new prover key; the old key is revoked. The
+// prover key is `mimc(birthday + nonce, photo_hash * contract)` with a
+// uniformly random nonce, so without the nonces the old and the new key
+// look unrelated. They are not unlinked, though: both chain records
+// carry the same photo hash, and the certifier knows both nonces. A user
+// who wants a key nobody can link to the old one must be enrolled with
+// a new photo.
+
+use crate::api::Private;
+use crate::error::ZkError;
+use crate::field::{field_from_dec_str, field_to_dec_string};
+use crate::zk::{generate_nonce, generate_prover_key};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// One user to be enrolled. Field elements are decimal strings as in
+/// `prover-db.json`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CertifyRecord {
+    pub birthday: i32,
+    pub photo_hash: String,
+    pub contract: String,
+}
+
+/// The secrets and the prover key of an enrolled user.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CertifiedKey {
+    pub nonce: String,
+    pub prover_key: String,
+    pub birthday: i32,
+}
+
+pub fn certify<R: Rng>(record: &CertifyRecord, rng: &mut R) -> Result<CertifiedKey, ZkError> {
+    let photo_hash = field_from_dec_str(&record.photo_hash)?;
+    let contract = field_from_dec_str(&record.contract)?;
+    let private = Private {
+        birthday: record.birthday,
+        nonce: generate_nonce(rng),
+    };
+    let prover_key = generate_prover_key(&private, &contract, &photo_hash);
+    Ok(CertifiedKey {
+        nonce: field_to_dec_string(&private.nonce),
+        prover_key: field_to_dec_string(&prover_key),
+        birthday: record.birthday,
+    })
+}
+
+/// Enrolls the records in order drawing all nonces from one generator.
+pub fn certify_batch<R: Rng>(
+    records: &[CertifyRecord],
+    rng: &mut R,
+) -> Result<Vec<CertifiedKey>, ZkError> {
+    records.iter().map(|r| certify(r, rng)).collect()
+}
+
+/// A user enrolled again under a new nonce. `key` goes to the user and
+/// the chain; the certifier keeps `old_prover_key` to revoke it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Reissue {
+    pub key: CertifiedKey,
+    pub old_prover_key: String,
+}
+
+impl Reissue {
+    /// Adds the old prover key to the revocation list of
+    /// `zk::verify_proof_with_revocation`.
+    pub fn revoke_old(&self, revoked: &mut HashSet<Vec<u8>>) -> Result<(), ZkError> {
+        revoked.insert(field_from_dec_str(&self.old_prover_key)?);
+        Ok(())
+    }
+}
+
+/// Replaces the secrets `old` of the user `record` by `new`, typically
+/// with a nonce drawn by `zk::generate_nonce`. The birthday is certified
+/// and cannot change, and the nonce must.
+pub fn reissue(old: &Private, new: &Private, record: &CertifyRecord) -> Result<Reissue, ZkError> {
+    if old.birthday != record.birthday || new.birthday != record.birthday {
+        return Err(ZkError::InvalidRequest(String::from(
+            "birthday differs from the certified one",
+        )));
+    }
+    if old.nonce == new.nonce {
+        return Err(ZkError::InvalidRequest(String::from(
+            "the new nonce equals the old one",
+        )));
+    }
+    let photo_hash = field_from_dec_str(&record.photo_hash)?;
+    let contract = field_from_dec_str(&record.contract)?;
+    let old_prover_key = generate_prover_key(old, &contract, &photo_hash);
+    let prover_key = generate_prover_key(new, &contract, &photo_hash);
+    Ok(Reissue {
+        key: CertifiedKey {
+            nonce: field_to_dec_string(&new.nonce),
+            prover_key: field_to_dec_string(&prover_key),
+            birthday: record.birthday,
+        },
+        old_prover_key: field_to_dec_string(&old_prover_key),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::testutil::bart_request;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::zk::{generate_proof, verify_proof_with_revocation};
+    use rand::{ChaChaRng, SeedableRng};
+
+    fn records() -> Vec<CertifyRecord> {
+        vec![
+            CertifyRecord {
+                birthday: 2455250,
+                photo_hash: "70573743172686605492515124569".to_string(),
+                contract: "291478163806436998532036252836091753082125673821".to_string(),
+            },
+            CertifyRecord {
+                birthday: 2451711,
+                photo_hash: "3".to_string(),
+                contract: "4".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn batch_matches_single_invocations() {
+        let seed = [1, 2, 3, 4];
+        let batch = certify_batch(&records(), &mut ChaChaRng::from_seed(&seed)).unwrap();
+
+        let mut rng = ChaChaRng::from_seed(&seed);
+        let single: Vec<CertifiedKey> = records()
+            .iter()
+            .map(|r| certify(r, &mut rng).unwrap())
+            .collect();
+        assert_eq!(single, batch);
+        assert_ne!(batch[0].nonce, batch[1].nonce);
+    }
+
+    #[test]
+    fn prover_key_derives_from_nonce() {
+        let record = &records()[0];
+        let key = certify(record, &mut ChaChaRng::from_seed(&[5])).unwrap();
+        let private = Private {
+            birthday: record.birthday,
+            nonce: field_from_dec_str(&key.nonce).unwrap(),
+        };
+        let expected = generate_prover_key(
+            &private,
+            &field_from_dec_str(&record.contract).unwrap(),
+            &field_from_dec_str(&record.photo_hash).unwrap(),
+        );
+        assert_eq!(field_to_dec_string(&expected), key.prover_key);
+    }
+
+    #[test]
+    fn reject_bad_record() {
+        let mut bad = records();
+        bad[1].contract = "four".to_string();
+        match certify_batch(&bad, &mut ChaChaRng::from_seed(&[5])) {
+            Err(ZkError::InvalidDecimal(s)) => assert_eq!("four", s),
+            other => panic!("expected InvalidDecimal, got {:?}", other),
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn reissue_revokes_old_key() {
+        let record = &records()[0];
+        let old = bart_request();
+        let new = Private {
+            birthday: record.birthday,
+            nonce: generate_nonce(&mut ChaChaRng::from_seed(&[6])),
+        };
+        let reissued = reissue(&old.private, &new, record).unwrap();
+        assert_eq!(
+            field_to_dec_string(&old.chain.prover_key),
+            reissued.old_prover_key
+        );
+        assert_ne!(reissued.old_prover_key, reissued.key.prover_key);
+        assert!(matches!(
+            reissue(&new, &new, record),
+            Err(ZkError::InvalidRequest(_))
+        ));
+
+        let mut revoked = HashSet::new();
+        reissued.revoke_old(&mut revoked).unwrap();
+        let old_chain = old.chain.clone();
+        let old_proof = generate_proof(old).unwrap();
+        assert!(matches!(
+            verify_proof_with_revocation(&old_proof, &old_chain, &revoked),
+            Err(ZkError::Revoked)
+        ));
+
+        let mut rq = bart_request();
+        rq.private = new;
+        rq.chain.prover_key = field_from_dec_str(&reissued.key.prover_key).unwrap();
+        let new_chain = rq.chain.clone();
+        let new_proof = generate_proof(rq).unwrap();
+        assert!(verify_proof_with_revocation(&new_proof, &new_chain, &revoked).is_ok());
+    }
+}
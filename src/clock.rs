@@ -0,0 +1,49 @@
+// Source of the current date.
+
+use crate::api::naive_date_to_jd;
+use chrono::{Local, NaiveDate};
+
+/// Tells the current date. Proofs are always about 'today', so the
+/// source of the date is a parameter rather than a hidden call to the
+/// system clock.
+pub trait Clock {
+    fn today(&self) -> NaiveDate;
+
+    /// Today as a julian date.
+    fn today_jd(&self) -> i32 {
+        naive_date_to_jd(self.today())
+    }
+}
+
+/// Local date according to the system clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn today(&self) -> NaiveDate {
+        Local::now().naive_local().date()
+    }
+}
+
+/// Always the same date. Used in tests and when the date comes from a
+/// trusted source.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub NaiveDate);
+
+impl Clock for FixedClock {
+    fn today(&self) -> NaiveDate {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_clock_julian_day() {
+        let clock = FixedClock(NaiveDate::from_ymd(2021, 1, 16));
+        assert_eq!(clock.today(), NaiveDate::from_ymd(2021, 1, 16));
+        assert_eq!(clock.today_jd(), 2459231);
+    }
+}
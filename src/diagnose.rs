@@ -0,0 +1,203 @@
+// Support tooling: explains why a proof does not verify.
+//
+// Most functions here need the secrets recorded at certification. They
+// belong in the certifier's support tools, never in a public gate.
+
+use crate::api::{Private, ProofQrCode, PublicChain, Relation};
+use crate::error::ZkError;
+use crate::field::field_to_bytes;
+use crate::zk::{generate_prover_key, verify_proof};
+use zokrates_field::{Bn128Field, Field};
+
+fn same_field(a: &[u8], b: &[u8]) -> bool {
+    let a = field_to_bytes(&Bn128Field::from_byte_vector(a.to_vec()));
+    let b = field_to_bytes(&Bn128Field::from_byte_vector(b.to_vec()));
+    a == b
+}
+
+/// Compares the public inputs of a verification with the prover's
+/// certified secrets and reports the first one which differs. `Ok`
+/// means the chain record agrees with the secrets and the failure lies
+/// elsewhere, e.g. the statement is false.
+pub fn diagnose(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    private: &Private,
+    photo_hash: &[u8],
+    contract: &[u8],
+) -> Result<(), ZkError> {
+    if !same_field(&qr.public.contract, contract) {
+        return Err(ZkError::ContractMismatch);
+    }
+    if !same_field(&chain.photo_hash, photo_hash) {
+        return Err(ZkError::PhotoHashMismatch);
+    }
+    let expected = generate_prover_key(private, contract, photo_hash);
+    if !same_field(&chain.prover_key, &expected) {
+        return Err(ZkError::ProverKeyMismatch);
+    }
+    Ok(())
+}
+
+/// Tells whether a failing proof was made for a relation of the other
+/// direction, i.e. someone relabelled the relation in the QR code. Needs
+/// no secrets but runs up to three verifications: the proof itself and
+/// each relation whose `is_younger` differs, as the delta of a strict
+/// relation fits only one of the other direction. Returns
+/// `RelationTampered` if the proof verifies with one of them; `Ok`
+/// otherwise. A relabel within one direction is `RelationTampered` from
+/// `verify_proof` already.
+pub fn diagnose_relation(qr: &ProofQrCode, chain: &PublicChain) -> Result<(), ZkError> {
+    if verify_proof(qr, chain).is_ok() {
+        return Ok(());
+    }
+    let relations = [
+        Relation::Older,
+        Relation::Younger,
+        Relation::YoungerOrEqual,
+        Relation::OlderOrEqual,
+    ];
+    let younger = qr.public.relation.is_younger();
+    for relation in relations.iter().filter(|r| r.is_younger() != younger) {
+        let mut flipped = qr.clone();
+        flipped.public.relation = *relation;
+        if verify_proof(&flipped, chain).is_ok() {
+            return Err(ZkError::RelationTampered);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::PublicQr;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::testutil::bart_request;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::zk::generate_proof;
+
+    struct Case {
+        qr: ProofQrCode,
+        chain: PublicChain,
+        private: Private,
+        photo_hash: Vec<u8>,
+        contract: Vec<u8>,
+    }
+
+    fn field(n: i32) -> Vec<u8> {
+        field_to_bytes(&Bn128Field::from(n))
+    }
+
+    fn consistent() -> Case {
+        let private = Private {
+            birthday: 2455250,
+            nonce: field(7999),
+        };
+        let photo_hash = field(3);
+        let contract = field(4);
+        let prover_key = generate_prover_key(&private, &contract, &photo_hash);
+        Case {
+            qr: ProofQrCode {
+                public: PublicQr {
+                    today: 2459231,
+                    relation: Relation::Older,
+                    delta: 2923,
+                    contract: contract.clone(),
+                    label: None,
+                },
+                proof: Vec::new(),
+            },
+            chain: PublicChain {
+                photo_hash: photo_hash.clone(),
+                prover_key,
+            },
+            private,
+            photo_hash,
+            contract,
+        }
+    }
+
+    fn run(c: &Case) -> Result<(), ZkError> {
+        diagnose(&c.qr, &c.chain, &c.private, &c.photo_hash, &c.contract)
+    }
+
+    #[test]
+    fn consistent_chain() {
+        assert!(run(&consistent()).is_ok());
+    }
+
+    #[test]
+    fn wrong_contract() {
+        let mut c = consistent();
+        c.qr.public.contract = field(5);
+        match run(&c) {
+            Err(ZkError::ContractMismatch) => {}
+            other => panic!("expected ContractMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrong_photo_hash() {
+        let mut c = consistent();
+        c.chain.photo_hash = field(6);
+        match run(&c) {
+            Err(ZkError::PhotoHashMismatch) => {}
+            other => panic!("expected PhotoHashMismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wrong_prover_key() {
+        let mut c = consistent();
+        c.chain.prover_key = field(7);
+        match run(&c) {
+            Err(ZkError::ProverKeyMismatch) => {}
+            other => panic!("expected ProverKeyMismatch, got {:?}", other),
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn flipped_relation() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let mut qr = generate_proof(rq).unwrap();
+        assert!(diagnose_relation(&qr, &chain).is_ok());
+
+        qr.public.relation = Relation::Younger;
+        match diagnose_relation(&qr, &chain) {
+            Err(ZkError::RelationTampered) => {}
+            other => panic!("expected RelationTampered, got {:?}", other),
+        }
+
+        qr.public.delta += 1;
+        assert!(diagnose_relation(&qr, &chain).is_ok());
+    }
+
+    /// A genuine inclusive proof relabelled to the strict relation of the
+    /// other direction.
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn flipped_inclusive_relation() {
+        use crate::api::age_to_delta;
+
+        for (genuine, age, relabel) in &[
+            (Relation::YoungerOrEqual, 11, Relation::Older),
+            (Relation::OlderOrEqual, 8, Relation::Younger),
+        ] {
+            let mut rq = bart_request();
+            rq.qr.relation = *genuine;
+            rq.qr.delta = age_to_delta(rq.private.birthday, *age, *genuine);
+            let chain = rq.chain.clone();
+            let mut qr = generate_proof(rq).unwrap();
+            assert!(diagnose_relation(&qr, &chain).is_ok());
+
+            qr.public.relation = *relabel;
+            match diagnose_relation(&qr, &chain) {
+                Err(ZkError::RelationTampered) => {}
+                other => panic!("{:?}: expected RelationTampered, got {:?}", genuine, other),
+            }
+        }
+    }
+}
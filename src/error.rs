@@ -0,0 +1,225 @@
+// Error type shared by the library and the command line utilities.
+
+use crate::api::QrError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ZkError {
+    /// The embedded (or supplied) verification key cannot be parsed.
+    KeyDeserialize(serde_json::Error),
+
+    /// Any other JSON document cannot be parsed or written.
+    Json(serde_json::Error),
+
+    /// Reading or writing a file or a byte buffer failed.
+    Io(io::Error),
+
+    /// The compiled circuit cannot be loaded.
+    Program(String),
+
+    /// The circuit refused the arguments.
+    Execution(String),
+
+    /// A field element is not encoded in exactly 32 bytes.
+    FieldLength(usize),
+
+    /// A field element is not below the field modulus.
+    FieldOutOfRange,
+
+    /// A JSON request document has invalid values.
+    InvalidRequest(String),
+
+    /// A string is not a decimal field element.
+    InvalidDecimal(String),
+
+    /// A string is not a `0x` prefixed hexadecimal field element.
+    InvalidHex(String),
+
+    /// A string has hex digits but no `0x` prefix.
+    AmbiguousNumber(String),
+
+    /// The embedded artifacts come from different setups.
+    ArtifactMismatch(String),
+
+    /// The delta in the request was not computed for its relation and
+    /// the policy says so.
+    RelationDeltaMismatch,
+
+    /// The statement to be proved is false and the policy says so.
+    StatementFalse,
+
+    /// The QR code payload cannot be decoded.
+    InvalidQrPayload,
+
+    /// The QR code payload lacks the named field.
+    MissingField(&'static str),
+
+    /// The QR code payload does not match its checksum, it was damaged.
+    CorruptPayload,
+
+    /// The QR code payload was written in a newer format.
+    UnsupportedVersion { found: u64, supported: u8 },
+
+    /// The contract in the QR code differs from the certified one.
+    ContractMismatch,
+
+    /// The proof is bound to a contract the verifier does not accept.
+    ContractNotAccepted,
+
+    /// The proof counts the birthday otherwise than the verifier's
+    /// `BoundaryPolicy`.
+    BoundaryNotAccepted,
+
+    /// The photo hash on the chain differs from the certified one.
+    PhotoHashMismatch,
+
+    /// The prover key on the chain does not derive from the secrets.
+    ProverKeyMismatch,
+
+    /// The proof is valid for the other relation than the QR code says.
+    RelationTampered,
+
+    /// The proofs of an age bucket do not match its bounds.
+    BucketMismatch,
+
+    /// No verification key is embedded or configured, so one must be
+    /// passed to `zk::verify_proof_with_key`.
+    NoVerificationKey,
+
+    /// The proof bytes do not decode into curve points.
+    MalformedProof,
+
+    /// The proof is well formed but does not verify.
+    InvalidProof,
+
+    /// The proof verifies but its prover key was revoked.
+    Revoked,
+
+    /// Proof generation was cancelled through its `CancellationToken`.
+    Cancelled,
+
+    /// The proof cannot be rendered as a QR code image.
+    QrRender(String),
+
+    /// The QR code image cannot be read.
+    QrScan(String),
+
+    /// The image has no QR code.
+    NoQrCode,
+
+    /// The image has more than one QR code, so which one is the proof
+    /// is ambiguous.
+    MultipleQrCodes(usize),
+
+    /// The URI of a proof is malformed or of an unsupported kind.
+    InvalidUri(String),
+
+    /// The proof cannot be downloaded, or it is too large.
+    Download(String),
+
+    /// The proof store cannot be read or written.
+    Store(String),
+
+    /// The proof was presented before.
+    Replayed,
+
+    /// The HTTP server cannot start.
+    Server(String),
+
+    /// The device signature of a proof does not verify.
+    InvalidSignature,
+}
+
+impl fmt::Display for ZkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZkError::KeyDeserialize(e) => write!(f, "couldn't deserialize verification key: {}", e),
+            ZkError::Json(e) => write!(f, "invalid json: {}", e),
+            ZkError::Io(e) => write!(f, "i/o error: {}", e),
+            ZkError::Program(e) => write!(f, "invalid program: {}", e),
+            ZkError::Execution(e) => write!(f, "execution failed: {}", e),
+            ZkError::FieldLength(n) => write!(f, "field element has {} bytes, expected 32", n),
+            ZkError::FieldOutOfRange => write!(f, "field element is not below the modulus"),
+            ZkError::InvalidRequest(e) => write!(f, "invalid request: {}", e),
+            ZkError::InvalidDecimal(s) => write!(f, "not a decimal field element: {}", s),
+            ZkError::InvalidHex(s) => write!(f, "not a hexadecimal field element: {}", s),
+            ZkError::AmbiguousNumber(s) => {
+                write!(f, "{} is not decimal, prefix hexadecimal with 0x", s)
+            }
+            ZkError::ArtifactMismatch(e) => write!(f, "artifact mismatch: {}", e),
+            ZkError::RelationDeltaMismatch => write!(f, "delta does not match the relation"),
+            ZkError::StatementFalse => write!(f, "the statement is false"),
+            ZkError::InvalidQrPayload => write!(f, "invalid QR code payload"),
+            ZkError::MissingField(name) => write!(f, "QR code payload has no {} field", name),
+            ZkError::CorruptPayload => write!(f, "damaged QR code payload, rescan the code"),
+            ZkError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "QR code format version {} is not supported, expected at most {}",
+                found, supported
+            ),
+            ZkError::ContractMismatch => write!(f, "contract differs from the certified one"),
+            ZkError::ContractNotAccepted => write!(f, "contract is not accepted"),
+            ZkError::BoundaryNotAccepted => {
+                write!(f, "relation does not follow the boundary policy")
+            }
+            ZkError::PhotoHashMismatch => write!(f, "photo hash differs from the certified one"),
+            ZkError::ProverKeyMismatch => write!(f, "prover key does not match the secrets"),
+            ZkError::RelationTampered => write!(f, "relation in the QR code was changed"),
+            ZkError::BucketMismatch => write!(f, "proofs do not match the age bucket"),
+            ZkError::NoVerificationKey => write!(f, "no verification key, supply one"),
+            ZkError::MalformedProof => write!(f, "malformed proof"),
+            ZkError::InvalidProof => write!(f, "proof verification failed"),
+            ZkError::Revoked => write!(f, "prover key revoked"),
+            ZkError::Cancelled => write!(f, "proof generation cancelled"),
+            ZkError::QrRender(e) => write!(f, "cannot render QR code: {}", e),
+            ZkError::QrScan(e) => write!(f, "cannot read QR code image: {}", e),
+            ZkError::NoQrCode => write!(f, "no QR code found in the image"),
+            ZkError::MultipleQrCodes(n) => {
+                write!(f, "{} QR codes found in the image, expected one", n)
+            }
+            ZkError::InvalidUri(e) => write!(f, "invalid proof URI: {}", e),
+            ZkError::Download(e) => write!(f, "cannot download proof: {}", e),
+            ZkError::Store(e) => write!(f, "proof store failed: {}", e),
+            ZkError::Replayed => write!(f, "proof was already presented"),
+            ZkError::Server(e) => write!(f, "server failed: {}", e),
+            ZkError::InvalidSignature => write!(f, "invalid device signature"),
+        }
+    }
+}
+
+impl Error for ZkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZkError::KeyDeserialize(e) | ZkError::Json(e) => Some(e),
+            ZkError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ZkError {
+    fn from(e: serde_json::Error) -> Self {
+        ZkError::Json(e)
+    }
+}
+
+impl From<io::Error> for ZkError {
+    fn from(e: io::Error) -> Self {
+        ZkError::Io(e)
+    }
+}
+
+impl From<QrError> for ZkError {
+    fn from(e: QrError) -> Self {
+        match e {
+            QrError::Malformed => ZkError::InvalidQrPayload,
+            QrError::Corrupt => ZkError::CorruptPayload,
+            QrError::MissingField(name) => ZkError::MissingField(name),
+            QrError::UnsupportedVersion { found, supported } => {
+                ZkError::UnsupportedVersion { found, supported }
+            }
+        }
+    }
+}
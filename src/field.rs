@@ -0,0 +1,352 @@
+// Conversions between byte vectors and field elements.
+//
+// Field elements travel between the certifier, the chain and the
+// prover as byte vectors. The canonical encoding is exactly
+// `FIELD_BYTES` little-endian bytes holding a value below the field
+// modulus. Numbers written for people, in hex or decimal, are
+// big-endian; the `_le_`/`_be_` functions name the order explicitly
+// for code talking to other languages.
+//
+// Every decoding function rejects a value at or above the modulus with
+// `FieldOutOfRange` instead of reducing it. Reduced, the modulus would
+// decode as 0 and a chain record could name one key in two ways.
+
+use crate::error::ZkError;
+use subtle::ConstantTimeEq;
+use zokrates_field::{Bn128Field, Field};
+
+/// Length of an encoded field element.
+pub const FIELD_BYTES: usize = 32;
+
+/// Modulus of the Bn128 scalar field, little-endian.
+const MODULUS_LE: [u8; FIELD_BYTES] = [
+    0x01, 0x00, 0x00, 0xf0, 0x93, 0xf5, 0xe1, 0x43, 0x91, 0x70, 0xb9, 0x79, 0x48, 0xe8, 0x33, 0x28,
+    0x5d, 0x58, 0x81, 0x81, 0xb6, 0x45, 0x50, 0xb8, 0x29, 0xa0, 0x31, 0xe1, 0x72, 0x4e, 0x64, 0x30,
+];
+
+/// Decodes a canonically encoded field element.
+pub fn field_from_bytes(bytes: &[u8]) -> Result<Bn128Field, ZkError> {
+    if bytes.len() != FIELD_BYTES {
+        return Err(ZkError::FieldLength(bytes.len()));
+    }
+    if bytes.iter().rev().cmp(MODULUS_LE.iter().rev()) != std::cmp::Ordering::Less {
+        return Err(ZkError::FieldOutOfRange);
+    }
+    Ok(Bn128Field::from_byte_vector(bytes.to_vec()))
+}
+
+/// Encodes a field element canonically.
+pub fn field_to_bytes(value: &Bn128Field) -> Vec<u8> {
+    let mut bytes = value.into_byte_vector();
+    bytes.resize(FIELD_BYTES, 0);
+    bytes
+}
+
+/// The canonical length of little-endian bytes of any length: trailing
+/// zeros dropped and padded to 32 bytes, so that `[4]` and `[4, 0, ...]`
+/// compare equal. Longer values are kept and equal no canonical one.
+pub fn canonical_field_bytes(bytes: &[u8]) -> Vec<u8> {
+    let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    let mut canonical = bytes[..len].to_vec();
+    if canonical.len() < FIELD_BYTES {
+        canonical.resize(FIELD_BYTES, 0);
+    }
+    canonical
+}
+
+/// The canonical encoding, least significant byte first; the same as
+/// `field_to_bytes`.
+pub fn field_to_le_bytes(value: &Bn128Field) -> Vec<u8> {
+    field_to_bytes(value)
+}
+
+/// `FIELD_BYTES` bytes, most significant first, as the number is
+/// written in hex.
+pub fn field_to_be_bytes(value: &Bn128Field) -> Vec<u8> {
+    let mut bytes = field_to_bytes(value);
+    bytes.reverse();
+    bytes
+}
+
+/// Inverse of `field_to_le_bytes`; the same as `field_from_bytes`.
+pub fn field_from_le_bytes(bytes: &[u8]) -> Result<Bn128Field, ZkError> {
+    field_from_bytes(bytes)
+}
+
+/// Inverse of `field_to_be_bytes`, with the checks of `field_from_bytes`.
+pub fn field_from_be_bytes(bytes: &[u8]) -> Result<Bn128Field, ZkError> {
+    let le: Vec<u8> = bytes.iter().rev().copied().collect();
+    field_from_bytes(&le)
+}
+
+/// Parses a decimal string as used in the JSON files of the
+/// certifier and the prover. Values at or above the modulus are
+/// rejected rather than reduced.
+pub fn field_from_dec_str(s: &str) -> Result<Vec<u8>, ZkError> {
+    if s.is_empty() || !s.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(ZkError::InvalidDecimal(s.to_string()));
+    }
+    let value =
+        Bn128Field::try_from_dec_str(s).map_err(|_| ZkError::InvalidDecimal(s.to_string()))?;
+    let digits = s.trim_start_matches('0');
+    if value.to_dec_string() != if digits.is_empty() { "0" } else { digits } {
+        return Err(ZkError::FieldOutOfRange);
+    }
+    Ok(field_to_bytes(&value))
+}
+
+/// Parses a `0x` prefixed hexadecimal number, most significant digit
+/// first as numbers are written, e.g. `0x0d` is 13.
+pub fn field_from_hex_str(s: &str) -> Result<Vec<u8>, ZkError> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .filter(|d| !d.is_empty() && d.bytes().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| ZkError::InvalidHex(s.to_string()))?
+        .trim_start_matches('0');
+    if digits.len() > 2 * FIELD_BYTES {
+        return Err(ZkError::FieldOutOfRange);
+    }
+    let padded = format!("{:0>width$}", digits, width = 2 * FIELD_BYTES);
+    let be = hex::decode(padded).map_err(|_| ZkError::InvalidHex(s.to_string()))?;
+    Ok(field_to_bytes(&field_from_be_bytes(&be)?))
+}
+
+/// Parses a field element written either in decimal or as `0x` prefixed
+/// hexadecimal. Hex digits without the prefix are refused rather than
+/// guessed, since `10` would be valid in both.
+pub fn field_from_str(s: &str) -> Result<Vec<u8>, ZkError> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        field_from_hex_str(s)
+    } else if !s.is_empty()
+        && s.bytes().all(|c| c.is_ascii_hexdigit())
+        && !s.bytes().all(|c| c.is_ascii_digit())
+    {
+        Err(ZkError::AmbiguousNumber(s.to_string()))
+    } else {
+        field_from_dec_str(s)
+    }
+}
+
+/// Formats a field element as a decimal string. The bytes are expected
+/// to be canonical, e.g. from `field_to_bytes`; they are not checked.
+pub fn field_to_dec_string(bytes: &[u8]) -> String {
+    Bn128Field::from_byte_vector(bytes.to_vec()).to_dec_string()
+}
+
+/// Compares two byte strings in time which depends on their length
+/// only. Used wherever bytes from a QR code or the chain are compared to
+/// a verifier's list, so the timing does not tell which entry matched.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Tells whether `item` is one of `set`, comparing with every member
+/// instead of stopping at the first match.
+pub fn ct_contains<'a, I>(set: I, item: &[u8]) -> bool
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    set.into_iter()
+        .fold(subtle::Choice::from(0), |found, m| found | m.ct_eq(item))
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let value = Bn128Field::from(7999);
+        let bytes = field_to_bytes(&value);
+        assert_eq!(FIELD_BYTES, bytes.len());
+        assert_eq!(value, field_from_bytes(&bytes).unwrap());
+    }
+
+    #[test]
+    fn reject_short_and_long() {
+        let bytes = field_to_bytes(&Bn128Field::from(3));
+        match field_from_bytes(&bytes[..FIELD_BYTES - 1]) {
+            Err(ZkError::FieldLength(31)) => {}
+            other => panic!("expected FieldLength, got {:?}", other),
+        }
+        let mut long = bytes.clone();
+        long.extend_from_slice(&[0; 8]);
+        match field_from_bytes(&long) {
+            Err(ZkError::FieldLength(40)) => {}
+            other => panic!("expected FieldLength, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn canonical_length() {
+        let four = field_to_bytes(&Bn128Field::from(4));
+        assert_eq!(four, canonical_field_bytes(&[4]));
+        assert_eq!(four, canonical_field_bytes(&four));
+        assert_eq!(vec![0; FIELD_BYTES], canonical_field_bytes(&[]));
+        let mut long = four.clone();
+        long.push(1);
+        assert_eq!(long, canonical_field_bytes(&long));
+    }
+
+    #[test]
+    fn decimal_round_trip() {
+        let bytes = field_from_dec_str("291478163806436998532036252836091753082125673821").unwrap();
+        assert_eq!(
+            "291478163806436998532036252836091753082125673821",
+            field_to_dec_string(&bytes)
+        );
+        assert_eq!(
+            field_to_bytes(&Bn128Field::from(0)),
+            field_from_dec_str("000").unwrap()
+        );
+        for bad in &["0x12", "-5", ""] {
+            match field_from_dec_str(bad) {
+                Err(ZkError::InvalidDecimal(_)) => {}
+                other => panic!("expected InvalidDecimal, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn hex_and_decimal() {
+        let three = field_to_bytes(&Bn128Field::from(3));
+        assert_eq!(three, field_from_hex_str("0x03").unwrap());
+        assert_eq!(three, field_from_str("0X3").unwrap());
+        assert_eq!(three, field_from_str("3").unwrap());
+        let bytes = field_from_dec_str("291478163806436998532036252836091753082125673821").unwrap();
+        assert_eq!(
+            bytes,
+            field_from_str("0x330e55395b367bab55b24b5377f7fe813735e55d").unwrap()
+        );
+        match field_from_str("ff") {
+            Err(ZkError::AmbiguousNumber(_)) => {}
+            other => panic!("expected AmbiguousNumber, got {:?}", other),
+        }
+        for bad in &["0x", "0xg1", "x12"] {
+            assert!(field_from_str(bad).is_err(), "{}", bad);
+        }
+        let modulus = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+        match field_from_hex_str(modulus) {
+            Err(ZkError::FieldOutOfRange) => {}
+            other => panic!("expected FieldOutOfRange, got {:?}", other),
+        }
+    }
+
+    /// The prover key of birthday 2001, nonce 7999, contract 4 and photo
+    /// hash 3 in both byte orders, for implementations in other
+    /// languages to reproduce.
+    #[test]
+    fn byte_order_vector() {
+        let private = crate::api::Private {
+            birthday: 2001,
+            nonce: field_to_le_bytes(&Bn128Field::from(7999)),
+        };
+        assert_eq!(
+            "3f1f000000000000000000000000000000000000000000000000000000000000",
+            hex::encode(&private.nonce)
+        );
+        let key = crate::zk::generate_prover_key(
+            &private,
+            &field_to_le_bytes(&Bn128Field::from(4)),
+            &field_to_le_bytes(&Bn128Field::from(3)),
+        );
+        let key = field_from_le_bytes(&key).unwrap();
+        let be = "1635dafe7bdecf9c2012aa518a3cf71aacdc54a27947d33d0e1e1dfa8b6bd8e7";
+        let le = "e7d86b8bfa1d1e0e3dd34779a254dcac1af73c8a51aa12209ccfde7bfeda3516";
+        assert_eq!(be, hex::encode(field_to_be_bytes(&key)));
+        assert_eq!(le, hex::encode(field_to_le_bytes(&key)));
+        assert_eq!(key, field_from_be_bytes(&hex::decode(be).unwrap()).unwrap());
+        assert_eq!(
+            field_to_le_bytes(&key),
+            field_from_hex_str(&format!("0x{}", be)).unwrap()
+        );
+        assert_eq!(
+            "10046037004840239707202533642544953578314335199439499999912878067091298310375",
+            key.to_dec_string()
+        );
+    }
+
+    #[test]
+    fn constant_time_membership() {
+        let set = vec![vec![1, 2, 3], vec![4, 5, 6]];
+        assert!(ct_eq(&[4, 5, 6], &set[1]));
+        assert!(!ct_eq(&[4, 5], &set[1]));
+        assert!(ct_contains(&set, &[4, 5, 6]));
+        assert!(!ct_contains(&set, &[4, 5, 7]));
+        assert!(!ct_contains(&Vec::new(), &[1]));
+    }
+
+    #[test]
+    fn decimal_over_modulus() {
+        let modulus =
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+        match field_from_dec_str(modulus) {
+            Err(ZkError::FieldOutOfRange) => {}
+            other => panic!("expected FieldOutOfRange, got {:?}", other),
+        }
+    }
+
+    /// The modulus p and p + 1 are refused in every notation; p - 1, which
+    /// is -1 in the field, is the largest value accepted.
+    #[test]
+    fn modulus_edges() {
+        let modulus =
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+        let hex = "0x30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f000000";
+        let with_last = |s: &str, digit: char| format!("{}{}", &s[..s.len() - 1], digit);
+        let with_low_byte = |b: u8| {
+            let mut bytes = MODULUS_LE;
+            bytes[0] = b;
+            bytes
+        };
+
+        let below = with_low_byte(0x00);
+        let max = field_from_bytes(&below).unwrap();
+        assert_eq!(Bn128Field::from(0) - Bn128Field::from(1), max);
+        assert_eq!(Bn128Field::from(0), max.clone() + Bn128Field::from(1));
+        assert_eq!(below.to_vec(), field_to_bytes(&max));
+        assert_eq!(with_last(modulus, '6'), field_to_dec_string(&below));
+        let be: Vec<u8> = below.iter().rev().copied().collect();
+        assert_eq!(max, field_from_be_bytes(&be).unwrap());
+        assert_eq!(
+            below.to_vec(),
+            field_from_dec_str(&with_last(modulus, '6')).unwrap()
+        );
+        assert_eq!(
+            below.to_vec(),
+            field_from_str(&format!("{}0", hex)).unwrap()
+        );
+
+        for (low, last) in &[(0x01, '7'), (0x02, '8')] {
+            let bytes = with_low_byte(*low);
+            let be: Vec<u8> = bytes.iter().rev().copied().collect();
+            let results = vec![
+                field_from_le_bytes(&bytes).map(|_| ()),
+                field_from_be_bytes(&be).map(|_| ()),
+                field_from_dec_str(&with_last(modulus, *last)).map(|_| ()),
+                field_from_hex_str(&format!("{}{}", hex, low)).map(|_| ()),
+                field_from_str(&format!("{}{}", hex, low)).map(|_| ()),
+            ];
+            for r in results {
+                match r {
+                    Err(ZkError::FieldOutOfRange) => {}
+                    other => panic!("{}: expected FieldOutOfRange, got {:?}", last, other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reject_over_modulus() {
+        match field_from_bytes(&MODULUS_LE) {
+            Err(ZkError::FieldOutOfRange) => {}
+            other => panic!("expected FieldOutOfRange, got {:?}", other),
+        }
+        match field_from_bytes(&[0xff; FIELD_BYTES]) {
+            Err(ZkError::FieldOutOfRange) => {}
+            other => panic!("expected FieldOutOfRange, got {:?}", other),
+        }
+    }
+}
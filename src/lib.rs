@@ -1,3 +1,31 @@
 // harla_zk is a background library for  legalage phone app.
+
+#[cfg(all(feature = "debug-inputs", not(debug_assertions)))]
+compile_error!("the debug-inputs feature prints circuit inputs and is for debug builds only");
+
 pub mod api;
+pub mod artifacts;
+pub mod base45;
+pub mod batch;
+pub mod bucket;
+pub mod cache;
+pub mod certify;
+pub mod clock;
+#[cfg(feature = "diagnostics")]
+pub mod diagnose;
+pub mod error;
+pub mod field;
+pub mod prelude;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod request;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "sqlite")]
+pub mod store;
+#[cfg(test)]
+mod testutil;
+pub mod uri;
 pub mod zk;
@@ -0,0 +1,17 @@
+// The stable public surface for `use harla_zk::prelude::*`.
+//
+// Everything here keeps its name and signature across minor versions.
+// The rest of the crate, in particular the functions taking or returning
+// ZoKrates types (witnesses, interpreters, verification keys), follows
+// the circuit and may change with it.
+
+pub use crate::api::{
+    age_to_delta, jd_to_naive_date, naive_date_to_jd, Private, ProofPolicy, ProofQrCode,
+    PublicChain, PublicQr, QrRequest, Relation, VerifiedInputs,
+};
+pub use crate::clock::{Clock, FixedClock, SystemClock};
+pub use crate::error::ZkError;
+pub use crate::zk::{
+    generate_nonce, generate_proof, generate_proof_with_policy, generate_prover_key, verify_proof,
+    warm_up,
+};
@@ -0,0 +1,448 @@
+// Rendering of proofs as QR code images.
+
+use crate::api::{split_payload, ProofQrCode, PublicChain, QrEncoding, VerifiedInputs, MAX_PARTS};
+use crate::error::ZkError;
+use crate::uri::{scan_image, MAX_IMAGE_SIDE};
+use crate::zk::{parse_proof, verify_proof};
+use image::{DynamicImage, GrayImage, ImageOutputFormat, Luma};
+use qrcode::render::svg;
+use qrcode::{QrCode, Version};
+use std::fs;
+use std::path::Path;
+
+/// Image format of a rendered QR code.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Svg,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Svg => "image/svg+xml",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    /// Guesses the format from a file name, e.g. `proof-qr.jpg`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "svg" => Some(ImageFormat::Svg),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+fn qr_code(proof: &ProofQrCode, encoding: QrEncoding) -> Result<QrCode, ZkError> {
+    QrCode::new(proof.encode(encoding)).map_err(|e| ZkError::QrRender(e.to_string()))
+}
+
+/// Characters used to draw a QR code in a terminal.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct TerminalStyle {
+    pub dark: char,
+    pub light: char,
+}
+
+impl TerminalStyle {
+    /// Plain ASCII, readable everywhere but needs a dark background.
+    pub fn ascii() -> Self {
+        TerminalStyle {
+            dark: '#',
+            light: ' ',
+        }
+    }
+
+    /// Large black and white squares.
+    pub fn blocks() -> Self {
+        TerminalStyle {
+            dark: '\u{2b1b}',
+            light: '\u{2b1c}',
+        }
+    }
+
+    /// Looks up a preset by its name.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(TerminalStyle::ascii()),
+            "blocks" => Some(TerminalStyle::blocks()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for TerminalStyle {
+    fn default() -> Self {
+        TerminalStyle::blocks()
+    }
+}
+
+/// Renders the proof as text to be printed in a terminal.
+pub fn render_terminal(proof: &ProofQrCode, style: TerminalStyle) -> Result<String, ZkError> {
+    render_terminal_encoded(proof, style, QrEncoding::Json)
+}
+
+pub fn render_terminal_encoded(
+    proof: &ProofQrCode,
+    style: TerminalStyle,
+    encoding: QrEncoding,
+) -> Result<String, ZkError> {
+    Ok(qr_code(proof, encoding)?
+        .render()
+        .dark_color(style.dark)
+        .light_color(style.light)
+        .build())
+}
+
+/// Renders the proof as an image file in memory.
+pub fn render_qr_image(proof: &ProofQrCode, format: ImageFormat) -> Result<Vec<u8>, ZkError> {
+    render_qr_image_encoded(proof, format, QrEncoding::Json)
+}
+
+pub fn render_qr_image_encoded(
+    proof: &ProofQrCode,
+    format: ImageFormat,
+    encoding: QrEncoding,
+) -> Result<Vec<u8>, ZkError> {
+    let code = qr_code(proof, encoding)?;
+    let output = match format {
+        ImageFormat::Png => ImageOutputFormat::Png,
+        ImageFormat::Jpeg => ImageOutputFormat::Jpeg(90),
+        ImageFormat::Svg => return Ok(code.render::<svg::Color>().build().into_bytes()),
+    };
+    let image = DynamicImage::ImageLuma8(code.render::<Luma<u8>>().build());
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut bytes, output)
+        .map_err(|e| ZkError::QrRender(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// What `render_qr` renders: the payload encoding and one image per
+/// format. The default is a JSON payload as PNG.
+#[derive(PartialEq, Debug, Clone)]
+pub struct QrOptions {
+    pub encoding: QrEncoding,
+    pub formats: Vec<ImageFormat>,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        QrOptions {
+            encoding: QrEncoding::Json,
+            formats: vec![ImageFormat::Png],
+        }
+    }
+}
+
+impl QrOptions {
+    pub fn with_encoding(mut self, encoding: QrEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn with_formats(mut self, formats: &[ImageFormat]) -> Self {
+        self.formats = formats.to_vec();
+        self
+    }
+}
+
+/// Images of one QR code, all carrying `payload`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct RenderedQr {
+    pub payload: String,
+    pub images: Vec<(ImageFormat, Vec<u8>)>,
+}
+
+impl RenderedQr {
+    pub fn image(&self, format: ImageFormat) -> Option<&[u8]> {
+        self.images
+            .iter()
+            .find(|(f, _)| *f == format)
+            .map(|(_, bytes)| &bytes[..])
+    }
+}
+
+/// Renders an existing proof, e.g. one parsed from a store, without
+/// proving again.
+pub fn render_qr(proof: &ProofQrCode, options: &QrOptions) -> Result<RenderedQr, ZkError> {
+    let images = options
+        .formats
+        .iter()
+        .map(|&format| {
+            Ok((
+                format,
+                render_qr_image_encoded(proof, format, options.encoding)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, ZkError>>()?;
+    Ok(RenderedQr {
+        payload: proof.encode(options.encoding),
+        images,
+    })
+}
+
+/// Writes the proof's QR code to each of the files, in the format given
+/// by the file's extension. All files carry the same payload.
+pub fn save_qr_images<P: AsRef<Path>>(proof: &ProofQrCode, paths: &[P]) -> Result<(), ZkError> {
+    save_qr_images_encoded(proof, paths, QrEncoding::Json)
+}
+
+pub fn save_qr_images_encoded<P: AsRef<Path>>(
+    proof: &ProofQrCode,
+    paths: &[P],
+    encoding: QrEncoding,
+) -> Result<(), ZkError> {
+    for path in paths {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            ZkError::QrRender(format!("unknown image format of {}", path.display()))
+        })?;
+        fs::write(path, render_qr_image_encoded(proof, format, encoding)?)?;
+    }
+    Ok(())
+}
+
+/// Splits the Base45 payload into the fewest parts, up to `MAX_PARTS`,
+/// whose QR codes are at most `max_version`. For proofs too large for
+/// one scannable code; `ProofQrCode::from_parts` joins the scanned parts.
+pub fn proof_qr_parts(proof: &ProofQrCode, max_version: i16) -> Result<Vec<String>, ZkError> {
+    let payload = proof.to_base45();
+    let max_width = Version::Normal(max_version).width();
+    for total in 1..=MAX_PARTS {
+        let parts = split_payload(&payload, total);
+        let fit = parts.iter().all(|part| match QrCode::new(part) {
+            Ok(code) => code.version().width() <= max_width,
+            Err(_) => false,
+        });
+        if fit {
+            return Ok(parts);
+        }
+    }
+    Err(ZkError::QrRender(format!(
+        "payload does not fit {} QR codes of version {}",
+        MAX_PARTS, max_version
+    )))
+}
+
+/// Renders the parts of `proof_qr_parts` as images, to be shown one
+/// after another or side by side.
+pub fn render_proof_qr_parts(
+    proof: &ProofQrCode,
+    max_version: i16,
+) -> Result<Vec<GrayImage>, ZkError> {
+    proof_qr_parts(proof, max_version)?
+        .iter()
+        .map(|part| {
+            let code = QrCode::new(part).map_err(|e| ZkError::QrRender(e.to_string()))?;
+            Ok(code.render::<Luma<u8>>().build())
+        })
+        .collect()
+}
+
+/// Reads the payload of the only QR code in the image. A code which is
+/// found but cannot be read is reported as a damaged payload.
+pub fn scan_qr_image(image: GrayImage) -> Result<String, ZkError> {
+    let mut prepared = rqrr::PreparedImage::prepare(image);
+    let grids = prepared.detect_grids();
+    match grids.len() {
+        0 => Err(ZkError::NoQrCode),
+        1 => grids[0]
+            .decode()
+            .map(|(_, content)| content)
+            .map_err(|_| ZkError::CorruptPayload),
+        n => Err(ZkError::MultipleQrCodes(n)),
+    }
+}
+
+/// Verifies the proof in the QR code of an image file, e.g. a photo of
+/// the user's phone. Any format known to the `image` crate is read, of
+/// at most `uri::MAX_IMAGE_SIDE` pixels on a side.
+pub fn verify_qr_image<P: AsRef<Path>>(
+    path: P,
+    chain: &PublicChain,
+) -> Result<VerifiedInputs, ZkError> {
+    let payload = scan_image(&fs::read(path)?, MAX_IMAGE_SIDE)?;
+    verify_proof(&parse_proof(&payload)?, chain)
+}
+
+/// PNG `data:` URI of the proof's QR code for an `<img>` tag.
+pub fn proof_qr_data_uri(proof: &ProofQrCode) -> Result<String, ZkError> {
+    proof_qr_data_uri_as(proof, ImageFormat::Png)
+}
+
+pub fn proof_qr_data_uri_as(proof: &ProofQrCode, format: ImageFormat) -> Result<String, ZkError> {
+    let image = render_qr_image(proof, format)?;
+    Ok(format!(
+        "data:{};base64,{}",
+        format.mime_type(),
+        base64::encode(&image)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{PublicQr, Relation};
+    use regex::Regex;
+    use std::str::FromStr;
+
+    fn proof() -> ProofQrCode {
+        ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 2923,
+                contract: vec![0x5d, 0xe5, 0x35, 0x37],
+                label: None,
+            },
+            proof: vec![7; 128],
+        }
+    }
+
+    #[test]
+    fn terminal_characters() {
+        let style = TerminalStyle {
+            dark: 'X',
+            light: '.',
+        };
+        let text = render_terminal(&proof(), style).unwrap();
+        assert!(text.contains('X'));
+        assert!(text.contains('.'));
+        assert!(!text.contains('\u{2b1b}'));
+
+        let ascii = render_terminal(&proof(), TerminalStyle::preset("ascii").unwrap()).unwrap();
+        assert!(ascii.contains('#'));
+    }
+
+    fn decode(image: image::GrayImage) -> ProofQrCode {
+        ProofQrCode::from_str(&scan_qr_image(image).unwrap()).unwrap()
+    }
+
+    /// Draws the modules of an SVG written by `qrcode`, which are
+    /// rectangles `M{x} {y}h{w}v{h}H{x}V{y}` of a single path.
+    fn rasterize_svg(svg: &str) -> image::GrayImage {
+        let size = Regex::new(r#"width="(\d+)" height="(\d+)""#)
+            .unwrap()
+            .captures(svg)
+            .unwrap();
+        let mut image = image::GrayImage::from_pixel(
+            size[1].parse().unwrap(),
+            size[2].parse().unwrap(),
+            Luma([255]),
+        );
+        let rect = Regex::new(r"M(\d+) (\d+)h(\d+)v(\d+)H\d+V\d+").unwrap();
+        for c in rect.captures_iter(svg) {
+            let n = |i| c[i].parse::<u32>().unwrap();
+            for x in n(1)..n(1) + n(3) {
+                for y in n(2)..n(2) + n(4) {
+                    image.put_pixel(x, y, Luma([0]));
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn formats_carry_same_payload() {
+        let png = render_qr_image(&proof(), ImageFormat::Png).unwrap();
+        let png = image::load_from_memory(&png).unwrap().to_luma8();
+        let svg = render_qr_image(&proof(), ImageFormat::Svg).unwrap();
+        let svg = rasterize_svg(&String::from_utf8(svg).unwrap());
+        assert_eq!(proof().canonical_bytes(), decode(png).canonical_bytes());
+        assert_eq!(proof().canonical_bytes(), decode(svg).canonical_bytes());
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            Some(ImageFormat::Jpeg),
+            ImageFormat::from_path(Path::new("proof-qr.JPG"))
+        );
+        assert_eq!(
+            Some(ImageFormat::Svg),
+            ImageFormat::from_path(Path::new("out.svg"))
+        );
+        assert_eq!(None, ImageFormat::from_path(Path::new("out")));
+    }
+
+    #[test]
+    fn png_data_uri() {
+        let uri = proof_qr_data_uri(&proof()).unwrap();
+        let prefix = "data:image/png;base64,";
+        assert!(uri.starts_with(prefix));
+        let png = base64::decode(&uri[prefix.len()..]).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert!(image.to_luma8().width() > 21);
+    }
+
+    #[test]
+    fn svg_data_uri() {
+        let uri = proof_qr_data_uri_as(&proof(), ImageFormat::Svg).unwrap();
+        let prefix = "data:image/svg+xml;base64,";
+        assert!(uri.starts_with(prefix));
+        let svg = String::from_utf8(base64::decode(&uri[prefix.len()..]).unwrap()).unwrap();
+        assert!(svg.contains("<svg"));
+    }
+
+    #[test]
+    fn base45_needs_smaller_version() {
+        let real = crate::zk::generate_proof(crate::testutil::bart_request()).unwrap();
+        let json = qr_code(&real, QrEncoding::Json).unwrap().version();
+        let base45 = qr_code(&real, QrEncoding::Base45).unwrap().version();
+        assert!(
+            base45.width() < json.width(),
+            "base45 {:?} json {:?}",
+            base45,
+            json
+        );
+
+        let png = render_qr_image_encoded(&real, ImageFormat::Png, QrEncoding::Base45).unwrap();
+        let decoded = decode(image::load_from_memory(&png).unwrap().to_luma8());
+        assert_eq!(real.canonical_bytes(), decoded.canonical_bytes());
+    }
+
+    #[test]
+    fn render_parsed_proof() {
+        let parsed = ProofQrCode::from_str(&proof().to_string()).unwrap();
+        let options = QrOptions::default()
+            .with_encoding(QrEncoding::Base45)
+            .with_formats(&[ImageFormat::Svg, ImageFormat::Png]);
+        let rendered = render_qr(&parsed, &options).unwrap();
+        assert_eq!(parsed.to_base45(), rendered.payload);
+        assert_eq!(None, rendered.image(ImageFormat::Jpeg));
+
+        let png = rendered.image(ImageFormat::Png).unwrap();
+        let png = image::load_from_memory(png).unwrap().to_luma8();
+        let svg = String::from_utf8(rendered.image(ImageFormat::Svg).unwrap().to_vec()).unwrap();
+        for image in vec![png, rasterize_svg(&svg)] {
+            assert_eq!(rendered.payload, scan_qr_image(image).unwrap());
+        }
+    }
+
+    #[test]
+    fn large_proof_in_parts() {
+        let mut large = proof();
+        large.proof = (0..2000).map(|i| (i * 7) as u8).collect();
+        let images = render_proof_qr_parts(&large, 10).unwrap();
+        assert!(images.len() > 1);
+
+        let parts: Vec<String> = images
+            .into_iter()
+            .rev()
+            .map(|image| scan_qr_image(image).unwrap())
+            .collect();
+        let joined = ProofQrCode::from_parts(&parts).unwrap();
+        assert_eq!(large.canonical_bytes(), joined.canonical_bytes());
+
+        assert_eq!(1, proof_qr_parts(&proof(), 10).unwrap().len());
+        assert!(proof_qr_parts(&large, 1).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+// JSON documents exchanged with the command line utilities and servers.
+//
+// Field elements are decimal strings and days are julian day numbers,
+// as in `prover-db.json`. Unknown fields are rejected so that a typo
+// does not silently fall back to a default.
+
+use crate::api::{
+    age_to_delta, check_label, supported_ranges, BoundaryPolicy, Private, ProofQrCode, PublicChain,
+    PublicQr, QrRequest, Relation, VerifiedInputs,
+};
+use crate::error::ZkError;
+use crate::field::{field_from_bytes, field_from_dec_str, field_to_dec_string};
+use crate::zk::{generate_prover_key, parse_proof, verify_proof};
+use serde::{Deserialize, Serialize};
+use zokrates_field::Bn128Field;
+
+/// The prover's secrets and certified public values, the content of
+/// `prover-db.json`. Other fields, e.g. the certifier's copy of the
+/// prover key, are allowed.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ProverDb {
+    /// Julian day of the birthday.
+    pub birthday: i32,
+    #[cfg_attr(feature = "schema", schemars(regex(pattern = r"^[0-9]+$")))]
+    pub nonce: String,
+    #[cfg_attr(feature = "schema", schemars(regex(pattern = r"^[0-9]+$")))]
+    pub contract: String,
+    #[cfg_attr(feature = "schema", schemars(regex(pattern = r"^[0-9]+$")))]
+    pub photo_hash: String,
+}
+
+/// Names of the documents `json_schema` describes.
+#[cfg(feature = "schema")]
+pub const SCHEMA_NAMES: [&str; 2] = ["prover-db", "proof"];
+
+/// JSON Schema of `prover-db.json` (`prover-db`) or of the JSON QR code
+/// payload (`proof`), generated from the serde types. Base45 payloads
+/// are not JSON and have no schema.
+#[cfg(feature = "schema")]
+pub fn json_schema(name: &str) -> Option<serde_json::Value> {
+    let schema = match name {
+        "prover-db" => schemars::schema_for!(ProverDb),
+        "proof" => schemars::schema_for!(crate::api::QrJson),
+        _ => return None,
+    };
+    serde_json::to_value(schema).ok()
+}
+
+impl ProverDb {
+    pub fn private(&self) -> Result<Private, ZkError> {
+        Ok(Private {
+            birthday: self.birthday,
+            nonce: field_from_dec_str(&self.nonce)?,
+        })
+    }
+
+    /// The prover key the certifier put on the chain for these secrets.
+    pub fn prover_key(&self) -> Result<Bn128Field, ZkError> {
+        let key = generate_prover_key(
+            &self.private()?,
+            &field_from_dec_str(&self.contract)?,
+            &field_from_dec_str(&self.photo_hash)?,
+        );
+        field_from_bytes(&key)
+    }
+}
+
+/// The proof file of `prove --proof-format json`: the QR code payload
+/// wrapped in a JSON document for tools which expect one.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProofFile {
+    /// The QR code payload.
+    pub qr: String,
+}
+
+impl ProofFile {
+    /// The payload of a proof file in either format. A bare JSON payload
+    /// has no `qr` field, so it is not mistaken for the wrapper.
+    pub fn payload(contents: &str) -> String {
+        match serde_json::from_str::<ProofFile>(contents) {
+            Ok(file) => file.qr.trim().to_string(),
+            Err(_) => contents.trim().to_string(),
+        }
+    }
+}
+
+/// Everything needed to generate a proof.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProveRequest {
+    pub birthday: i32,
+    pub nonce: String,
+    pub contract: String,
+    pub photo_hash: String,
+    pub today: i32,
+    pub relation: Relation,
+    pub age: i32,
+    /// Whether `older` holds on the birthday; strictly after it when
+    /// absent.
+    #[serde(default)]
+    pub boundary: BoundaryPolicy,
+    /// Shown to the verifier next to the proof, see `PublicQr::label`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl ProveRequest {
+    /// Validates the document and turns it into a request for
+    /// `generate_proof`. The prover key is derived from the secrets.
+    pub fn to_qr_request(&self) -> Result<QrRequest, ZkError> {
+        if self.age <= 0 {
+            return Err(ZkError::InvalidRequest(format!(
+                "age must be positive, got {}",
+                self.age
+            )));
+        }
+        if self.today <= self.birthday {
+            return Err(ZkError::InvalidRequest(String::from(
+                "today must be after the birthday",
+            )));
+        }
+        supported_ranges().check_request(self.birthday, self.today, self.age)?;
+        check_label(&self.label)?;
+        let private = Private {
+            birthday: self.birthday,
+            nonce: field_from_dec_str(&self.nonce)?,
+        };
+        let contract = field_from_dec_str(&self.contract)?;
+        let photo_hash = field_from_dec_str(&self.photo_hash)?;
+        let prover_key = generate_prover_key(&private, &contract, &photo_hash);
+        let relation = self.boundary.relation(self.relation);
+        Ok(QrRequest {
+            qr: PublicQr {
+                today: self.today,
+                relation,
+                delta: age_to_delta(self.birthday, self.age, relation),
+                contract,
+                label: self.label.clone(),
+            },
+            chain: PublicChain {
+                photo_hash,
+                prover_key,
+            },
+            private,
+        })
+    }
+}
+
+/// A proof and the chain record to verify it against, e.g. `prove
+/// --proof-format bundle` output handed to the verifier as one file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(deny_unknown_fields)]
+pub struct VerifyRequest {
+    /// The QR code payload.
+    pub qr: String,
+    pub photo_hash: String,
+    pub prover_key: String,
+}
+
+/// The name of a `VerifyRequest` when it travels as a file.
+pub type VerificationBundle = VerifyRequest;
+
+impl VerifyRequest {
+    /// Bundles the proof, in the JSON encoding, with its chain record.
+    pub fn bundle(qr: &ProofQrCode, chain: &PublicChain) -> Self {
+        VerifyRequest {
+            qr: qr.to_string(),
+            photo_hash: field_to_dec_string(&chain.photo_hash),
+            prover_key: field_to_dec_string(&chain.prover_key),
+        }
+    }
+
+    pub fn parse(&self) -> Result<(ProofQrCode, PublicChain), ZkError> {
+        let qr = parse_proof(&self.qr)?;
+        let chain = PublicChain::from_strs(&self.photo_hash, &self.prover_key)?;
+        Ok((qr, chain))
+    }
+}
+
+/// Verifies the proof of a bundle against the chain record in it.
+pub fn verify_bundle(bundle: &VerificationBundle) -> Result<VerifiedInputs, ZkError> {
+    let (qr, chain) = bundle.parse()?;
+    verify_proof(&qr, &chain)
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct VerifyResponse {
+    pub valid: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl VerifyResponse {
+    pub fn of<T>(result: &Result<T, ZkError>) -> Self {
+        VerifyResponse {
+            valid: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::naive_date_to_jd;
+    use chrono::NaiveDate;
+    use zokrates_field::Field;
+
+    fn prove_json() -> &'static str {
+        r#"{"birthday": 2451711, "nonce": "7999", "contract": "4", "photo_hash": "3",
+            "today": 2459231, "relation": "older", "age": 18}"#
+    }
+
+    #[test]
+    fn prover_db_key() {
+        let pdb: ProverDb = serde_json::from_str(
+            r#"{"birthday": 2001, "nonce": "7999", "contract": "4", "photo_hash": "3"}"#,
+        )
+        .unwrap();
+        let m1 = Bn128Field::try_from_dec_str(
+            "10046037004840239707202533642544953578314335199439499999912878067091298310375",
+        )
+        .unwrap();
+        assert_eq!(m1, pdb.prover_key().unwrap());
+
+        let bad = ProverDb {
+            contract: String::from("0x04"),
+            ..pdb
+        };
+        assert!(bad.prover_key().is_err());
+    }
+
+    #[test]
+    fn parse_prove_request() {
+        let p: ProveRequest = serde_json::from_str(prove_json()).unwrap();
+        assert_eq!(Relation::Older, p.relation);
+        let rq = p.to_qr_request().unwrap();
+        assert!(rq.is_delta_consistent());
+        assert!(rq.is_relation_valid());
+        assert_eq!(BoundaryPolicy::StrictlyAfter, p.boundary);
+
+        let json =
+            prove_json().replace("\"age\": 18", "\"age\": 18, \"boundary\": \"on_or_after\"");
+        let p: ProveRequest = serde_json::from_str(&json).unwrap();
+        let rq = p.to_qr_request().unwrap();
+        assert_eq!(Relation::OlderOrEqual, rq.qr.relation);
+        assert!(rq.is_delta_consistent());
+    }
+
+    #[test]
+    fn reject_unknown_field() {
+        let json = prove_json().replace("\"age\"", "\"agee\"");
+        assert!(serde_json::from_str::<ProveRequest>(&json).is_err());
+        let json = prove_json().replace("older", "elder");
+        assert!(serde_json::from_str::<ProveRequest>(&json).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_values() {
+        let mut p: ProveRequest = serde_json::from_str(prove_json()).unwrap();
+        p.age = 0;
+        match p.to_qr_request() {
+            Err(ZkError::InvalidRequest(_)) => {}
+            other => panic!("expected InvalidRequest, got {:?}", other),
+        }
+        p.age = 18;
+        p.nonce = String::from("0x1f3f");
+        match p.to_qr_request() {
+            Err(ZkError::InvalidDecimal(_)) => {}
+            other => panic!("expected InvalidDecimal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn proof_file_formats() {
+        let payload = ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 6576,
+                contract: field_from_dec_str("4").unwrap(),
+                label: None,
+            },
+            proof: vec![7; 128],
+        }
+        .to_string();
+        let wrapped = serde_json::to_string(&ProofFile {
+            qr: payload.clone(),
+        })
+        .unwrap();
+        assert_eq!(payload, ProofFile::payload(&wrapped));
+        assert_eq!(payload, ProofFile::payload(&format!("{}\n", payload)));
+    }
+
+    #[test]
+    fn reject_out_of_range_days() {
+        let ranges = supported_ranges();
+        let p: ProveRequest = serde_json::from_str(prove_json()).unwrap();
+        let cases = vec![
+            (
+                "birthday",
+                ProveRequest {
+                    birthday: ranges.min_day - 1,
+                    ..p.clone()
+                },
+            ),
+            (
+                "today",
+                ProveRequest {
+                    today: ranges.max_day + 1,
+                    ..p.clone()
+                },
+            ),
+            (
+                "age",
+                ProveRequest {
+                    age: ranges.max_age + 1,
+                    ..p.clone()
+                },
+            ),
+        ];
+        for (name, request) in cases {
+            match request.to_qr_request() {
+                Err(ZkError::InvalidRequest(e)) => assert!(e.starts_with(name), "{}", e),
+                other => panic!("expected InvalidRequest, got {:?}", other),
+            }
+        }
+
+        let earliest = ProveRequest {
+            birthday: ranges.min_day,
+            ..p.clone()
+        };
+        assert!(earliest.to_qr_request().is_ok());
+        let oldest = ProveRequest {
+            birthday: naive_date_to_jd(NaiveDate::from_ymd(ranges.min_birth_year, 1, 1)),
+            today: ranges.max_day,
+            age: ranges.max_age,
+            ..p
+        };
+        assert!(oldest.to_qr_request().is_ok());
+    }
+}
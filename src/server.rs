@@ -0,0 +1,531 @@
+// HTTP verification service.
+//
+// The handlers are plain functions from a request to a `Response` so
+// that they can be tested without a socket; `serve` connects them to
+// tiny_http.
+//
+// `/metrics` exposes, in the Prometheus text format:
+//
+// * `harla_verifications_total{relation, outcome}` - counter of `/verify`
+//   calls; `outcome` is `valid`, `invalid` or `malformed` and `relation`
+//   is `older`, `younger`, `older_or_equal`, `younger_or_equal`, or
+//   `unknown` when the request cannot be parsed,
+// * `harla_verification_seconds{relation}` - histogram of the time spent
+//   verifying a parsed proof.
+//
+// These names are part of the interface of the service.
+
+use crate::api::Relation;
+use crate::error::ZkError;
+use crate::request::{VerifyRequest, VerifyResponse};
+use crate::zk::{verify_proof, warm_up};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use utoipa::OpenApi;
+
+/// An HTTP response before it is written to the socket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    /// `{"error": message}`.
+    fn error(status: u16, message: &str) -> Self {
+        Response::json(status, serde_json::json!({ "error": message }).to_string())
+    }
+
+    fn not_found() -> Self {
+        Response {
+            status: 404,
+            content_type: "text/plain",
+            body: String::from("not found"),
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(verify), components(schemas(VerifyRequest, VerifyResponse)))]
+struct ApiDoc;
+
+/// OpenAPI 3 document of the service, served at `/openapi.json`.
+pub fn openapi_json() -> String {
+    ApiDoc::openapi().to_pretty_json().unwrap()
+}
+
+/// Verifies a proof against a chain record. An invalid proof is a
+/// successful request with `valid: false`; only a document which cannot
+/// be parsed is a client error.
+#[utoipa::path(
+    post,
+    path = "/verify",
+    request_body = VerifyRequest,
+    responses(
+        (status = 200, description = "The proof was checked", body = VerifyResponse),
+        (status = 400, description = "The request cannot be parsed", body = VerifyResponse)
+    )
+)]
+fn verify(metrics: &Metrics, body: &str) -> Response {
+    let request = serde_json::from_str::<VerifyRequest>(body)
+        .map_err(ZkError::from)
+        .and_then(|r| r.parse());
+    let (status, result) = match request {
+        Ok((qr, chain)) => {
+            let relation = relation_label(qr.public.relation);
+            let timer = metrics.seconds.with_label_values(&[relation]).start_timer();
+            let result = verify_proof(&qr, &chain);
+            timer.observe_duration();
+            metrics.count(relation, &result);
+            (200, result)
+        }
+        Err(e) => {
+            let result = Err(e);
+            metrics.count("unknown", &result);
+            (400, result)
+        }
+    };
+    Response::json(
+        status,
+        serde_json::to_string(&VerifyResponse::of(&result)).unwrap(),
+    )
+}
+
+fn relation_label(relation: Relation) -> &'static str {
+    match relation {
+        Relation::Older => "older",
+        Relation::Younger => "younger",
+        Relation::YoungerOrEqual => "younger_or_equal",
+        Relation::OlderOrEqual => "older_or_equal",
+    }
+}
+
+/// Prometheus metrics of one service, see the top of this file.
+pub struct Metrics {
+    registry: Registry,
+    verifications: IntCounterVec,
+    seconds: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let verifications = IntCounterVec::new(
+            Opts::new("harla_verifications_total", "Proofs submitted to /verify."),
+            &["relation", "outcome"],
+        )
+        .unwrap();
+        let seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "harla_verification_seconds",
+                "Time spent verifying a proof.",
+            ),
+            &["relation"],
+        )
+        .unwrap();
+        let registry = Registry::new();
+        registry.register(Box::new(verifications.clone())).unwrap();
+        registry.register(Box::new(seconds.clone())).unwrap();
+        Metrics {
+            registry,
+            verifications,
+            seconds,
+        }
+    }
+
+    fn count<T>(&self, relation: &str, result: &Result<T, ZkError>) {
+        let outcome = match result {
+            Ok(_) => "valid",
+            Err(ZkError::InvalidProof) => "invalid",
+            Err(_) => "malformed",
+        };
+        self.verifications
+            .with_label_values(&[relation, outcome])
+            .inc();
+    }
+
+    /// Current value of `harla_verifications_total`.
+    pub fn verifications(&self, relation: &str, outcome: &str) -> u64 {
+        self.verifications
+            .with_label_values(&[relation, outcome])
+            .get()
+    }
+
+    fn render(&self) -> Response {
+        let mut text = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut text)
+            .unwrap();
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: String::from_utf8(text).unwrap(),
+        }
+    }
+}
+
+/// How `serve` tells clients apart for rate limiting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientKey {
+    /// The source IP address of the connection.
+    SourceIp,
+
+    /// The last value of the header `name`, e.g. `X-Forwarded-For`, on
+    /// connections from one of `proxies`. Anyone else could send a new
+    /// value with each request, so their requests are keyed by the
+    /// source IP, as are requests without the header.
+    Header { name: String, proxies: Vec<IpAddr> },
+}
+
+impl ClientKey {
+    /// The key of a request from `source` carrying `header`, the value of
+    /// the header named by `ClientKey::Header` if there is one.
+    fn of(&self, source: Option<IpAddr>, header: Option<&str>) -> String {
+        if let ClientKey::Header { proxies, .. } = self {
+            let last = header.and_then(|h| h.rsplit(',').next()).map(str::trim);
+            match (source, last) {
+                (Some(ip), Some(client)) if proxies.contains(&ip) && !client.is_empty() => {
+                    return client.to_string()
+                }
+                _ => {}
+            }
+        }
+        source.map(|ip| ip.to_string()).unwrap_or_default()
+    }
+}
+
+/// Fixed window limit of requests per client and minute.
+pub struct RateLimiter {
+    per_minute: u32,
+    windows: Mutex<Windows>,
+}
+
+/// The window of each client and when the ended ones were last dropped.
+struct Windows {
+    clients: HashMap<String, (Instant, u32)>,
+    swept: Option<Instant>,
+}
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            windows: Mutex::new(Windows {
+                clients: HashMap::new(),
+                swept: None,
+            }),
+        }
+    }
+
+    /// Counts a request of `client` at `now`; false when it is over the
+    /// limit. Once per window the windows which ended are dropped, so
+    /// only clients of the last two windows are kept.
+    pub fn allow(&self, client: &str, now: Instant) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+        if windows
+            .swept
+            .map_or(true, |swept| now.duration_since(swept) >= WINDOW)
+        {
+            windows
+                .clients
+                .retain(|_, (start, _)| now.duration_since(*start) < WINDOW);
+            windows.swept = Some(now);
+        }
+        let window = windows
+            .clients
+            .entry(client.to_string())
+            .or_insert((now, 0));
+        if now.duration_since(window.0) >= WINDOW {
+            *window = (now, 0);
+        }
+        window.1 += 1;
+        window.1 <= self.per_minute
+    }
+}
+
+/// State shared by the handlers.
+pub struct Service {
+    ready: AtomicBool,
+    metrics: Metrics,
+    limiter: Option<RateLimiter>,
+}
+
+impl Service {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Service {
+            ready: AtomicBool::new(false),
+            metrics: Metrics::new(),
+            limiter: None,
+        }
+    }
+
+    /// Answers 429 to clients sending more than `per_minute` requests.
+    /// `/healthz` and `/metrics` are not limited.
+    pub fn with_rate_limit(mut self, per_minute: u32) -> Self {
+        self.limiter = Some(RateLimiter::new(per_minute));
+        self
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Checks the artifacts and parses the keys; `/healthz` reports
+    /// ready once this succeeded.
+    pub fn warm_up(&self) -> Result<(), ZkError> {
+        warm_up()?;
+        self.ready.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn healthz(&self) -> Response {
+        if self.ready.load(Ordering::SeqCst) {
+            Response::json(200, String::from("{\"status\": \"ready\"}"))
+        } else {
+            Response::json(503, String::from("{\"status\": \"warming up\"}"))
+        }
+    }
+
+    /// Routes a request to its handler.
+    pub fn handle(&self, method: &str, path: &str, body: &str) -> Response {
+        match (method, path) {
+            ("POST", "/verify") => verify(&self.metrics, body),
+            ("GET", "/metrics") => self.metrics.render(),
+            ("GET", "/openapi.json") => Response::json(200, openapi_json()),
+            ("GET", "/healthz") => self.healthz(),
+            _ => Response::not_found(),
+        }
+    }
+
+    /// Like `handle` but subject to the rate limit of `client`.
+    pub fn handle_from(
+        &self,
+        client: &str,
+        now: Instant,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> Response {
+        let exempt = method == "GET" && (path == "/healthz" || path == "/metrics");
+        match &self.limiter {
+            Some(limiter) if !exempt && !limiter.allow(client, now) => Response {
+                status: 429,
+                content_type: "text/plain",
+                body: String::from("too many requests"),
+            },
+            _ => self.handle(method, path, body),
+        }
+    }
+}
+
+fn client_of(request: &tiny_http::Request, key: &ClientKey) -> String {
+    let header = match key {
+        ClientKey::Header { name, .. } => request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(name.as_str()))
+            .map(|h| h.value.as_str()),
+        ClientKey::SourceIp => None,
+    };
+    key.of(request.remote_addr().map(|a| a.ip()), header)
+}
+
+/// Largest request body read. A verification request is a few
+/// kilobytes at most.
+pub const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// Reads a request body of at most `MAX_BODY_BYTES`, or the response
+/// refusing it.
+fn read_body<R: Read>(reader: R) -> Result<String, Response> {
+    let mut body = String::new();
+    match reader.take(MAX_BODY_BYTES + 1).read_to_string(&mut body) {
+        Ok(n) if n as u64 > MAX_BODY_BYTES => Err(Response::error(413, "request body too large")),
+        Ok(_) => Ok(body),
+        Err(e) => Err(Response::error(400, &e.to_string())),
+    }
+}
+
+/// Serves requests on `addr`, e.g. `127.0.0.1:8080`, until the process
+/// ends. The service warms up in the background; until then `/healthz`
+/// answers 503.
+pub fn serve(addr: &str, service: Service, client_key: ClientKey) -> Result<(), ZkError> {
+    let server = tiny_http::Server::http(addr).map_err(|e| ZkError::Server(e.to_string()))?;
+    let service = Arc::new(service);
+    let warming = Arc::clone(&service);
+    thread::spawn(move || {
+        if let Err(e) = warming.warm_up() {
+            eprintln!("warm up failed: {}", e);
+        }
+    });
+
+    for mut request in server.incoming_requests() {
+        let response = match read_body(request.as_reader()) {
+            Ok(body) => service.handle_from(
+                &client_of(&request, &client_key),
+                Instant::now(),
+                request.method().as_str(),
+                request.url(),
+                &body,
+            ),
+            Err(response) => response,
+        };
+        let header = tiny_http::Header::from_bytes("Content-Type", response.content_type).unwrap();
+        let http = tiny_http::Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(header);
+        // A client which went away is not an error of the server.
+        let _ = request.respond(http);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::field::field_to_dec_string;
+    use serde_json::Value;
+
+    fn handle(method: &str, path: &str, body: &str) -> Response {
+        Service::new().handle(method, path, body)
+    }
+
+    /// The metric labels are the names of the relations in requests.
+    #[test]
+    fn relation_labels() {
+        for code in 0..4 {
+            let relation = Relation::from_code(code).unwrap();
+            assert_eq!(
+                Value::from(relation_label(relation)),
+                serde_json::to_value(relation).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn spec_describes_verify() {
+        let spec: Value = serde_json::from_str(&handle("GET", "/openapi.json", "").body).unwrap();
+        assert!(spec["paths"]["/verify"]["post"].is_object());
+        let properties = &spec["components"]["schemas"]["VerifyRequest"]["properties"];
+        for field in &["qr", "photo_hash", "prover_key"] {
+            assert!(properties[field].is_object(), "missing {}", field);
+        }
+    }
+
+    #[test]
+    fn bad_request() {
+        let response = handle("POST", "/verify", "{\"qr\": 1}");
+        assert_eq!(400, response.status);
+        let body: VerifyResponse = serde_json::from_str(&response.body).unwrap();
+        assert!(!body.valid);
+    }
+
+    #[test]
+    fn healthz_after_warm_up() {
+        let service = Service::new();
+        assert_eq!(503, service.handle("GET", "/healthz", "").status);
+        service.warm_up().unwrap();
+        assert_eq!(200, service.handle("GET", "/healthz", "").status);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn count_invalid_verification() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let mut qr = crate::zk::generate_proof(rq).unwrap();
+        qr.public.delta += 365;
+        let body = serde_json::to_string(&VerifyRequest {
+            qr: qr.to_string(),
+            photo_hash: field_to_dec_string(&chain.photo_hash),
+            prover_key: field_to_dec_string(&chain.prover_key),
+        })
+        .unwrap();
+
+        let service = Service::new();
+        assert_eq!(0, service.metrics().verifications("older", "invalid"));
+        assert_eq!(200, service.handle("POST", "/verify", &body).status);
+        assert_eq!(1, service.metrics().verifications("older", "invalid"));
+        assert_eq!(0, service.metrics().verifications("older", "valid"));
+
+        let text = service.handle("GET", "/metrics", "").body;
+        assert!(text.contains(r#"harla_verifications_total{outcome="invalid",relation="older"} 1"#));
+        assert!(text.contains("harla_verification_seconds_count"));
+    }
+
+    #[test]
+    fn rate_limit() {
+        let service = Service::new().with_rate_limit(3);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(404, service.handle_from("a", start, "GET", "/", "").status);
+        }
+        assert_eq!(429, service.handle_from("a", start, "GET", "/", "").status);
+        assert_eq!(404, service.handle_from("b", start, "GET", "/", "").status);
+        assert_eq!(
+            503,
+            service
+                .handle_from("a", start, "GET", "/healthz", "")
+                .status
+        );
+
+        let later = start + Duration::from_secs(61);
+        assert_eq!(404, service.handle_from("a", later, "GET", "/", "").status);
+        let limiter = service.limiter.as_ref().unwrap();
+        assert_eq!(1, limiter.windows.lock().unwrap().clients.len());
+    }
+
+    #[test]
+    fn client_header_from_proxies_only() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.7".parse().unwrap();
+        let key = ClientKey::Header {
+            name: String::from("X-Forwarded-For"),
+            proxies: vec![proxy],
+        };
+        let forwarded = Some("198.51.100.1, 203.0.113.9");
+        assert_eq!("203.0.113.9", key.of(Some(proxy), forwarded));
+        assert_eq!("10.0.0.1", key.of(Some(proxy), None));
+        assert_eq!("192.0.2.7", key.of(Some(other), forwarded));
+        assert_eq!("192.0.2.7", ClientKey::SourceIp.of(Some(other), forwarded));
+    }
+
+    #[test]
+    fn body_limit() {
+        let body = vec![b' '; MAX_BODY_BYTES as usize];
+        assert_eq!(body.len(), read_body(&body[..]).unwrap().len());
+
+        let large = vec![b' '; MAX_BODY_BYTES as usize + 1];
+        assert_eq!(413, read_body(&large[..]).unwrap_err().status);
+
+        let refused = read_body(&b"\"\xff"[..]).unwrap_err();
+        assert_eq!(400, refused.status);
+        let error: Value = serde_json::from_str(&refused.body).unwrap();
+        assert!(error["error"].is_string());
+    }
+
+    #[test]
+    fn unknown_path() {
+        assert_eq!(404, handle("GET", "/", "").status);
+        assert_eq!(404, handle("GET", "/verify", "").status);
+    }
+}
@@ -0,0 +1,88 @@
+// Device signatures over proofs.
+//
+// A trusted prover device can sign its proofs so that a verifier can
+// later show which device produced them. The signature covers the
+// canonical bytes of the proof and is checked before the zk proof.
+
+use crate::api::{ProofQrCode, PublicChain, VerifiedInputs};
+use crate::error::ZkError;
+use crate::zk::verify_proof;
+use k256::ecdsa::signature::{Signer, Verifier};
+use k256::ecdsa::{Signature, SigningKey, VerifyingKey};
+
+/// A proof together with an ECDSA (secp256k1) signature of its
+/// `canonical_bytes`.
+#[derive(Debug, Clone)]
+pub struct SignedProofQrCode {
+    pub proof: ProofQrCode,
+
+    /// Fixed size 64 byte encoding of the signature, `r` followed by `s`.
+    pub signature: Vec<u8>,
+}
+
+pub fn sign_proof(proof: ProofQrCode, signing_key: &SigningKey) -> SignedProofQrCode {
+    let signature: Signature = signing_key.sign(&proof.canonical_bytes());
+    SignedProofQrCode {
+        proof,
+        signature: signature.to_bytes().to_vec(),
+    }
+}
+
+/// Checks the device signature and then the proof itself.
+pub fn verify_signed_proof(
+    signed: &SignedProofQrCode,
+    verifying_key: &VerifyingKey,
+    chain: &PublicChain,
+) -> Result<VerifiedInputs, ZkError> {
+    let signature =
+        Signature::from_slice(&signed.signature).map_err(|_| ZkError::InvalidSignature)?;
+    verifying_key
+        .verify(&signed.proof.canonical_bytes(), &signature)
+        .map_err(|_| ZkError::InvalidSignature)?;
+    verify_proof(&signed.proof, chain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::bart_request;
+    use crate::zk::generate_proof;
+
+    fn device_key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn signed_proof_verifies() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let signed = sign_proof(generate_proof(rq).unwrap(), &device_key());
+        assert_eq!(64, signed.signature.len());
+        assert!(verify_signed_proof(&signed, device_key().verifying_key(), &chain).is_ok());
+    }
+
+    #[test]
+    fn tampered_payload() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let mut signed = sign_proof(generate_proof(rq).unwrap(), &device_key());
+        signed.proof.public.delta += 1;
+        match verify_signed_proof(&signed, device_key().verifying_key(), &chain) {
+            Err(ZkError::InvalidSignature) => {}
+            other => panic!("expected InvalidSignature, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_device() {
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let signed = sign_proof(generate_proof(rq).unwrap(), &device_key());
+        let other = SigningKey::from_slice(&[8; 32]).unwrap();
+        match verify_signed_proof(&signed, other.verifying_key(), &chain) {
+            Err(ZkError::InvalidSignature) => {}
+            other => panic!("expected InvalidSignature, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Durable log of verified proofs.
+//
+// A verifier keeps every proof it has seen so that the log can be
+// audited and a proof presented twice can be refused.
+//
+// A replay is recognized by what the proof states, not by its bytes:
+// anyone can re-randomize a Groth16 proof into new bytes which verify
+// just the same. The statement is claimed by one INSERT into a table
+// with UNIQUE keys, so of two verifiers sharing the database only one
+// accepts it.
+
+use crate::api::{naive_date_to_jd, ProofQrCode, PublicChain, VerifiedInputs};
+use crate::error::ZkError;
+use crate::field::field_to_bytes;
+use crate::zk::verify_proof;
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// What became of a proof presented to the verifier.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Outcome {
+    Valid,
+    Invalid,
+    Replayed,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Valid => "valid",
+            Outcome::Invalid => "invalid",
+            Outcome::Replayed => "replayed",
+        }
+    }
+}
+
+fn store_error(e: rusqlite::Error) -> ZkError {
+    ZkError::Store(e.to_string())
+}
+
+pub struct ProofStore {
+    connection: Connection,
+}
+
+impl ProofStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ZkError> {
+        ProofStore::with_connection(Connection::open(path).map_err(store_error)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, ZkError> {
+        ProofStore::with_connection(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, ZkError> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS proofs (
+                     fingerprint BLOB NOT NULL,
+                     nullifier BLOB,
+                     outcome TEXT NOT NULL,
+                     timestamp INTEGER NOT NULL
+                 );
+                 CREATE INDEX IF NOT EXISTS proofs_fingerprint ON proofs (fingerprint);
+                 CREATE INDEX IF NOT EXISTS proofs_nullifier ON proofs (nullifier);
+                 CREATE TABLE IF NOT EXISTS claims (
+                     statement BLOB NOT NULL UNIQUE,
+                     nullifier BLOB UNIQUE,
+                     timestamp INTEGER NOT NULL
+                 );",
+            )
+            .map_err(store_error)?;
+        Ok(ProofStore { connection })
+    }
+
+    /// Appends an entry to the log. `timestamp` is in seconds since the
+    /// unix epoch.
+    pub fn record(
+        &self,
+        fingerprint: &[u8],
+        nullifier: Option<&[u8]>,
+        outcome: Outcome,
+        timestamp: i64,
+    ) -> Result<(), ZkError> {
+        self.connection
+            .execute(
+                "INSERT INTO proofs (fingerprint, nullifier, outcome, timestamp)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![fingerprint, nullifier, outcome.as_str(), timestamp],
+            )
+            .map_err(store_error)?;
+        Ok(())
+    }
+
+    /// Claims a statement key and the optional nullifier in one atomic
+    /// step. False if either was claimed before, in which case nothing
+    /// is stored.
+    pub fn claim(
+        &self,
+        statement: &[u8],
+        nullifier: Option<&[u8]>,
+        timestamp: i64,
+    ) -> Result<bool, ZkError> {
+        let inserted = self
+            .connection
+            .execute(
+                "INSERT OR IGNORE INTO claims (statement, nullifier, timestamp)
+                 VALUES (?1, ?2, ?3)",
+                params![statement, nullifier, timestamp],
+            )
+            .map_err(store_error)?;
+        Ok(inserted == 1)
+    }
+
+    /// True when a logged proof has this fingerprint or nullifier, or a
+    /// claim has this statement key or nullifier.
+    pub fn was_seen(&self, key: &[u8]) -> Result<bool, ZkError> {
+        self.connection
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM proofs WHERE fingerprint = ?1 OR nullifier = ?1)
+                     OR EXISTS (SELECT 1 FROM claims WHERE statement = ?1 OR nullifier = ?1)",
+                params![key],
+                |row| row.get(0),
+            )
+            .map_err(store_error)
+    }
+}
+
+/// Identifies what a verified proof states: the day, relation, delta and
+/// contract together with the chain record of the prover. Proofs with
+/// other bytes but the same statement have the same key.
+pub fn statement_key(inputs: &VerifiedInputs) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"harla statement 1");
+    hasher.update(&naive_date_to_jd(inputs.today).to_be_bytes());
+    hasher.update(&[inputs.relation.to_code()]);
+    hasher.update(&inputs.delta.to_be_bytes());
+    for field in &[&inputs.contract, &inputs.photo_hash, &inputs.prover_key] {
+        hasher.update(&field_to_bytes(field));
+    }
+    let mut key = [0; 32];
+    key.copy_from_slice(&hasher.finalize());
+    key
+}
+
+/// Verifies a proof whose statement must not have been presented before
+/// and logs the attempt. A valid proof whose `statement_key` or optional
+/// `nullifier` was claimed already is refused with `Replayed`. So the
+/// same prover cannot show the same claim to the same contract twice on
+/// one day, however the proof is re-randomized. An invalid proof claims
+/// nothing.
+pub fn verify_proof_once(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    nullifier: Option<&[u8]>,
+    store: &ProofStore,
+    timestamp: i64,
+) -> Result<VerifiedInputs, ZkError> {
+    let result = verify_proof(qr, chain);
+    let outcome = match &result {
+        Ok(inputs) if store.claim(&statement_key(inputs), nullifier, timestamp)? => Outcome::Valid,
+        Ok(_) => Outcome::Replayed,
+        Err(_) => Outcome::Invalid,
+    };
+    store.record(&qr.fingerprint(), nullifier, outcome, timestamp)?;
+    match outcome {
+        Outcome::Replayed => Err(ZkError::Replayed),
+        _ => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::testutil::bart_request;
+    #[cfg(not(feature = "no-embedded-vk"))]
+    use crate::zk::generate_proof;
+
+    #[test]
+    fn record_and_query() {
+        let store = ProofStore::open_in_memory().unwrap();
+        assert!(!store.was_seen(&[1; 32]).unwrap());
+        store
+            .record(&[1; 32], Some(&[2; 32]), Outcome::Valid, 1610755200)
+            .unwrap();
+        assert!(store.was_seen(&[1; 32]).unwrap());
+        assert!(store.was_seen(&[2; 32]).unwrap());
+        assert!(!store.was_seen(&[3; 32]).unwrap());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn second_verification_is_replay() {
+        let store = ProofStore::open_in_memory().unwrap();
+        let rq = bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let mut invalid = qr.clone();
+        invalid.proof.reverse();
+        assert!(verify_proof_once(&invalid, &chain, None, &store, 1610755140).is_err());
+        assert!(verify_proof_once(&qr, &chain, None, &store, 1610755200).is_ok());
+
+        // Another proof of the same statement has other bytes.
+        let again = generate_proof(bart_request()).unwrap();
+        assert_ne!(qr.fingerprint(), again.fingerprint());
+        for qr in &[qr, again] {
+            match verify_proof_once(qr, &chain, None, &store, 1610755260) {
+                Err(ZkError::Replayed) => {}
+                other => panic!("expected Replayed, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn one_claim_across_connections() {
+        let path = std::env::temp_dir().join(format!("harla-store-{}.db", std::process::id()));
+        let first = ProofStore::open(&path).unwrap();
+        let second = ProofStore::open(&path).unwrap();
+        let claims = [
+            first.claim(&[1; 32], None, 1610755200).unwrap(),
+            second.claim(&[1; 32], None, 1610755200).unwrap(),
+            second.claim(&[2; 32], Some(&[3; 32]), 1610755200).unwrap(),
+            first.claim(&[4; 32], Some(&[3; 32]), 1610755200).unwrap(),
+        ];
+        drop((first, second));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!([true, false, true, false], claims);
+    }
+}
@@ -0,0 +1,37 @@
+// Known good inputs shared by the tests.
+
+use crate::api::{Private, PublicChain, PublicQr, QrRequest, Relation};
+use crate::field::field_to_bytes;
+use crate::zk::generate_prover_key;
+use zokrates_field::{Bn128Field, Field};
+
+pub fn bn128(s: &str) -> Bn128Field {
+    Bn128Field::try_from_dec_str(s).unwrap()
+}
+
+/// Bart, born 2010-02-22, proves that he is older than 8 on 2021-01-16.
+pub fn bart_request() -> QrRequest {
+    let private = Private {
+        birthday: 2455250,
+        nonce: field_to_bytes(&bn128(
+            "49562589987336948678371811862197518411894129330930510829597277386215323558419",
+        )),
+    };
+    let contract = field_to_bytes(&bn128("291478163806436998532036252836091753082125673821"));
+    let photo_hash = field_to_bytes(&bn128("70573743172686605492515124569"));
+    let prover_key = generate_prover_key(&private, &contract, &photo_hash);
+    QrRequest {
+        qr: PublicQr {
+            today: 2459231,
+            relation: Relation::Older,
+            delta: 2923,
+            contract,
+            label: None,
+        },
+        chain: PublicChain {
+            photo_hash,
+            prover_key,
+        },
+        private,
+    }
+}
@@ -0,0 +1,373 @@
+// Proofs received as links rather than files: `data:` URIs embedded in
+// a page, `file:` URIs from a file picker and, with the `net` feature,
+// `http:` and `https:` downloads.
+//
+// A link may come from whoever presents the proof, so every way of
+// reading it is bounded: files and downloads by `MAX_PROOF_FILE_BYTES`,
+// QR code images by `MAX_IMAGE_SIDE` before they are decoded. Downloads
+// do not follow redirects, which could lead from a public address to an
+// internal one.
+
+use crate::api::ProofQrCode;
+use crate::error::ZkError;
+use crate::request::ProofFile;
+use crate::zk::parse_proof;
+use std::fs::File;
+use std::io::Read;
+#[cfg(feature = "net")]
+use std::time::Duration;
+
+/// Largest proof file read from a `file:` URI or the network. A payload
+/// is well below a kilobyte, so anything near the limit is not a proof.
+pub const MAX_PROOF_FILE_BYTES: u64 = 64 * 1024;
+
+/// Longest side in pixels of a QR code image, in a `data:` URI or read
+/// by `qr::verify_qr_image`.
+#[cfg(feature = "qr")]
+pub const MAX_IMAGE_SIDE: u32 = 4096;
+
+/// Time limit of a whole download, connecting included.
+#[cfg(feature = "net")]
+pub const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl ProofQrCode {
+    /// Reads the proof a URI points to. The resource holds the payload
+    /// in either format of a proof file; a `data:` URI may hold an image
+    /// of the QR code instead, which needs the `qr` feature.
+    pub fn from_uri(uri: &str) -> Result<ProofQrCode, ZkError> {
+        let colon = uri
+            .find(':')
+            .ok_or_else(|| ZkError::InvalidUri(String::from("no scheme")))?;
+        let rest = &uri[colon + 1..];
+        let payload = match uri[..colon].to_ascii_lowercase().as_str() {
+            "data" => data_payload(rest)?,
+            "file" => {
+                let file = File::open(file_path(rest)?)?;
+                let body = read_limited(file, MAX_PROOF_FILE_BYTES)?
+                    .ok_or_else(|| ZkError::InvalidUri(too_large(MAX_PROOF_FILE_BYTES)))?;
+                ProofFile::payload(&String::from_utf8_lossy(&body))
+            }
+            #[cfg(feature = "net")]
+            "http" | "https" => {
+                let body = download(uri, MAX_PROOF_FILE_BYTES, DOWNLOAD_TIMEOUT)?;
+                ProofFile::payload(&String::from_utf8_lossy(&body))
+            }
+            #[cfg(not(feature = "net"))]
+            "http" | "https" => {
+                return Err(ZkError::InvalidUri(String::from(
+                    "downloads need the net feature",
+                )))
+            }
+            scheme => {
+                return Err(ZkError::InvalidUri(format!(
+                    "unsupported scheme {}",
+                    scheme
+                )))
+            }
+        };
+        parse_proof(&payload)
+    }
+}
+
+/// Decodes `[<media type>][;base64],<data>`.
+fn data_payload(rest: &str) -> Result<String, ZkError> {
+    let comma = rest
+        .find(',')
+        .ok_or_else(|| ZkError::InvalidUri(String::from("data URI without a comma")))?;
+    let (meta, data) = (&rest[..comma], &rest[comma + 1..]);
+    let (meta, is_base64) = match meta.strip_suffix(";base64") {
+        Some(meta) => (meta, true),
+        None => (meta, false),
+    };
+    let bytes = if is_base64 {
+        base64::decode(data).map_err(|e| ZkError::InvalidUri(e.to_string()))?
+    } else {
+        percent_decode(data)?
+    };
+
+    let media_type = meta.split(';').next().unwrap_or("").trim();
+    match media_type.to_ascii_lowercase().as_str() {
+        "" | "text/plain" | "application/json" => {
+            let text = String::from_utf8(bytes).map_err(|_| ZkError::InvalidQrPayload)?;
+            Ok(ProofFile::payload(&text))
+        }
+        #[cfg(feature = "qr")]
+        t if t.starts_with("image/") => scan_image(&bytes, MAX_IMAGE_SIDE),
+        t => Err(ZkError::InvalidUri(format!("unsupported media type {}", t))),
+    }
+}
+
+/// Decodes an image and reads the QR code in it. The size in the header
+/// is checked first, a small file may claim a huge image.
+#[cfg(feature = "qr")]
+pub(crate) fn scan_image(bytes: &[u8], max_side: u32) -> Result<String, ZkError> {
+    let scan_error = |e: image::ImageError| ZkError::QrScan(e.to_string());
+    let reader = || {
+        image::io::Reader::new(std::io::Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(ZkError::Io)
+    };
+    let (width, height) = reader()?.into_dimensions().map_err(scan_error)?;
+    if width > max_side || height > max_side {
+        return Err(ZkError::QrScan(format!(
+            "image of {}x{} pixels, at most {} on a side",
+            width, height, max_side
+        )));
+    }
+    let image = reader()?.decode().map_err(scan_error)?;
+    crate::qr::scan_qr_image(image.to_luma8())
+}
+
+/// The local path of `file:///path`, `file://localhost/path` or
+/// `file:/path`.
+fn file_path(rest: &str) -> Result<String, ZkError> {
+    let path = match rest.strip_prefix("//") {
+        Some(authority) if authority.starts_with('/') => authority,
+        Some(authority) => authority
+            .strip_prefix("localhost")
+            .filter(|path| path.starts_with('/'))
+            .ok_or_else(|| ZkError::InvalidUri(String::from("file URI on another host")))?,
+        None => rest,
+    };
+    String::from_utf8(percent_decode(path)?)
+        .map_err(|_| ZkError::InvalidUri(String::from("path is not UTF-8")))
+}
+
+fn percent_decode(s: &str) -> Result<Vec<u8>, ZkError> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let escape = bytes
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| ZkError::InvalidUri(String::from("bad percent escape")))?;
+            out.push(escape);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Reads at most `limit` bytes. None if there are more.
+fn read_limited<R: Read>(reader: R, limit: u64) -> std::io::Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut body)?;
+    Ok(if body.len() as u64 > limit {
+        None
+    } else {
+        Some(body)
+    })
+}
+
+fn too_large(limit: u64) -> String {
+    format!("proof is larger than {} bytes", limit)
+}
+
+/// Reads the body of a GET request, failing on a body above `limit`
+/// bytes instead of reading it all. Redirects are not followed.
+#[cfg(feature = "net")]
+fn download(uri: &str, limit: u64, timeout: Duration) -> Result<Vec<u8>, ZkError> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .redirects(0)
+        .build();
+    let response = agent
+        .get(uri)
+        .call()
+        .map_err(|e| ZkError::Download(e.to_string()))?;
+    if response.status() != 200 {
+        return Err(ZkError::Download(format!(
+            "status {} {}",
+            response.status(),
+            response.status_text()
+        )));
+    }
+    read_limited(response.into_reader(), limit)
+        .map_err(|e| ZkError::Download(e.to_string()))?
+        .ok_or_else(|| ZkError::Download(too_large(limit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{PublicQr, Relation};
+    use std::fs;
+
+    fn proof() -> ProofQrCode {
+        ProofQrCode {
+            public: PublicQr {
+                today: 2459231,
+                relation: Relation::Older,
+                delta: 2923,
+                contract: vec![4; 32],
+                label: None,
+            },
+            proof: vec![7; 128],
+        }
+    }
+
+    #[test]
+    fn data_uris() {
+        let payload = proof().to_string();
+        let uris = vec![
+            format!("data:application/json;base64,{}", base64::encode(&payload)),
+            format!("data:;base64,{}", base64::encode(&payload)),
+            format!(
+                "data:text/plain;charset=utf-8,{}",
+                payload.replace('"', "%22").replace(' ', "%20")
+            ),
+            format!(
+                "data:application/json,{}",
+                serde_json::json!({ "qr": payload })
+            ),
+        ];
+        for uri in &uris {
+            assert_eq!(payload, ProofQrCode::from_uri(uri).unwrap().to_string());
+        }
+
+        for bad in &["data:text/plain;base64", "data:,%2", "data:text/html,{}"] {
+            match ProofQrCode::from_uri(bad) {
+                Err(ZkError::InvalidUri(_)) => {}
+                other => panic!("{}: expected InvalidUri, got {:?}", bad, other),
+            }
+        }
+        assert!(matches!(
+            ProofQrCode::from_uri("data:,[]"),
+            Err(ZkError::InvalidQrPayload)
+        ));
+    }
+
+    #[cfg(feature = "qr")]
+    #[test]
+    fn data_uri_of_image() {
+        let qr = proof();
+        let uri = crate::qr::proof_qr_data_uri(&qr).unwrap();
+        assert_eq!(
+            qr.to_string(),
+            ProofQrCode::from_uri(&uri).unwrap().to_string()
+        );
+
+        let wide = image::DynamicImage::ImageLuma8(image::GrayImage::new(MAX_IMAGE_SIDE + 1, 1));
+        let mut png = Vec::new();
+        wide.write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        let uri = format!("data:image/png;base64,{}", base64::encode(&png));
+        match ProofQrCode::from_uri(&uri) {
+            Err(ZkError::QrScan(why)) => assert!(why.contains("4097x1"), "{}", why),
+            other => panic!("expected QrScan, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn file_uris() {
+        let payload = proof().to_string();
+        let dir = std::env::temp_dir();
+        let name = format!("harla proof {}.json", std::process::id());
+        let path = dir.join(&name);
+        fs::write(&path, &payload).unwrap();
+
+        let absolute = path.to_str().unwrap().replace(' ', "%20");
+        let results: Vec<_> = [
+            format!("file://{}", absolute),
+            format!("file://localhost{}", absolute),
+            format!("FILE:{}", absolute),
+        ]
+        .iter()
+        .map(|uri| ProofQrCode::from_uri(uri).map(|qr| qr.to_string()))
+        .collect();
+        fs::remove_file(&path).unwrap();
+
+        for r in results {
+            assert_eq!(payload, r.unwrap());
+        }
+        assert!(matches!(
+            ProofQrCode::from_uri(&format!("file://{}", absolute)),
+            Err(ZkError::Io(_))
+        ));
+
+        fs::write(&path, vec![b' '; MAX_PROOF_FILE_BYTES as usize + 1]).unwrap();
+        let large = ProofQrCode::from_uri(&format!("file://{}", absolute));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(large, Err(ZkError::InvalidUri(_))));
+        assert!(matches!(
+            ProofQrCode::from_uri("file://example.com/proof.json"),
+            Err(ZkError::InvalidUri(_))
+        ));
+        assert!(matches!(
+            ProofQrCode::from_uri("ftp://example.com/proof.json"),
+            Err(ZkError::InvalidUri(_))
+        ));
+    }
+
+    /// Serves one canned response with the given status on a local port.
+    /// It names the same URI as the target of a redirect.
+    #[cfg(feature = "net")]
+    fn serve_once(status: &'static str, body: Vec<u8>, delay: Duration) -> String {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            std::thread::sleep(delay);
+            let head = format!(
+                "HTTP/1.1 {}\r\nLocation: /proof.json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        format!("http://{}/proof.json", addr)
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn download_with_limits() {
+        let payload = proof().to_string();
+        let uri = serve_once(
+            "200 OK",
+            payload.clone().into_bytes(),
+            Duration::from_millis(0),
+        );
+        assert_eq!(payload, ProofQrCode::from_uri(&uri).unwrap().to_string());
+
+        let uri = serve_once(
+            "302 Found",
+            payload.clone().into_bytes(),
+            Duration::from_millis(0),
+        );
+        match ProofQrCode::from_uri(&uri) {
+            Err(ZkError::Download(why)) => assert!(why.contains("302"), "{}", why),
+            other => panic!("expected Download, got {:?}", other),
+        }
+
+        let uri = serve_once("200 OK", vec![b' '; 2048], Duration::from_millis(0));
+        assert!(matches!(
+            download(&uri, 1024, DOWNLOAD_TIMEOUT),
+            Err(ZkError::Download(_))
+        ));
+
+        let uri = serve_once("200 OK", payload.into_bytes(), Duration::from_secs(2));
+        assert!(matches!(
+            download(&uri, MAX_PROOF_FILE_BYTES, Duration::from_millis(200)),
+            Err(ZkError::Download(_))
+        ));
+    }
+}
@@ -1,12 +1,33 @@
 // Zero-knowledge algorithms.
 
-use crate::api::{Private, ProofQrCode, PublicChain, QrError, QrRequest, Relation};
+use crate::api::{
+    check_label, jd_to_naive_date, BoundaryPolicy, Nonce, Private, ProofPolicy, ProofQrCode,
+    PublicChain, PublicQr, QrRequest, RequestValidity, VerifiedInputs,
+};
+use crate::artifacts::{active_artifacts, validate_artifacts};
+use crate::cache::Lru;
+use crate::error::ZkError;
+use crate::field::{canonical_field_bytes, ct_contains, field_from_bytes, field_to_bytes};
 
-use bellman_ce::groth16::Proof as BellmanProof;
-use bellman_ce::pairing::{bn256::Bn256, ff::ScalarEngine};
+use bellman_ce::groth16::{
+    prepare_verifying_key, verify_proof as pairing_check, PreparedVerifyingKey,
+    Proof as BellmanProof, VerifyingKey,
+};
+use bellman_ce::pairing::{
+    bn256::{Bn256, Fq, Fq2, G1Affine, G2Affine},
+    ff::ScalarEngine,
+    from_hex, CurveAffine, EncodedPoint,
+};
 use ff_mimc::{PrimeField, PrimeFieldRepr};
+use once_cell::sync::{Lazy, OnceCell};
+use qrcode::{EcLevel, QrCode, Version};
 use rand::{thread_rng, ChaChaRng, Rng, SeedableRng};
-use std::io::Cursor;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use zokrates_core::ir::{self, ProgEnum};
 use zokrates_core::proof_system::{
     bellman::groth16::{ProofPoints, G16},
@@ -15,18 +36,53 @@ use zokrates_core::proof_system::{
 use zokrates_core::typed_absy::abi::Abi;
 use zokrates_field::{Bn128Field, Field};
 
-static PROGRAM: &[u8] = include_bytes!("../zokrates/out");
-static ABI: &[u8] = include_bytes!("../zokrates/abi.json");
-static PROVING_KEY: &[u8] = include_bytes!("../zokrates/proving.key");
-static VERIFICATION_KEY: &[u8] = include_bytes!("../zokrates/verification.key");
+/// Opens a span named after a stage of proving or verification which
+/// lasts until the end of the enclosing block.
+#[cfg(feature = "tracing")]
+macro_rules! stage {
+    ($name:expr) => {
+        let _stage = tracing::info_span!($name).entered();
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! stage {
+    ($name:expr) => {};
+}
 
 type Fr = <Bn256 as ScalarEngine>::Fr;
 
+/// A verification key with its pairings prepared, ready to be shared by
+/// all verifications.
+struct VerificationKey {
+    inputs: usize,
+    prepared: PreparedVerifyingKey<Bn256>,
+}
+
+#[deprecated(note = "use `Nonce::random` or `generate_random_nonce`")]
 pub fn generate_random_private_key() -> Vec<u8> {
+    Nonce::random().to_bytes()
+}
+
+/// A fresh random nonce, e.g. for `compute_mimc7r10_hash`.
+pub fn generate_random_nonce() -> Bn128Field {
     let seed = thread_rng().gen::<[u32; 4]>();
     let mut rng = ChaChaRng::from_seed(&seed);
     let r: Fr = rng.gen();
-    Bn128Field::from_bellman(r).into_byte_vector()
+    Bn128Field::from_bellman(r)
+}
+
+impl Nonce {
+    pub fn random() -> Self {
+        Nonce(generate_random_nonce())
+    }
+}
+
+/// Draws a nonce from the given generator. Callers creating many nonces
+/// share one generator; tests inject a seeded one.
+pub fn generate_nonce<R: Rng>(rng: &mut R) -> Vec<u8> {
+    let r: Fr = rng.gen();
+    field_to_bytes(&Bn128Field::from_bellman(r))
 }
 
 fn zok2mimc(value: &Bn128Field) -> mimc_rs::Fr {
@@ -36,6 +92,8 @@ fn zok2mimc(value: &Bn128Field) -> mimc_rs::Fr {
 }
 
 fn mimc2zok(value: mimc_rs::Fr) -> Bn128Field {
+    // `write_le` writes the limbs least significant first, the order
+    // `from_byte_vector` reads.
     let mut res: Vec<u8> = vec![];
     value.into_repr().write_le(&mut res).unwrap();
     Bn128Field::from_byte_vector(res)
@@ -47,27 +105,308 @@ fn compute_mimc7r10_hash(x: &Bn128Field, k: &Bn128Field) -> Bn128Field {
     mimc2zok(hash)
 }
 
-pub fn generate_prover_key(private: &Private, contract: &Vec<u8>, photo_hash: &Vec<u8>) -> Vec<u8> {
+pub fn generate_prover_key(private: &Private, contract: &[u8], photo_hash: &[u8]) -> Vec<u8> {
     let nonce = Bn128Field::from_byte_vector(private.nonce.to_vec());
     let birthday = Bn128Field::from(private.birthday);
     let photo_hash = Bn128Field::from_byte_vector(photo_hash.to_vec());
     let contract = Bn128Field::from_byte_vector(contract.to_vec());
 
     let card_key = compute_mimc7r10_hash(&(birthday + nonce), &(photo_hash * contract));
-    card_key.into_byte_vector()
+    field_to_bytes(&card_key)
+}
+
+// Concurrency. The program, the embedded verification key and the
+// interpreter are parsed once into process wide cells and then only
+// read, so any number of threads can prove and verify at the same time.
+// The first callers may race to initialize a cell; one of them wins and
+// the others wait for it. The only shared mutable state is the cache of
+// supplied verification keys, which sits behind a mutex held for the
+// lookup only and never while a key is parsed or used.
+
+/// Compile time check that the cached state and the values passed
+/// between threads are `Send + Sync`.
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn is_send_sync<T: Send + Sync>() {}
+    is_send_sync::<ir::Prog<Bn128Field>>();
+    is_send_sync::<ir::Interpreter>();
+    is_send_sync::<VerificationKey>();
+    is_send_sync::<crate::cache::ProofCache>();
+    is_send_sync::<crate::cache::WitnessCache>();
+    is_send_sync::<ProofQrCode>();
+    is_send_sync::<PublicChain>();
+    is_send_sync::<VerifiedInputs>();
+    is_send_sync::<ZkError>();
+}
+
+static PROGRAM_CACHE: OnceCell<ir::Prog<Bn128Field>> = OnceCell::new();
+static VERIFICATION_KEY_CACHE: OnceCell<VerificationKey> = OnceCell::new();
+
+/// Prepares everything needed to prove and verify: loads and checks the
+/// artifacts and deserializes the program once. Call it at startup to
+/// catch a broken build before the first user does.
+pub fn warm_up() -> Result<(), ZkError> {
+    validate_artifacts()?;
+    program()?;
+    if active_artifacts()?.verification_key.is_some() {
+        verification_key()?;
+    }
+    Ok(())
+}
+
+fn program() -> Result<&'static ir::Prog<Bn128Field>, ZkError> {
+    PROGRAM_CACHE.get_or_try_init(|| {
+        let program = &active_artifacts()?.program;
+        match ProgEnum::deserialize(&mut &program[..]).map_err(ZkError::Program)? {
+            ProgEnum::Bn128Program(p) => Ok(p),
+            _ => Err(ZkError::Program(String::from("invalid program type"))),
+        }
+    })
+}
+
+/// The verification key in use, parsed on first use.
+fn verification_key() -> Result<&'static VerificationKey, ZkError> {
+    VERIFICATION_KEY_CACHE.get_or_try_init(|| match &active_artifacts()?.verification_key {
+        Some(vk) => parse_verification_key(vk),
+        None => Err(ZkError::NoVerificationKey),
+    })
+}
+
+/// Parses the JSON which ZoKrates writes. Verification uses alpha, beta,
+/// gamma, delta and the input points only.
+fn parse_verification_key(bytes: &[u8]) -> Result<VerificationKey, ZkError> {
+    let json: Value = serde_json::from_slice(bytes).map_err(ZkError::KeyDeserialize)?;
+    let invalid = |name: &str| {
+        ZkError::KeyDeserialize(serde::de::Error::custom(format!("invalid point {}", name)))
+    };
+    let g2 = |name: &str| g2_from_json(&json[name]).ok_or_else(|| invalid(name));
+    let ic = json["gamma_abc"]
+        .as_array()
+        .and_then(|points| points.iter().map(g1_from_json).collect::<Option<Vec<_>>>())
+        .filter(|ic| !ic.is_empty())
+        .ok_or_else(|| invalid("gamma_abc"))?;
+    let vk = VerifyingKey::<Bn256> {
+        alpha_g1: g1_from_json(&json["alpha"]).ok_or_else(|| invalid("alpha"))?,
+        beta_g1: G1Affine::zero(),
+        beta_g2: g2("beta")?,
+        gamma_g2: g2("gamma")?,
+        delta_g1: G1Affine::zero(),
+        delta_g2: g2("delta")?,
+        ic,
+    };
+    Ok(VerificationKey {
+        inputs: vk.ic.len() - 1,
+        prepared: prepare_verifying_key(&vk),
+    })
+}
+
+/// A G1 point in the ZoKrates JSON form `[x, y]` of hex coordinates.
+/// `None` unless it lies on the curve.
+pub(crate) fn g1_from_json(json: &Value) -> Option<G1Affine> {
+    G1Affine::from_xy_checked(fq_from_json(&json[0])?, fq_from_json(&json[1])?).ok()
+}
+
+/// A G2 point in the ZoKrates JSON form `[[x.c0, x.c1], [y.c0, y.c1]]`.
+pub(crate) fn g2_from_json(json: &Value) -> Option<G2Affine> {
+    G2Affine::from_xy_checked(fq2_from_json(&json[0])?, fq2_from_json(&json[1])?).ok()
+}
+
+fn fq2_from_json(json: &Value) -> Option<Fq2> {
+    Some(Fq2 {
+        c0: fq_from_json(&json[0])?,
+        c1: fq_from_json(&json[1])?,
+    })
+}
+
+fn fq_from_json(json: &Value) -> Option<Fq> {
+    from_hex(json.as_str()?).ok()
+}
+
+pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, ZkError> {
+    generate_proof_with_policy(rq, ProofPolicy::FailSilently)
+}
+
+/// Sizes of a generated proof, for keeping an eye on the QR capacity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofMetrics {
+    /// Length of the serialized proof points.
+    pub proof_len: usize,
+
+    /// Length of the QR code payload, i.e. of `ProofQrCode::to_string`.
+    pub payload_len: usize,
+
+    /// Smallest QR version (1 to 40) holding the payload at the default
+    /// error correction, `None` when it does not fit at all.
+    pub qr_version: Option<i16>,
+
+    /// Error correction level of that QR code.
+    pub qr_ec_level: Option<EcLevel>,
+}
+
+impl ProofMetrics {
+    pub fn measure(qr: &ProofQrCode) -> Self {
+        let payload = qr.to_string();
+        let code = QrCode::new(&payload).ok();
+        ProofMetrics {
+            proof_len: qr.proof.len(),
+            payload_len: payload.len(),
+            qr_version: code.as_ref().and_then(|c| match c.version() {
+                Version::Normal(v) => Some(v),
+                Version::Micro(_) => None,
+            }),
+            qr_ec_level: code.map(|c| c.error_correction_level()),
+        }
+    }
 }
 
-pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, String> {
-    let prg = match ProgEnum::deserialize(&mut PROGRAM.clone())? {
-        ProgEnum::Bn128Program(p) => p,
-        _ => panic!("Invalid program type"),
+pub fn generate_proof_with_metrics(rq: QrRequest) -> Result<(ProofQrCode, ProofMetrics), ZkError> {
+    let qr = generate_proof(rq)?;
+    let metrics = ProofMetrics::measure(&qr);
+    Ok((qr, metrics))
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip(rq, policy),
+        fields(relation = ?rq.qr.relation, today = rq.qr.today)
+    )
+)]
+pub fn generate_proof_with_policy(
+    rq: QrRequest,
+    policy: ProofPolicy,
+) -> Result<ProofQrCode, ZkError> {
+    prove_stages(rq, policy, None, execute_witness)
+}
+
+/// Lets another thread abort `generate_proof_cancellable`, e.g. when the
+/// client of a server disconnects. Clones share one flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Like `generate_proof` but gives up with `Cancelled` once `token` is
+/// cancelled. The token is checked before and between the stages. The
+/// G16 prover cannot be interrupted, so a cancellation while it runs
+/// takes effect when it returns and its proof is dropped.
+pub fn generate_proof_cancellable(
+    rq: QrRequest,
+    token: &CancellationToken,
+) -> Result<ProofQrCode, ZkError> {
+    prove_stages(rq, ProofPolicy::FailSilently, Some(token), execute_witness)
+}
+
+/// Checks `rq` as `policy` says and proves it with the witness which
+/// `execute` computes from the circuit arguments, e.g. from a cache.
+pub(crate) fn prove_stages<E>(
+    rq: QrRequest,
+    policy: ProofPolicy,
+    token: Option<&CancellationToken>,
+    execute: E,
+) -> Result<ProofQrCode, ZkError>
+where
+    E: FnOnce(&[Bn128Field]) -> Result<ir::Witness<Bn128Field>, ZkError>,
+{
+    let check = || match token {
+        Some(token) if token.is_cancelled() => Err(ZkError::Cancelled),
+        _ => Ok(()),
     };
+    check()?;
+    match precheck_request(&rq) {
+        RequestValidity::DeltaMismatch if policy == ProofPolicy::ReturnError => {
+            return Err(ZkError::RelationDeltaMismatch)
+        }
+        RequestValidity::StatementFalse if policy == ProofPolicy::ReturnError => {
+            return Err(ZkError::StatementFalse)
+        }
+        _ => {}
+    }
 
-    let abi: Abi = serde_json::from_reader(&mut ABI.clone()).unwrap();
-    let _signature = abi.signature();
+    check_label(&rq.qr.label)?;
+    let arguments = build_arguments(&rq)?;
+    #[cfg(feature = "debug-inputs")]
+    eprintln!(
+        "{}",
+        dump_field_elements("arguments", &ARGUMENT_NAMES, &arguments)
+    );
+    program()?;
+    check()?;
+    let witness = execute(&arguments)?;
+    check()?;
+    let qr = prove_witness(witness, rq.qr)?;
+    check()?;
+    Ok(qr)
+}
+
+/// Tells without proving whether the proof of `rq` would verify, so a
+/// trusted app can warn its owner "you are not older than 21" and still
+/// decide to prove. It reads the birthday: run it only on the user's own
+/// device and never answer it to anyone else, or the age leaks as
+/// `ProofPolicy::FailSilently` explains.
+pub fn precheck_request(rq: &QrRequest) -> RequestValidity {
+    if !rq.is_delta_consistent() {
+        RequestValidity::DeltaMismatch
+    } else if rq.is_relation_valid() {
+        RequestValidity::Provable
+    } else {
+        RequestValidity::StatementFalse
+    }
+}
 
-    let interpreter = ir::Interpreter::default();
+/// Names of the circuit arguments and of the verifier's public inputs.
+#[cfg_attr(not(feature = "debug-inputs"), allow(dead_code))]
+const ARGUMENT_NAMES: [&str; 7] = [
+    "birthday",
+    "delta",
+    "today",
+    "is_younger",
+    "photo_hash",
+    "contract",
+    "nonce",
+];
+#[cfg_attr(not(feature = "debug-inputs"), allow(dead_code))]
+const INPUT_NAMES: [&str; PUBLIC_INPUTS] = [
+    "delta",
+    "today",
+    "is_younger",
+    "photo_hash",
+    "contract",
+    "prover_key",
+];
 
+/// Formats field elements for the `debug-inputs` feature, one per line
+/// in decimal. The nonce is secret and never printed.
+#[cfg_attr(not(feature = "debug-inputs"), allow(dead_code))]
+fn dump_field_elements(stage: &str, names: &[&str], values: &[Bn128Field]) -> String {
+    let mut out = format!("harla_zk {}:", stage);
+    for (name, value) in names.iter().zip(values) {
+        let shown = if *name == "nonce" {
+            String::from("<redacted>")
+        } else {
+            value.to_dec_string()
+        };
+        out += &format!("\n  {:<11}{}", name, shown);
+    }
+    out
+}
+
+/// Circuit arguments in the order of `main` in legalage.zok. A false
+/// statement, or a delta not computed for the relation, gets arguments
+/// of another, true one (see below).
+pub fn build_arguments(rq: &QrRequest) -> Result<Vec<Bn128Field>, ZkError> {
     let mut arguments: Vec<Bn128Field> = Vec::new();
 
     let birthday = rq.private.birthday;
@@ -76,8 +415,8 @@ pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, String> {
 
     let mut is_younger = 0;
 
-    if rq.is_relation_valid() {
-        if rq.qr.relation == Relation::Younger {
+    if rq.is_relation_valid() && rq.is_delta_consistent() {
+        if rq.qr.relation.is_younger() {
             is_younger = 1;
         }
     } else {
@@ -98,78 +437,408 @@ pub fn generate_proof(rq: QrRequest) -> Result<ProofQrCode, String> {
     arguments.push(Bn128Field::from(delta));
     arguments.push(Bn128Field::from(today));
     arguments.push(Bn128Field::from(is_younger));
-    arguments.push(Bn128Field::from_byte_vector(rq.chain.photo_hash.clone()));
-    arguments.push(Bn128Field::from_byte_vector(rq.qr.contract.clone()));
-    arguments.push(Bn128Field::from_byte_vector(rq.private.nonce));
+    arguments.push(field_from_bytes(&rq.chain.photo_hash)?);
+    arguments.push(field_from_bytes(&rq.qr.contract)?);
+    arguments.push(field_from_bytes(&rq.private.nonce)?);
+    Ok(arguments)
+}
 
-    let witness = interpreter
-        .execute(&prg, &arguments)
-        .map_err(|e| format!("Execution failed: {}", e))?;
+/// The interpreter only holds its configuration and `execute` takes it
+/// by shared reference, so one instance serves all proofs and threads.
+static INTERPRETER: Lazy<ir::Interpreter> = Lazy::new(ir::Interpreter::default);
+
+/// Runs the circuit. The only return value of the witness is the
+/// prover key.
+pub fn execute_witness(arguments: &[Bn128Field]) -> Result<ir::Witness<Bn128Field>, ZkError> {
+    execute_witness_with(&INTERPRETER, arguments)
+}
+
+/// Like `execute_witness` but with the caller's interpreter.
+pub fn execute_witness_with(
+    interpreter: &ir::Interpreter,
+    arguments: &[Bn128Field],
+) -> Result<ir::Witness<Bn128Field>, ZkError> {
+    let prg = {
+        stage!("deserialize");
+        let abi: Abi = serde_json::from_slice(&active_artifacts()?.abi)?;
+        let _signature = abi.signature();
+        program()?
+    };
+
+    let witness = {
+        stage!("witness");
+        interpreter
+            .execute(prg, arguments)
+            .map_err(|e| ZkError::Execution(e.to_string()))?
+    };
 
     let outs = witness.return_values();
     assert_eq!(1, outs.len());
-    //    let out = &outs[0];
+    Ok(witness)
+}
 
-    let proof = G16::generate_proof(prg, witness, PROVING_KEY.to_vec());
+/// The value the circuit returned, the prover key it computed from the
+/// secrets. It equals `PublicChain::prover_key` exactly when the secrets
+/// belong to the chain record. It does not depend on the comparison, so
+/// a false statement, run with the arguments of a true one as
+/// `build_arguments` explains, returns the same key.
+pub fn circuit_output(witness: &ir::Witness<Bn128Field>) -> Bn128Field {
+    witness.return_values()[0].clone()
+}
+
+/// Generates the proof for a witness of the public fields `public`.
+pub fn prove_witness(
+    witness: ir::Witness<Bn128Field>,
+    public: PublicQr,
+) -> Result<ProofQrCode, ZkError> {
+    let prg = program()?.clone();
+    let proof = {
+        stage!("prove");
+        G16::generate_proof(prg, witness, active_artifacts()?.proving_key.to_vec())
+    };
     let bellman_proof = &proof.proof.into_bellman::<Bn128Field>();
     let mut proof_bytes: Vec<u8> = Vec::new();
-    bellman_proof.write(&mut proof_bytes).unwrap();
+    bellman_proof.write(&mut proof_bytes)?;
 
     let qr = ProofQrCode {
-        public: rq.qr,
+        public,
         proof: proof_bytes,
     };
     Ok(qr)
 }
 
-pub fn verify_proof(qr: &ProofQrCode, chain: &PublicChain) -> Result<(), String> {
-    let vk = serde_json::from_reader(VERIFICATION_KEY)
-        .map_err(|why| format!("Couldn't deserialize verification key: {}", why))?;
+/// Length of a serialized proof: two compressed G1 points (A, C) and
+/// one compressed G2 point (B).
+pub fn proof_byte_len() -> usize {
+    2 * <G1Affine as CurveAffine>::Compressed::size()
+        + <G2Affine as CurveAffine>::Compressed::size()
+}
+
+/// Reads a QR code payload as `ProofQrCode::from_str` does and checks
+/// that the proof has the length of `proof_byte_len`, so that a payload
+/// cut short is a `MalformedProof` before any verification.
+pub fn parse_proof(payload: &str) -> Result<ProofQrCode, ZkError> {
+    let qr: ProofQrCode = payload.trim().parse()?;
+    if qr.proof.len() != proof_byte_len() {
+        return Err(ZkError::MalformedProof);
+    }
+    Ok(qr)
+}
+
+/// Number of public inputs of the circuit: delta, today, is_younger,
+/// photo hash, contract and the prover key.
+const PUBLIC_INPUTS: usize = 6;
+
+/// Cheap structural check: the proof decodes into curve points and the
+/// public fields fit the circuit. Does not run the pairing check, so
+/// `true` does not mean the proof verifies.
+pub fn is_well_formed(qr: &ProofQrCode) -> bool {
+    if qr.proof.len() != proof_byte_len() || field_from_bytes(&qr.public.contract).is_err() {
+        return false;
+    }
+    // Decompression checks that every point lies on the curve and in
+    // the prime order subgroup.
+    if BellmanProof::<Bn256>::read(&mut &qr.proof[..]).is_err() {
+        return false;
+    }
+    match verification_key() {
+        Ok(vk) => vk.inputs == PUBLIC_INPUTS,
+        // The proving key was checked against the ABI instead.
+        Err(ZkError::NoVerificationKey) => true,
+        Err(_) => false,
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn record_outcome<T>(result: &Result<T, ZkError>) {
+    let outcome = match result {
+        Ok(_) => "valid",
+        Err(ZkError::InvalidProof) => "invalid",
+        Err(_) => "error",
+    };
+    tracing::Span::current().record("outcome", &outcome);
+}
+
+#[cfg(not(feature = "tracing"))]
+fn record_outcome<T>(_: &Result<T, ZkError>) {}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip(qr, chain),
+        fields(
+            relation = ?qr.borrow().public.relation,
+            today = qr.borrow().public.today,
+            outcome = tracing::field::Empty
+        )
+    )
+)]
+pub fn verify_proof<Q: Borrow<ProofQrCode>>(
+    qr: Q,
+    chain: &PublicChain,
+) -> Result<VerifiedInputs, ZkError> {
+    let result = check_proof(qr.borrow(), chain);
+    record_outcome(&result);
+    result
+}
+
+/// Verifies the proof against a verification key other than the
+/// embedded one, e.g. of a previous setup. The key is a JSON document as
+/// written by ZoKrates and is parsed once per process.
+pub fn verify_proof_with_key(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    vk: &[u8],
+) -> Result<VerifiedInputs, ZkError> {
+    let result = supplied_key(vk).and_then(|vk| check_proof_with(qr, chain, &vk));
+    record_outcome(&result);
+    result
+}
+
+/// Verifies the proof against each of the verification keys in turn and
+/// stops at the first one which accepts it. Lets a verifier accept proofs
+/// of the old and the new setup during a migration. Returns the error of
+/// the last key, `InvalidProof` if there are none.
+pub fn verify_proof_any_key(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    vks: &[Vec<u8>],
+) -> Result<VerifiedInputs, ZkError> {
+    let mut result = Err(ZkError::InvalidProof);
+    for vk in vks {
+        result = supplied_key(vk).and_then(|vk| check_proof_with(qr, chain, &vk));
+        if result.is_ok() {
+            break;
+        }
+    }
+    record_outcome(&result);
+    result
+}
+
+/// Like `verify_proof` but refuses relations which count the birthday
+/// otherwise than `policy`. The circuit does not take the relation
+/// itself; `verify_proof` binds it through the delta, see
+/// `Relation::fits_delta`, so relabelling an `OlderOrEqual` proof as
+/// `Older` is `RelationTampered` rather than a way around the policy.
+pub fn verify_proof_with_boundary(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    policy: BoundaryPolicy,
+) -> Result<VerifiedInputs, ZkError> {
+    if !policy.accepts(qr.public.relation) {
+        return Err(ZkError::BoundaryNotAccepted);
+    }
+    verify_proof(qr, chain)
+}
+
+/// Most verification keys given by the caller kept parsed. A verifier
+/// accepts a handful of setups at a time; one cycling through more parses
+/// them again.
+pub const MAX_SUPPLIED_KEYS: usize = 16;
+
+/// Verification keys given by the caller, by the SHA-256 of their bytes,
+/// the least recently used dropped first.
+static SUPPLIED_KEYS: Lazy<Lru<Arc<VerificationKey>>> = Lazy::new(|| Lru::new(MAX_SUPPLIED_KEYS));
 
-    let mut inputs: Vec<Bn128Field> = Vec::new();
+fn supplied_key(bytes: &[u8]) -> Result<Arc<VerificationKey>, ZkError> {
+    stage!("deserialize");
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&Sha256::digest(bytes));
+    if let Some(vk) = SUPPLIED_KEYS.get(&hash) {
+        return Ok(vk);
+    }
+    let vk = Arc::new(parse_verification_key(bytes)?);
+    SUPPLIED_KEYS.insert(hash, Arc::clone(&vk));
+    Ok(vk)
+}
 
-    // Inverting the relation.
-    let is_younger = qr.public.relation == Relation::Younger;
-    inputs.push(Bn128Field::from(qr.public.delta));
-    inputs.push(Bn128Field::from(qr.public.today));
-    inputs.push(Bn128Field::from(if is_younger { 1 } else { 0 }));
-    inputs.push(Bn128Field::from_byte_vector(chain.photo_hash.clone()));
-    inputs.push(Bn128Field::from_byte_vector(qr.public.contract.clone()));
+fn check_proof(qr: &ProofQrCode, chain: &PublicChain) -> Result<VerifiedInputs, ZkError> {
+    let vk = {
+        stage!("deserialize");
+        verification_key()?
+    };
+    check_proof_with(qr, chain, vk)
+}
 
-    inputs.push(Bn128Field::from_byte_vector(chain.prover_key.clone()));
+/// The public inputs of the verification in the order of the circuit:
+/// delta, today, is_younger, photo_hash, contract, prover_key. For an
+/// on-chain verifier, which takes them apart from the proof points.
+pub fn public_inputs(qr: &ProofQrCode, chain: &PublicChain) -> Result<Vec<Bn128Field>, ZkError> {
+    Ok(input_array(qr, chain)?.to_vec())
+}
 
-    let mut rdr = Cursor::new(&qr.proof);
-    let proof = BellmanProof::<Bn256>::read(&mut rdr)
-        .map_err(|_| QrError {})
-        .unwrap();
+fn input_array(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+) -> Result<[Bn128Field; PUBLIC_INPUTS], ZkError> {
+    // The circuit knows only the direction of the comparison; the
+    // inclusive relations differ in the delta.
+    let is_younger = qr.public.relation.is_younger();
+    Ok([
+        Bn128Field::from(qr.public.delta),
+        Bn128Field::from(qr.public.today),
+        Bn128Field::from(if is_younger { 1 } else { 0 }),
+        field_from_bytes(&chain.photo_hash)?,
+        field_from_bytes(&qr.public.contract)?,
+        field_from_bytes(&chain.prover_key)?,
+    ])
+}
 
-    let mut raw: Vec<u8> = Vec::new();
-    proof.write(&mut raw).unwrap();
+/// The proof points of `qr` with the given inputs in the form the
+/// ZoKrates verifier takes.
+fn zokrates_proof(qr: &ProofQrCode, inputs: &[Bn128Field]) -> Result<Proof<ProofPoints>, ZkError> {
+    let proof =
+        BellmanProof::<Bn256>::read(&mut &qr.proof[..]).map_err(|_| ZkError::MalformedProof)?;
 
     let proof_points = ProofPoints::from_bellman::<Bn128Field>(&proof);
 
-    let proof = Proof::<ProofPoints> {
+    Ok(Proof::<ProofPoints> {
         proof: proof_points,
         inputs: inputs
             .iter()
             .map(|bn128| bn128.to_biguint().to_str_radix(16))
             .collect(),
-        raw: hex::encode(&raw),
-    };
+        // `verify` uses only the points and the inputs. The proof was
+        // read from `qr.proof` so those bytes are its raw form.
+        raw: hex::encode(&qr.proof),
+    })
+}
 
-    let ans = <G16 as ProofSystem<Bn128Field>>::verify(vk, proof);
+/// The proof as the `proof.json` which `zokrates generate-proof` writes,
+/// so that `zokrates verify` with the verification key of the artifacts
+/// checks it. Inputs are `0x` and 64 hex digits as ZoKrates writes them.
+pub fn to_zokrates_proof_json(qr: &ProofQrCode, chain: &PublicChain) -> Result<String, ZkError> {
+    if qr.proof.len() != proof_byte_len() {
+        return Err(ZkError::MalformedProof);
+    }
+    let mut proof = zokrates_proof(qr, &input_array(qr, chain)?)?;
+    proof.inputs = proof
+        .inputs
+        .iter()
+        .map(|hex| format!("0x{:0>64}", hex))
+        .collect();
+    Ok(serde_json::to_string_pretty(&proof)?)
+}
+
+fn check_proof_with(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    vk: &VerificationKey,
+) -> Result<VerifiedInputs, ZkError> {
+    if qr.proof.len() != proof_byte_len() {
+        return Err(ZkError::MalformedProof);
+    }
+
+    let today = jd_to_naive_date(qr.public.today).ok_or(ZkError::InvalidQrPayload)?;
+    let inputs = input_array(qr, chain)?;
+
+    #[cfg(feature = "debug-inputs")]
+    eprintln!("{}", dump_field_elements("inputs", &INPUT_NAMES, &inputs));
+
+    let proof =
+        BellmanProof::<Bn256>::read(&mut &qr.proof[..]).map_err(|_| ZkError::MalformedProof)?;
+    let scalars: Vec<Fr> = inputs.iter().map(|i| i.clone().into_bellman()).collect();
+    let ans = {
+        stage!("verify");
+        // A key for another number of inputs accepts nothing.
+        pairing_check(&vk.prepared, &proof, &scalars).unwrap_or(false)
+    };
     if ans {
-        Ok(())
+        if !qr.public.relation.fits_delta(qr.public.delta) {
+            return Err(ZkError::RelationTampered);
+        }
+        let [_, _, _, photo_hash, contract, prover_key] = inputs;
+        Ok(VerifiedInputs {
+            today,
+            relation: qr.public.relation,
+            delta: qr.public.delta,
+            contract,
+            photo_hash,
+            prover_key,
+        })
     } else {
-        Err(String::from("no"))
+        Err(ZkError::InvalidProof)
+    }
+}
+
+/// Parses a QR code payload as scanned, e.g. the body of a request, and
+/// verifies it. A payload which cannot be parsed is a `MalformedProof`.
+pub fn verify_proof_bytes(
+    proof_bytes: &[u8],
+    chain: &PublicChain,
+) -> Result<VerifiedInputs, ZkError> {
+    let qr = std::str::from_utf8(proof_bytes)
+        .ok()
+        .and_then(|s| s.trim().parse::<ProofQrCode>().ok())
+        .ok_or(ZkError::MalformedProof)?;
+    verify_proof(&qr, chain)
+}
+
+/// Verifies the proof against each chain record in turn, e.g. one per
+/// certified document of the same person. Returns the first success or
+/// the last error.
+pub fn verify_proof_any(
+    qr: &ProofQrCode,
+    chains: &[PublicChain],
+) -> Result<VerifiedInputs, ZkError> {
+    let mut result = Err(ZkError::InvalidProof);
+    for chain in chains {
+        result = verify_proof(qr, chain);
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+/// Verifies only proofs bound to one of the `accepted` contracts, e.g.
+/// of the partner issuers. Both sides are brought to the canonical 32
+/// byte encoding by `canonical_field_bytes`, so an accepted `[4]` matches
+/// a proof's `[4, 0, ...]`, and compared in constant time, so the time
+/// of the refusal does not tell how close the contract was to an
+/// accepted one. An unknown contract is refused before verification.
+pub fn verify_proof_for_contracts(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    accepted: &[Vec<u8>],
+) -> Result<VerifiedInputs, ZkError> {
+    let accepted: Vec<Vec<u8>> = accepted.iter().map(|c| canonical_field_bytes(c)).collect();
+    if !ct_contains(&accepted, &canonical_field_bytes(&qr.public.contract)) {
+        return Err(ZkError::ContractNotAccepted);
+    }
+    verify_proof(qr, chain)
+}
+
+/// Verifies the proof and then rejects it if the chain's prover key was
+/// revoked, e.g. after its nonce leaked. The cryptographic check runs
+/// first so that an invalid proof takes the same time whether or not
+/// its key is revoked. The key is compared with every revoked key in
+/// constant time rather than looked up by its hash, both in the
+/// canonical encoding of `canonical_field_bytes`.
+pub fn verify_proof_with_revocation(
+    qr: &ProofQrCode,
+    chain: &PublicChain,
+    revoked_prover_keys: &HashSet<Vec<u8>>,
+) -> Result<VerifiedInputs, ZkError> {
+    let verified = verify_proof(qr, chain)?;
+    let revoked: Vec<Vec<u8>> = revoked_prover_keys
+        .iter()
+        .map(|k| canonical_field_bytes(k))
+        .collect();
+    if ct_contains(&revoked, &canonical_field_bytes(&chain.prover_key)) {
+        return Err(ZkError::Revoked);
     }
+    Ok(verified)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::api::{Private, PublicQr, QrRequest, Relation};
+    use crate::api::{age_to_delta, Private, PublicQr, QrRequest, Relation};
+    use crate::artifacts::VERIFICATION_KEY;
+    use std::error::Error;
+    #[cfg(not(feature = "no-embedded-vk"))]
     use std::str::FromStr;
     use zokrates_field::Bn128Field;
     //    use num_bigint::BigUint;
@@ -178,6 +847,24 @@ mod tests {
         Bn128Field::try_from_dec_str(s).unwrap()
     }
 
+    #[test]
+    fn random_nonce() {
+        let nonce = Nonce::random();
+        let bytes = nonce.to_bytes();
+        assert_eq!(32, bytes.len());
+        assert_eq!(nonce.as_field(), &field_from_bytes(&bytes).unwrap());
+        assert_ne!(nonce, Nonce::random());
+        assert_eq!("Nonce(..)", format!("{:?}", nonce));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_private_key() {
+        let key = generate_random_private_key();
+        assert_eq!(32, key.len());
+        assert!(field_from_bytes(&key).is_ok());
+    }
+
     #[test]
     fn mimc7r10() {
         // values from ZoKrartes test
@@ -229,92 +916,526 @@ mod tests {
 
         let private = Private {
             birthday: 2001,
-            nonce: bn128("7999").into_byte_vector(),
+            nonce: field_to_bytes(&bn128("7999")),
         };
-        let photo_hash = bn128("3").into_byte_vector();
-        let contract = bn128("4").into_byte_vector();
+        let photo_hash = field_to_bytes(&bn128("3"));
+        let contract = field_to_bytes(&bn128("4"));
         let key = super::generate_prover_key(&private, &photo_hash, &contract);
         assert_eq!(32, key.len());
 
         assert_eq!(Bn128Field::from_byte_vector(key), m1);
     }
-    /*
-        fn test_verification(today: i32, birthday: i32, relation: Relation, delta: i32, result: bool) {
-            let m1 =
-                bn128("10046037004840239707202533642544953578314335199439499999912878067091298310375");
-            assert_eq!(compute_mimc7r10_hash(&bn128("10000"), &bn128("12")), m1);
-
-            let chain = PublicChain {
-                photo_hash: bn128("3").into_byte_vector(),
-                prover_key: m1.into_byte_vector(),
-            };
-
-            let rq = QrRequest {
-                qr: PublicQr {
-                    today,
-                    relation,
-                    delta,
-                    contract: bn128("4").into_byte_vector(),
-                },
-                chain: chain.clone(),
-                private: Private {
-                    birthday,
-                    nonce: bn128("7999").into_byte_vector(),
-                },
-            };
-
-            let p = super::generate_proof(rq).unwrap();
-            println!("{}", p.to_string());
-            assert_eq!(result, super::verify_proof(&p, &chain).is_ok());
-            let pp = ProofQrCode::from_str(&p.to_string()).unwrap();
-            println!("{}", pp.to_string());
-            assert_eq!(result, super::verify_proof(&pp, &chain).is_ok());
-            println!("------------------");
+    /// Proves that someone born on `birthday` is in `relation` to `age`
+    /// on `today` and checks that the proof verifies if and only if
+    /// that holds, also read back from its payload.
+    #[cfg(not(feature = "no-embedded-vk"))]
+    fn test_verification(
+        today: (i32, u32, u32),
+        birthday: (i32, u32, u32),
+        relation: Relation,
+        age: i32,
+        result: bool,
+    ) {
+        use crate::api::naive_date_to_jd;
+        use chrono::NaiveDate;
+
+        let jd = |(y, m, d)| naive_date_to_jd(NaiveDate::from_ymd(y, m, d));
+        let private = Private {
+            birthday: jd(birthday),
+            nonce: field_to_bytes(&bn128("7999")),
+        };
+        let photo_hash = field_to_bytes(&bn128("3"));
+        let contract = field_to_bytes(&bn128("4"));
+        let chain = PublicChain {
+            prover_key: super::generate_prover_key(&private, &contract, &photo_hash),
+            photo_hash,
+        };
+        let rq = QrRequest {
+            qr: PublicQr {
+                today: jd(today),
+                relation,
+                delta: age_to_delta(private.birthday, age, relation),
+                contract,
+                label: None,
+            },
+            chain: chain.clone(),
+            private,
+        };
+
+        let p = super::generate_proof(rq).unwrap();
+        assert_eq!(result, super::verify_proof(&p, &chain).is_ok());
+        let pp = ProofQrCode::from_str(&p.to_string()).unwrap();
+        assert_eq!(result, super::verify_proof(&pp, &chain).is_ok());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_older() {
+        test_verification((2020, 1, 1), (2001, 1, 1), Relation::Older, 18, true);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_younger() {
+        test_verification((2020, 1, 1), (2001, 1, 1), Relation::Younger, 21, true);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_invalid() {
+        test_verification((2020, 1, 1), (2010, 1, 1), Relation::Older, 18, false);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_marginal_case_older() {
+        // Equality is refused; see boundary_policies_on_birthday.
+        test_verification((2020, 1, 1), (2000, 1, 1), Relation::Older, 20, false);
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_marginal_case_younger() {
+        test_verification((2020, 1, 1), (2000, 1, 1), Relation::Younger, 20, false);
+    }
+
+    /// Bart's request on his 10th birthday, 2020-02-22.
+    #[cfg(not(feature = "no-embedded-vk"))]
+    fn tenth_birthday(relation: Relation) -> QrRequest {
+        let mut rq = crate::testutil::bart_request();
+        rq.qr.today = 2458902;
+        rq.qr.relation = relation;
+        rq.qr.delta = age_to_delta(rq.private.birthday, 10, relation);
+        rq
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn marginal_case_younger_or_equal() {
+        for &(relation, valid) in &[
+            (Relation::Older, false),
+            (Relation::Younger, false),
+            (Relation::YoungerOrEqual, true),
+        ] {
+            let rq = tenth_birthday(relation);
+            assert_eq!(valid, rq.is_relation_valid(), "{:?}", relation);
+            let chain = rq.chain.clone();
+            let qr = generate_proof(rq).unwrap();
+            assert_eq!(valid, verify_proof(&qr, &chain).is_ok(), "{:?}", relation);
+            let qr = ProofQrCode::from_str(&qr.to_string()).unwrap();
+            assert_eq!(valid, verify_proof(&qr, &chain).is_ok(), "{:?}", relation);
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn negated_relation_on_birthday() {
+        // On the birthday exactly one of the inclusive relation and its
+        // negation holds.
+        for &relation in &[Relation::YoungerOrEqual, Relation::OlderOrEqual] {
+            let valid = tenth_birthday(relation).is_relation_valid();
+            let negated = tenth_birthday(relation.negate()).is_relation_valid();
+            assert!(valid && !negated, "{:?}", relation);
+        }
+        let rq = tenth_birthday(Relation::OlderOrEqual);
+        let chain = rq.chain.clone();
+        assert!(verify_proof(&generate_proof(rq).unwrap(), &chain).is_ok());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn relabelled_inclusive_relation() {
+        for &(proved, claimed) in &[
+            (Relation::OlderOrEqual, Relation::Older),
+            (Relation::Younger, Relation::YoungerOrEqual),
+        ] {
+            let mut rq = crate::testutil::bart_request();
+            rq.qr.relation = proved;
+            rq.qr.delta = age_to_delta(
+                rq.private.birthday,
+                if proved.is_younger() { 12 } else { 8 },
+                proved,
+            );
+            let chain = rq.chain.clone();
+            let mut qr = generate_proof(rq).unwrap();
+            assert!(verify_proof(&qr, &chain).is_ok(), "{:?}", proved);
+
+            qr.public.relation = claimed;
+            match verify_proof_with_boundary(&qr, &chain, BoundaryPolicy::StrictlyAfter) {
+                Err(ZkError::RelationTampered) => {}
+                other => panic!("{:?}: expected RelationTampered, got {:?}", claimed, other),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn boundary_policies_on_birthday() {
+        for &(policy, valid) in &[
+            (BoundaryPolicy::StrictlyAfter, false),
+            (BoundaryPolicy::OnOrAfter, true),
+        ] {
+            let rq = tenth_birthday(policy.relation(Relation::Older));
+            let chain = rq.chain.clone();
+            let qr = generate_proof(rq).unwrap();
+            let result = verify_proof_with_boundary(&qr, &chain, policy);
+            assert_eq!(valid, result.is_ok(), "{:?}", policy);
+        }
+
+        let rq = tenth_birthday(Relation::OlderOrEqual);
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        match verify_proof_with_boundary(&qr, &chain, BoundaryPolicy::StrictlyAfter) {
+            Err(ZkError::BoundaryNotAccepted) => {}
+            other => panic!("expected BoundaryNotAccepted, got {:?}", other),
+        }
+        assert!(!BoundaryPolicy::OnOrAfter.accepts(Relation::YoungerOrEqual));
+        assert_eq!(BoundaryPolicy::StrictlyAfter, BoundaryPolicy::default());
+    }
+
+    #[test]
+    fn zokrates_proof_json_shape() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let json = to_zokrates_proof_json(&qr, &chain).unwrap();
+
+        let proof: Proof<ProofPoints> = serde_json::from_str(&json).unwrap();
+        let expected: Vec<String> = public_inputs(&qr, &chain)
+            .unwrap()
+            .iter()
+            .map(|f| format!("0x{:0>64}", f.to_biguint().to_str_radix(16)))
+            .collect();
+        assert_eq!(expected, proof.inputs);
+        assert_eq!(66, proof.inputs[5].len());
+        assert_eq!(hex::encode(&qr.proof), proof.raw);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for point in &["a", "b", "c"] {
+            assert!(value["proof"][point].is_array(), "{}", point);
+        }
+
+        let mut short = qr;
+        short.proof.pop();
+        assert!(matches!(
+            to_zokrates_proof_json(&short, &chain),
+            Err(ZkError::MalformedProof)
+        ));
+    }
+
+    #[test]
+    fn circuit_output_is_prover_key() {
+        let output = |rq: &QrRequest| {
+            let witness = execute_witness(&build_arguments(rq).unwrap()).unwrap();
+            circuit_output(&witness)
+        };
+        let rq = crate::testutil::bart_request();
+        let prover_key = field_from_bytes(&rq.chain.prover_key).unwrap();
+        assert_eq!(prover_key, output(&rq));
+
+        let mut false_statement = crate::testutil::bart_request();
+        false_statement.qr.delta += 3650;
+        assert!(!false_statement.is_relation_valid());
+        assert_eq!(prover_key, output(&false_statement));
+
+        let mut other_nonce = rq;
+        other_nonce.private.nonce = field_to_bytes(&bn128("7999"));
+        assert_ne!(prover_key, output(&other_nonce));
+    }
+
+    #[test]
+    fn parse_proof_checks_length() {
+        let qr = ProofQrCode {
+            public: test_request(Relation::Older, 2923).qr,
+            proof: vec![0xab; proof_byte_len()],
+        };
+        assert!(parse_proof(&qr.to_string()).is_ok());
+        assert!(parse_proof(&format!(" {}\n", qr.to_base45())).is_ok());
+
+        for len in &[0, proof_byte_len() - 1, proof_byte_len() + 1] {
+            let mut wrong = qr.clone();
+            wrong.proof = vec![0xab; *len];
+            for payload in &[wrong.to_string(), wrong.to_base45()] {
+                match parse_proof(payload) {
+                    Err(ZkError::MalformedProof) => {}
+                    other => panic!("{} bytes: expected MalformedProof, got {:?}", len, other),
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn label_does_not_change_verification() {
+        let mut rq = crate::testutil::bart_request();
+        rq.qr.label = Some(String::from("BarXYZ age-gate"));
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let verified = verify_proof(&qr, &chain).unwrap();
+
+        let mut relabelled = ProofQrCode::from_str(&qr.to_string()).unwrap();
+        assert_eq!(qr.public.label, relabelled.public.label);
+        relabelled.public.label = None;
+        assert_eq!(verified, verify_proof(&relabelled, &chain).unwrap());
+
+        let mut rq = crate::testutil::bart_request();
+        rq.qr.label = Some("x".repeat(crate::api::MAX_LABEL_BYTES + 1));
+        assert!(matches!(
+            generate_proof(rq),
+            Err(ZkError::InvalidRequest(_))
+        ));
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn boundary_matrix() {
+        // Bart turns A on 2010+A-02-22. Older holds strictly after that
+        // day and Younger strictly before it.
+        let birthday = crate::testutil::bart_request().private.birthday;
+        for &relation in &[Relation::Older, Relation::Younger] {
+            for age in 9..=11 {
+                let anniversary =
+                    crate::api::naive_date_to_jd(chrono::NaiveDate::from_ymd(2010 + age, 2, 22));
+                for today in 2458901..=2458903 {
+                    let expected = match relation {
+                        Relation::Older => today > anniversary,
+                        _ => today < anniversary,
+                    };
+                    let mut rq = crate::testutil::bart_request();
+                    rq.qr.today = today;
+                    rq.qr.relation = relation;
+                    rq.qr.delta = age_to_delta(birthday, age, relation);
+                    let case = format!("{:?} {} on jd {}", relation, age, today);
+                    assert_eq!(expected, rq.is_relation_valid(), "{}", case);
+
+                    let chain = rq.chain.clone();
+                    let qr = generate_proof(rq).unwrap();
+                    assert_eq!(expected, verify_proof(&qr, &chain).is_ok(), "{}", case);
+                    let qr = ProofQrCode::from_str(&qr.to_string()).unwrap();
+                    assert_eq!(expected, verify_proof(&qr, &chain).is_ok(), "{}", case);
+                }
+            }
         }
+    }
 
-        #[test]
-        fn verify_older() {
-            test_verification(2020, 2001, Relation::Older, 18, true);
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn younger_or_equal_ends_after_birthday() {
+        let mut rq = tenth_birthday(Relation::YoungerOrEqual);
+        rq.qr.today += 1;
+        assert!(!rq.is_relation_valid());
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        assert!(verify_proof(&qr, &chain).is_err());
+    }
+    #[test]
+    fn corrupt_verification_key() {
+        let vk = &VERIFICATION_KEY[..VERIFICATION_KEY.len() / 2];
+        let err = parse_verification_key(vk)
+            .err()
+            .expect("corrupt verification key accepted");
+        let why = match &err {
+            ZkError::KeyDeserialize(why) => why.to_string(),
+            other => panic!("expected KeyDeserialize, got {}", other),
+        };
+        assert!(err.to_string().contains(&why));
+        assert!(err.source().is_some());
+
+        // First digit of the alpha x coordinate, which moves alpha off
+        // the curve.
+        let vk = String::from_utf8(VERIFICATION_KEY.to_vec()).unwrap();
+        let off_curve = vk.replacen("0x1936c2", "0x1936c3", 1);
+        assert_ne!(vk, off_curve);
+        assert!(matches!(
+            parse_verification_key(off_curve.as_bytes()),
+            Err(ZkError::KeyDeserialize(_))
+        ));
+    }
+
+    fn test_request(relation: Relation, delta: i32) -> QrRequest {
+        QrRequest {
+            qr: PublicQr {
+                today: 2459231,
+                relation,
+                delta,
+                contract: field_to_bytes(&bn128("4")),
+                label: None,
+            },
+            chain: PublicChain {
+                photo_hash: field_to_bytes(&bn128("3")),
+                prover_key: field_to_bytes(&bn128("0")),
+            },
+            private: Private {
+                birthday: 2455250,
+                nonce: field_to_bytes(&bn128("7999")),
+            },
         }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn reject_delta_of_other_relation() {
+        let older = age_to_delta(2455250, 8, Relation::Older);
+        let younger = age_to_delta(2455250, 8, Relation::Younger);
+        assert!(test_request(Relation::Older, older).is_delta_consistent());
+        assert!(test_request(Relation::Younger, younger).is_delta_consistent());
 
-        #[test]
-        fn verify_younger() {
-            test_verification(2020, 2001, Relation::Younger, 21, true);
+        for rq in vec![
+            test_request(Relation::Older, younger),
+            test_request(Relation::Younger, older),
+            test_request(Relation::Older, -older),
+            test_request(Relation::Younger, 0),
+        ] {
+            match super::generate_proof_with_policy(rq, ProofPolicy::ReturnError) {
+                Err(ZkError::RelationDeltaMismatch) => {}
+                other => panic!("expected RelationDeltaMismatch, got {:?}", other),
+            }
         }
 
-        #[test]
-        fn verify_invalid() {
-            test_verification(2020, 2010, Relation::Older, 18, false);
+        // Silently, the delta of OlderOrEqual under Older gives a proof
+        // which fails like that of a false statement.
+        let mut rq = crate::testutil::bart_request();
+        rq.qr.delta = age_to_delta(rq.private.birthday, 8, Relation::OlderOrEqual);
+        assert!(rq.is_relation_valid() && !rq.is_delta_consistent());
+        let chain = rq.chain.clone();
+        let qr = super::generate_proof(rq).unwrap();
+        match verify_proof(&qr, &chain) {
+            Err(ZkError::InvalidProof) => {}
+            other => panic!("expected InvalidProof, got {:?}", other),
         }
+    }
 
-        #[test]
-        fn verify_marginal_case_older() {
-            // Equality is refused. Wait till midnight.
-            test_verification(2020, 2000, Relation::Older, 20, false);
+    #[test]
+    fn false_statement_policy() {
+        // Born 2010, not older than 18 in 2021.
+        let delta = age_to_delta(2455250, 18, Relation::Older);
+        let rq = test_request(Relation::Older, delta);
+        assert!(!rq.is_relation_valid());
+        match super::generate_proof_with_policy(rq, ProofPolicy::ReturnError) {
+            Err(ZkError::StatementFalse) => {}
+            other => panic!("expected StatementFalse, got {:?}", other),
         }
+    }
 
-        #[test]
-        fn verify_marginal_case_younger() {
-            test_verification(2020, 2000, Relation::Older, 20, false);
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn reject_short_proof() {
+        let qr = ProofQrCode {
+            public: test_request(Relation::Older, 2923).qr,
+            proof: vec![0; proof_byte_len() - 1],
+        };
+        match verify_proof(&qr, &test_request(Relation::Older, 2923).chain) {
+            Err(ZkError::MalformedProof) => {}
+            other => panic!("expected MalformedProof, got {:?}", other),
         }
-    */
+    }
+
+    #[test]
+    fn well_formed() {
+        let qr = generate_proof(crate::testutil::bart_request()).unwrap();
+        assert!(is_well_formed(&qr));
+
+        // x = 4 has no y on the curve y^2 = x^3 + 3.
+        let mut off_curve = qr.clone();
+        off_curve.proof[..32].copy_from_slice(&[0; 32]);
+        off_curve.proof[31] = 4;
+        assert!(!is_well_formed(&off_curve));
+
+        let mut short = qr;
+        short.proof.pop();
+        assert!(!is_well_formed(&short));
+    }
+
+    #[test]
+    fn metrics_payload_len() {
+        let qr = ProofQrCode {
+            public: test_request(Relation::Older, 2923).qr,
+            proof: vec![0xab; proof_byte_len()],
+        };
+        let metrics = ProofMetrics::measure(&qr);
+        assert_eq!(qr.to_string().len(), metrics.payload_len);
+        assert_eq!(proof_byte_len(), metrics.proof_len);
+        assert_eq!(Some(EcLevel::M), metrics.qr_ec_level);
+        assert!(metrics.qr_version.unwrap() <= 40);
+    }
+
+    #[test]
+    fn stage_arguments() {
+        let rq = crate::testutil::bart_request();
+        let arguments = build_arguments(&rq).unwrap();
+        assert_eq!(7, arguments.len());
+        assert_eq!(Bn128Field::from(2455250), arguments[0]);
+        assert_eq!(Bn128Field::from(2923), arguments[1]);
+        assert_eq!(Bn128Field::from(2459231), arguments[2]);
+        assert_eq!(Bn128Field::from(0), arguments[3]);
+        assert_eq!(field_from_bytes(&rq.private.nonce).unwrap(), arguments[6]);
+
+        // An unprovable statement is replaced by one with zero delta.
+        let mut false_rq = crate::testutil::bart_request();
+        false_rq.qr.today = false_rq.private.birthday + 1;
+        assert_eq!(Bn128Field::from(0), build_arguments(&false_rq).unwrap()[1]);
+    }
+
+    #[test]
+    fn stage_witness() {
+        let rq = crate::testutil::bart_request();
+        let witness = execute_witness(&build_arguments(&rq).unwrap()).unwrap();
+        assert_eq!(
+            vec![field_from_bytes(&rq.chain.prover_key).unwrap()],
+            witness.return_values()
+        );
+    }
+
+    #[test]
+    fn reused_interpreter() {
+        let arguments = build_arguments(&crate::testutil::bart_request()).unwrap();
+        let fresh = execute_witness_with(&ir::Interpreter::default(), &arguments).unwrap();
+        let first = execute_witness(&arguments).unwrap();
+        let second = execute_witness(&arguments).unwrap();
+        assert_eq!(fresh.to_string(), first.to_string());
+        assert_eq!(first.to_string(), second.to_string());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn stage_prove() {
+        let rq = crate::testutil::bart_request();
+        let witness = execute_witness(&build_arguments(&rq).unwrap()).unwrap();
+        let qr = prove_witness(witness, rq.qr.clone()).unwrap();
+        assert_eq!(rq.qr, qr.public);
+        assert!(verify_proof(&qr, &rq.chain).is_ok());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn raw_is_the_qr_proof() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let proof = BellmanProof::<Bn256>::read(&mut &qr.proof[..]).unwrap();
+        let mut raw = Vec::new();
+        proof.write(&mut raw).unwrap();
+        assert_eq!(qr.proof, raw);
+        assert!(verify_proof(&qr, &chain).is_ok());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
     #[test]
     fn verify_bart() {
         let private = Private {
             birthday: 2455250,
-            nonce: bn128(
+            nonce: field_to_bytes(&bn128(
                 "49562589987336948678371811862197518411894129330930510829597277386215323558419",
-            )
-            .into_byte_vector(),
+            )),
         };
 
         //	"0x330e55395b367bab55b24b5377f7fe813735e55d";
-        let contract = bn128("291478163806436998532036252836091753082125673821").into_byte_vector();
+        let contract = field_to_bytes(&bn128("291478163806436998532036252836091753082125673821"));
         println!("c {:?}", contract);
         //	let contract2 = BigUint::from_str("291478163806436998532036252836091753082125673821").unwrap();
         //        println!("c2 {:?}", contract2.to_bytes_be());
 
-        let photo_hash = bn128("70573743172686605492515124569").into_byte_vector();
+        let photo_hash = field_to_bytes(&bn128("70573743172686605492515124569"));
         let prover_key = super::generate_prover_key(&private, &photo_hash, &contract);
         println!("prover key: {:?}", prover_key);
 
@@ -329,6 +1450,7 @@ mod tests {
                 relation: Relation::Older,
                 delta: 2923,
                 contract,
+                label: None,
             },
             chain: chain.clone(),
             private,
@@ -342,4 +1464,289 @@ mod tests {
         assert_eq!(true, super::verify_proof(&pp, &chain).is_ok());
         println!("------------------");
     }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verified_inputs_are_decoded() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let contract = rq.qr.contract.clone();
+        let verified = verify_proof(&generate_proof(rq).unwrap(), &chain).unwrap();
+        assert_eq!("2021-01-16", verified.today.format("%Y-%m-%d").to_string());
+        assert_eq!(Relation::Older, verified.relation);
+        assert_eq!(2923, verified.delta);
+        assert_eq!(field_from_bytes(&contract).unwrap(), verified.contract);
+        assert_eq!(
+            field_from_bytes(&chain.prover_key).unwrap(),
+            verified.prover_key
+        );
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_any_second_chain() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let passport = PublicChain {
+            photo_hash: field_to_bytes(&bn128("12345")),
+            prover_key: chain.prover_key.clone(),
+        };
+        let p = super::generate_proof(rq).unwrap();
+        let verified = verify_proof_any(&p, &[passport.clone(), chain.clone()]).unwrap();
+        assert_eq!(
+            field_from_bytes(&chain.photo_hash).unwrap(),
+            verified.photo_hash
+        );
+        assert!(verify_proof_any(&p, &[passport]).is_err());
+        assert!(verify_proof_any(&p, &[]).is_err());
+    }
+
+    /// The embedded key with two inputs swapped. It parses but belongs to
+    /// no setup.
+    fn foreign_verification_key() -> Vec<u8> {
+        let mut vk: serde_json::Value = serde_json::from_slice(VERIFICATION_KEY).unwrap();
+        vk["gamma_abc"].as_array_mut().unwrap().swap(1, 2);
+        serde_json::to_vec(&vk).unwrap()
+    }
+
+    #[test]
+    fn second_key_verifies() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let foreign = foreign_verification_key();
+
+        let keys = vec![foreign.clone(), VERIFICATION_KEY.to_vec()];
+        assert!(verify_proof_any_key(&qr, &chain, &keys).is_ok());
+        assert!(verify_proof_with_key(&qr, &chain, VERIFICATION_KEY).is_ok());
+        match verify_proof_any_key(&qr, &chain, &[foreign.clone()]) {
+            Err(ZkError::InvalidProof) => {}
+            other => panic!("expected InvalidProof, got {:?}", other),
+        }
+        match verify_proof_any_key(&qr, &chain, &[foreign, b"{".to_vec()]) {
+            Err(ZkError::KeyDeserialize(_)) => {}
+            other => panic!("expected KeyDeserialize, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn supplied_keys_are_bounded() {
+        // Trailing spaces make other bytes of the same key.
+        let mut vk = VERIFICATION_KEY.to_vec();
+        for _ in 0..MAX_SUPPLIED_KEYS + 4 {
+            vk.push(b' ');
+            supplied_key(&vk).unwrap();
+        }
+        assert_eq!(MAX_SUPPLIED_KEYS, SUPPLIED_KEYS.len());
+        assert!(supplied_key(&vk).is_ok());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn public_inputs_in_verifier_order() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let inputs = public_inputs(&qr, &chain).unwrap();
+        let verified = verify_proof(&qr, &chain).unwrap();
+        assert_eq!(
+            vec![
+                Bn128Field::from(verified.delta),
+                Bn128Field::from(crate::api::naive_date_to_jd(verified.today)),
+                Bn128Field::from(0),
+                verified.photo_hash,
+                verified.contract,
+                verified.prover_key,
+            ],
+            inputs
+        );
+        assert_eq!(INPUT_NAMES.len(), inputs.len());
+
+        let mut younger = qr;
+        younger.public.relation = Relation::Younger;
+        assert_eq!(
+            Bn128Field::from(1),
+            public_inputs(&younger, &chain).unwrap()[2]
+        );
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_owned_and_borrowed() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let borrowed = verify_proof(&qr, &chain).unwrap();
+        let owned = verify_proof(qr.clone(), &chain).unwrap();
+        assert_eq!(borrowed, owned);
+
+        let mut tampered = qr;
+        tampered.public.today += 1;
+        assert_eq!(
+            verify_proof(&tampered, &chain).unwrap_err().to_string(),
+            verify_proof(tampered, &chain).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn precheck_true_and_false() {
+        let rq = crate::testutil::bart_request();
+        assert_eq!(RequestValidity::Provable, precheck_request(&rq));
+
+        // Bart is 10, not older than 12.
+        let mut older = crate::testutil::bart_request();
+        older.qr.delta = age_to_delta(older.private.birthday, 12, Relation::Older);
+        assert_eq!(RequestValidity::StatementFalse, precheck_request(&older));
+        let mut younger = crate::testutil::bart_request();
+        younger.qr.relation = Relation::Younger;
+        younger.qr.delta = age_to_delta(younger.private.birthday, 12, Relation::Younger);
+        assert_eq!(RequestValidity::Provable, precheck_request(&younger));
+
+        younger.qr.delta += 2;
+        assert_eq!(RequestValidity::DeltaMismatch, precheck_request(&younger));
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_from_many_threads() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let qr = generate_proof(rq).unwrap();
+        let mut tampered = qr.clone();
+        tampered.public.delta += 1;
+
+        let threads: Vec<_> = (0..16)
+            .map(|i| {
+                let qr = if i % 2 == 0 {
+                    qr.clone()
+                } else {
+                    tampered.clone()
+                };
+                let chain = chain.clone();
+                let key = VERIFICATION_KEY.to_vec();
+                std::thread::spawn(move || {
+                    let cached = verify_proof(&qr, &chain).is_ok();
+                    let supplied = verify_proof_with_key(&qr, &chain, &key).is_ok();
+                    (cached, supplied)
+                })
+            })
+            .collect();
+        for (i, thread) in threads.into_iter().enumerate() {
+            let valid = i % 2 == 0;
+            assert_eq!((valid, valid), thread.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn dump_redacts_nonce() {
+        let rq = crate::testutil::bart_request();
+        let nonce = field_from_bytes(&rq.private.nonce).unwrap().to_dec_string();
+        let arguments = build_arguments(&rq).unwrap();
+        let dump = dump_field_elements("arguments", &ARGUMENT_NAMES, &arguments);
+        assert!(!dump.contains(&nonce));
+        assert!(dump.contains("nonce      <redacted>"));
+        assert!(dump.contains("today      2459231"));
+        assert_eq!(1 + ARGUMENT_NAMES.len(), dump.lines().count());
+
+        let inputs = vec![Bn128Field::from(1); PUBLIC_INPUTS];
+        let dump = dump_field_elements("inputs", &INPUT_NAMES, &inputs);
+        assert!(dump.contains("prover_key 1"));
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn verify_payload_bytes() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let payload = generate_proof(rq).unwrap().to_string().into_bytes();
+        assert!(verify_proof_bytes(&payload, &chain).is_ok());
+
+        for bad in &[&payload[..payload.len() / 2], &[0xff, 0xfe, 0x00][..]] {
+            match verify_proof_bytes(bad, &chain) {
+                Err(ZkError::MalformedProof) => {}
+                other => panic!("expected MalformedProof, got {:?}", other),
+            }
+        }
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn accepted_contracts() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let contract = rq.qr.contract.clone();
+        let qr = generate_proof(rq).unwrap();
+        let other = field_to_bytes(&Bn128Field::from(4));
+
+        // The membership tests use `ct_contains`; its timing is not
+        // measured here, only that it finds the same members.
+        assert!(
+            verify_proof_for_contracts(&qr, &chain, &[other.clone(), contract.clone()]).is_ok()
+        );
+        // The accepted one written without its trailing zero bytes.
+        let short = contract[..=contract.iter().rposition(|b| *b != 0).unwrap()].to_vec();
+        assert!(short.len() < contract.len());
+        assert!(verify_proof_for_contracts(&qr, &chain, &[short]).is_ok());
+        match verify_proof_for_contracts(&qr, &chain, &[other]) {
+            Err(ZkError::ContractNotAccepted) => {}
+            other => panic!("expected ContractNotAccepted, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn same_statement_different_proofs() {
+        use crate::testutil::bart_request;
+
+        let first = generate_proof(bart_request()).unwrap();
+        let mut second = generate_proof(bart_request()).unwrap();
+        assert!(first.same_statement(&second));
+        assert_ne!(first.fingerprint(), second.fingerprint());
+
+        second.public.label = Some(String::from("gate 2"));
+        assert!(first.same_statement(&second));
+        second.public.contract.push(0);
+        assert!(first.same_statement(&second));
+
+        let mut rq = bart_request();
+        rq.qr.delta -= 1;
+        assert!(!first.same_statement(&generate_proof(rq).unwrap()));
+        let mut other = first.clone();
+        other.public.relation = Relation::Younger;
+        assert!(!first.same_statement(&other));
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn cancelled_before_proving() {
+        use crate::testutil::bart_request;
+
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+        assert!(matches!(
+            generate_proof_cancellable(bart_request(), &token),
+            Err(ZkError::Cancelled)
+        ));
+
+        let qr = generate_proof_cancellable(bart_request(), &CancellationToken::new()).unwrap();
+        assert!(verify_proof(&qr, &bart_request().chain).is_ok());
+    }
+
+    #[cfg(not(feature = "no-embedded-vk"))]
+    #[test]
+    fn revoked_prover_key() {
+        let rq = crate::testutil::bart_request();
+        let chain = rq.chain.clone();
+        let p = super::generate_proof(rq).unwrap();
+
+        let mut revoked = HashSet::new();
+        revoked.insert(field_to_bytes(&bn128("1")));
+        assert!(verify_proof_with_revocation(&p, &chain, &revoked).is_ok());
+
+        revoked.insert(chain.prover_key.clone());
+        match verify_proof_with_revocation(&p, &chain, &revoked) {
+            Err(ZkError::Revoked) => {}
+            other => panic!("expected Revoked, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,26 @@
+// Runs harla bench-verify on a handful of proofs.
+#![cfg(not(feature = "no-embedded-vk"))]
+
+use assert_cmd::Command;
+
+#[test]
+fn reports_throughput() {
+    let output = Command::cargo_bin("harla")
+        .unwrap()
+        .args(&["bench-verify", "--proofs", "3", "--jobs", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for label in &["serial", "parallel"] {
+        let line = stdout
+            .lines()
+            .find(|l| l.starts_with(label))
+            .unwrap_or_else(|| panic!("no {} line in {}", label, stdout));
+        let rate: f64 = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+        assert!(rate > 0.0, "{}", line);
+    }
+    assert!(stdout.contains("(2 jobs)"));
+    assert!(stdout.contains("p99"));
+}
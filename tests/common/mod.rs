@@ -0,0 +1,71 @@
+// Fixtures shared by the integration tests. Each test binary uses only
+// some of them.
+#![allow(dead_code)]
+
+use harla_zk::api::{ProofQrCode, PublicChain};
+use harla_zk::request::{ProveRequest, ProverDb};
+use harla_zk::zk::generate_proof;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Someone born on 2000-06-15 proving to be older than 18 on
+/// 2021-01-16, as a `prove --stdin` document.
+pub const REQUEST: &str = r#"{
+    "birthday": 2451711,
+    "nonce": "7999",
+    "contract": "4",
+    "photo_hash": "3",
+    "today": 2459231,
+    "relation": "older",
+    "age": 18
+}"#;
+
+/// The secrets of `REQUEST` as in `prover-db.json`.
+pub fn prover_db() -> ProverDb {
+    ProverDb {
+        birthday: 2451711,
+        nonce: String::from("7999"),
+        contract: String::from("4"),
+        photo_hash: String::from("3"),
+    }
+}
+
+/// The proof of `REQUEST` and the chain record to verify it against.
+pub fn proof() -> (ProofQrCode, PublicChain) {
+    let request: ProveRequest = serde_json::from_str(REQUEST).unwrap();
+    let request = request.to_qr_request().unwrap();
+    let chain = request.chain.clone();
+    (generate_proof(request).unwrap(), chain)
+}
+
+/// A temporary directory holding `prover-db.json` of `prover_db`.
+/// Removed when dropped.
+pub struct ProverDir(PathBuf);
+
+impl ProverDir {
+    /// `name` tells the directories of concurrent tests apart.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("harla-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("prover-db.json"),
+            serde_json::to_string(&prover_db()).unwrap(),
+        )
+        .unwrap();
+        ProverDir(dir)
+    }
+
+    pub fn prover_db(&self) -> PathBuf {
+        self.join("prover-db.json")
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, file: P) -> PathBuf {
+        self.0.join(file)
+    }
+}
+
+impl Drop for ProverDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
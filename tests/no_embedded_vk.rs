@@ -0,0 +1,66 @@
+// Verification in builds without the embedded verification key.
+#![cfg(feature = "no-embedded-vk")]
+
+use assert_cmd::Command;
+use harla_zk::error::ZkError;
+use harla_zk::zk::{verify_proof, verify_proof_with_key};
+use std::path::PathBuf;
+
+mod common;
+use common::proof;
+
+fn vk_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("zokrates/verification.key")
+}
+
+#[test]
+fn injected_key_verifies() {
+    let (qr, chain) = proof();
+    assert!(matches!(
+        verify_proof(&qr, &chain),
+        Err(ZkError::NoVerificationKey)
+    ));
+    let vk = std::fs::read(vk_path()).unwrap();
+    assert!(verify_proof_with_key(&qr, &chain, &vk).is_ok());
+}
+
+#[test]
+fn verifier_requires_vk() {
+    let (qr, chain) = proof();
+    let photo_hash = harla_zk::field::field_to_dec_string(&chain.photo_hash);
+    let prover_key = harla_zk::field::field_to_dec_string(&chain.prover_key);
+    let args = ["-", photo_hash.as_str(), prover_key.as_str()];
+
+    let output = Command::cargo_bin("verifier-zk")
+        .unwrap()
+        .args(&args)
+        .write_stdin(qr.to_string())
+        .output()
+        .unwrap();
+    assert_eq!(Some(2), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--vk is required"));
+
+    Command::cargo_bin("verifier-zk")
+        .unwrap()
+        .arg("--vk")
+        .arg(vk_path())
+        .args(&args)
+        .write_stdin(qr.to_string())
+        .assert()
+        .code(0)
+        .stdout("1\n");
+}
+
+#[test]
+fn key_is_not_in_the_binary() {
+    let vk = std::fs::read(vk_path()).unwrap();
+    let binary = std::fs::read(env!("CARGO_BIN_EXE_verifier-zk")).unwrap();
+    let probe = &vk[..vk.len().min(256)];
+    assert!(!binary.windows(probe.len()).any(|w| w == probe));
+    assert_eq!(
+        None,
+        harla_zk::artifacts::artifact_fingerprints()
+            .unwrap()
+            .verification_key
+    );
+}
@@ -0,0 +1,46 @@
+// The common flow written against the prelude alone.
+#![cfg(not(feature = "no-embedded-vk"))]
+
+use chrono::NaiveDate;
+use harla_zk::prelude::*;
+
+/// A field element below 256 in the canonical 32 byte encoding.
+fn small_field(n: u8) -> Vec<u8> {
+    let mut bytes = vec![0; 32];
+    bytes[0] = n;
+    bytes
+}
+
+#[test]
+fn prove_and_verify_with_prelude() -> Result<(), ZkError> {
+    warm_up()?;
+    let clock = FixedClock(NaiveDate::from_ymd(2021, 1, 16));
+    let private = Private {
+        birthday: naive_date_to_jd(NaiveDate::from_ymd(2000, 6, 15)),
+        nonce: generate_nonce(&mut rand::thread_rng()),
+    };
+    let contract = small_field(4);
+    let photo_hash = small_field(3);
+    let chain = PublicChain {
+        prover_key: generate_prover_key(&private, &contract, &photo_hash),
+        photo_hash,
+    };
+    let rq = QrRequest {
+        qr: PublicQr {
+            today: clock.today_jd(),
+            relation: Relation::Older,
+            delta: age_to_delta(private.birthday, 18, Relation::Older),
+            contract,
+            label: None,
+        },
+        chain: chain.clone(),
+        private,
+    };
+
+    let qr: ProofQrCode = generate_proof_with_policy(rq, ProofPolicy::ReturnError)?;
+    let parsed: ProofQrCode = qr.to_string().parse()?;
+    let inputs: VerifiedInputs = verify_proof(&parsed, &chain)?;
+    assert_eq!(Some(inputs.today), jd_to_naive_date(qr.public.today));
+    assert_eq!(clock.today(), inputs.today);
+    Ok(())
+}
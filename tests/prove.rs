@@ -0,0 +1,192 @@
+// Runs the prove binary in the --stdin mode as a server would, and with
+// files as the verifier's scripts do.
+#![cfg(not(feature = "no-embedded-vk"))]
+
+use assert_cmd::Command;
+use harla_zk::api::{ProofQrCode, PublicChain};
+use harla_zk::field::field_from_dec_str;
+use harla_zk::request::{verify_bundle, ProofFile, ProveRequest, VerificationBundle};
+use harla_zk::zk::verify_proof;
+use std::fs;
+use std::str::FromStr;
+use zokrates_field::Field;
+
+mod common;
+use common::{ProverDir, REQUEST};
+
+#[test]
+fn prove_from_stdin() {
+    let output = Command::cargo_bin("prove")
+        .unwrap()
+        .arg("--stdin")
+        .write_stdin(REQUEST)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let qr = ProofQrCode::from_str(String::from_utf8(output.stdout).unwrap().trim()).unwrap();
+    let request: ProveRequest = serde_json::from_str(REQUEST).unwrap();
+    let chain: PublicChain = request.to_qr_request().unwrap().chain;
+    assert_eq!(field_from_dec_str("4").unwrap(), qr.public.contract);
+    assert!(verify_proof(&qr, &chain).is_ok());
+}
+
+#[test]
+fn reject_invalid_request() {
+    Command::cargo_bin("prove")
+        .unwrap()
+        .arg("--stdin")
+        .write_stdin(REQUEST.replace("\"age\": 18", "\"age\": -1"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn verbose_from_stdin() {
+    let output = Command::cargo_bin("prove")
+        .unwrap()
+        .args(&["--stdin", "--verbose"])
+        .write_stdin(REQUEST)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("payload bytes"));
+
+    Command::cargo_bin("prove")
+        .unwrap()
+        .args(&["--stdin", "--today", "2021-01-16"])
+        .write_stdin(REQUEST)
+        .assert()
+        .failure();
+}
+
+/// An age the calendar cannot hold is an error, not a panic.
+#[test]
+fn reject_age_out_of_range() {
+    let dir = ProverDir::new("range");
+    for age in &["1000000", "-1", "eighteen"] {
+        Command::cargo_bin("prove")
+            .unwrap()
+            .args(&["--older", age, "--today", "2021-01-16", "--no-terminal-qr"])
+            .arg("--prover-db")
+            .arg(dir.prover_db())
+            .arg("--proof")
+            .arg(dir.join("proof.json"))
+            .assert()
+            .code(1);
+    }
+}
+
+/// A terminal QR character must be a single one.
+#[test]
+fn reject_long_qr_character() {
+    let dir = ProverDir::new("qr-char");
+    for flag in &["--qr-dark", "--qr-light"] {
+        let output = Command::cargo_bin("prove")
+            .unwrap()
+            .args(&["--older", "18", "--today", "2021-01-16", flag, "ab"])
+            .arg("--prover-db")
+            .arg(dir.prover_db())
+            .arg("--proof")
+            .arg(dir.join("proof.json"))
+            .output()
+            .unwrap();
+        assert_eq!(Some(1), output.status.code());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("single character"));
+    }
+}
+
+/// The proof file is the QR code payload as is, which `verifier-zk`
+/// reads back.
+#[test]
+fn verifier_reads_proof_file() {
+    let dir = ProverDir::new("prove");
+    let prover_key = common::prover_db().prover_key().unwrap().to_dec_string();
+
+    for (name, flags) in &[
+        ("proof.json", &[][..]),
+        ("proof.b45", &["--base45"][..]),
+        ("wrapped.json", &["--proof-format", "json"][..]),
+        ("wrapped.b45", &["--proof-format", "json", "--base45"][..]),
+    ] {
+        let proof_path = dir.join(name);
+        Command::cargo_bin("prove")
+            .unwrap()
+            .args(&["--older", "18", "--today", "2021-01-16", "--no-terminal-qr"])
+            .arg("--prover-db")
+            .arg(dir.prover_db())
+            .arg("--proof")
+            .arg(&proof_path)
+            .arg("--qr")
+            .arg(dir.join("proof-qr.png"))
+            .args(*flags)
+            .assert()
+            .success();
+
+        let written = fs::read_to_string(&proof_path).unwrap();
+        let payload = if flags.contains(&"json") {
+            serde_json::from_str::<ProofFile>(&written).unwrap().qr
+        } else {
+            written
+        };
+        let qr = ProofQrCode::from_str(&payload).unwrap();
+        assert_eq!(payload, qr.encode(qr_encoding(flags)));
+
+        Command::cargo_bin("verifier-zk")
+            .unwrap()
+            .arg(&proof_path)
+            .args(&["3", &prover_key])
+            .assert()
+            .code(0)
+            .stdout("1\n");
+    }
+}
+
+/// A bundle names the chain record too, so nothing else is needed to
+/// verify it.
+#[test]
+fn bundle_verifies_alone() {
+    let dir = ProverDir::new("bundle");
+    let bundle_path = dir.join("bundle.json");
+    Command::cargo_bin("prove")
+        .unwrap()
+        .args(&["--older", "18", "--today", "2021-01-16", "--no-terminal-qr"])
+        .arg("--prover-db")
+        .arg(dir.prover_db())
+        .arg("--proof")
+        .arg(&bundle_path)
+        .arg("--qr")
+        .arg(dir.join("proof-qr.png"))
+        .args(&["--proof-format", "bundle"])
+        .assert()
+        .success();
+
+    let bundle: VerificationBundle =
+        serde_json::from_str(&fs::read_to_string(&bundle_path).unwrap()).unwrap();
+    assert_eq!("3", bundle.photo_hash);
+    assert!(verify_bundle(&bundle).is_ok());
+    let reparsed: VerificationBundle =
+        serde_json::from_str(&serde_json::to_string(&bundle).unwrap()).unwrap();
+    assert_eq!(bundle, reparsed);
+
+    Command::cargo_bin("verifier-zk")
+        .unwrap()
+        .arg(&bundle_path)
+        .assert()
+        .code(0)
+        .stdout("1\n");
+
+    let tampered = VerificationBundle {
+        photo_hash: String::from("12345"),
+        ..bundle
+    };
+    assert!(verify_bundle(&tampered).is_err());
+}
+
+fn qr_encoding(flags: &[&str]) -> harla_zk::api::QrEncoding {
+    if flags.contains(&"--base45") {
+        harla_zk::api::QrEncoding::Base45
+    } else {
+        harla_zk::api::QrEncoding::Json
+    }
+}
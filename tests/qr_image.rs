@@ -0,0 +1,71 @@
+// Verifies proofs from QR code images as a gate app does.
+#![cfg(all(feature = "qr", not(feature = "no-embedded-vk")))]
+
+use harla_zk::error::ZkError;
+use harla_zk::qr::{render_qr_image, save_qr_images, verify_qr_image, ImageFormat};
+use harla_zk::uri::MAX_IMAGE_SIDE;
+use image::{imageops, GrayImage, Luma};
+use std::path::PathBuf;
+
+mod common;
+use common::proof;
+
+fn temp_png(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("harla-{}-{}.png", name, std::process::id()))
+}
+
+#[test]
+fn verify_rendered_png() {
+    let (qr, chain) = proof();
+    let path = temp_png("proof");
+    save_qr_images(&qr, &[&path]).unwrap();
+    let result = verify_qr_image(&path, &chain);
+    std::fs::remove_file(&path).unwrap();
+
+    let inputs = result.unwrap();
+    assert_eq!(qr.public.delta, inputs.delta);
+    assert_eq!(qr.public.relation, inputs.relation);
+}
+
+#[test]
+fn no_code_and_two_codes() {
+    let (qr, chain) = proof();
+    let png = render_qr_image(&qr, ImageFormat::Png).unwrap();
+    let code = image::load_from_memory(&png).unwrap().to_luma8();
+
+    let blank = GrayImage::from_pixel(code.width(), code.height(), Luma([255]));
+    let mut two = GrayImage::from_pixel(code.width() * 2, code.height(), Luma([255]));
+    imageops::replace(&mut two, &code, 0, 0);
+    imageops::replace(&mut two, &code, code.width(), 0);
+
+    let blank_path = temp_png("blank");
+    let two_path = temp_png("two");
+    blank.save(&blank_path).unwrap();
+    two.save(&two_path).unwrap();
+    let blank_result = verify_qr_image(&blank_path, &chain);
+    let two_result = verify_qr_image(&two_path, &chain);
+    std::fs::remove_file(&blank_path).unwrap();
+    std::fs::remove_file(&two_path).unwrap();
+
+    assert!(matches!(blank_result, Err(ZkError::NoQrCode)));
+    assert!(matches!(two_result, Err(ZkError::MultipleQrCodes(2))));
+    assert!(matches!(
+        verify_qr_image(temp_png("missing"), &chain),
+        Err(ZkError::Io(_))
+    ));
+}
+
+#[test]
+fn reject_oversize_image() {
+    let (_, chain) = proof();
+    let path = temp_png("wide");
+    GrayImage::from_pixel(MAX_IMAGE_SIDE + 1, 1, Luma([255]))
+        .save(&path)
+        .unwrap();
+    let result = verify_qr_image(&path, &chain);
+    std::fs::remove_file(&path).unwrap();
+    match result {
+        Err(ZkError::QrScan(why)) => assert!(why.contains("at most")),
+        other => panic!("expected QrScan, got {:?}", other.map(|_| ())),
+    }
+}
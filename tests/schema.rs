@@ -0,0 +1,52 @@
+// Checks documents as integrators write them against `harla schema`.
+#![cfg(feature = "schema")]
+
+use assert_cmd::Command;
+use harla_zk::request::json_schema;
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+
+mod common;
+
+fn schema(name: &str) -> JSONSchema {
+    JSONSchema::compile(&json_schema(name).unwrap()).unwrap()
+}
+
+#[test]
+fn prover_db_fixtures() {
+    let prover_db = schema("prover-db");
+    let valid = json!({
+        "birthday": 2451711,
+        "nonce": "7999",
+        "contract": "4",
+        "photo_hash": "3",
+        "prover_key": "12345"
+    });
+    assert!(prover_db.is_valid(&valid));
+
+    let mut numeric_nonce = valid.clone();
+    numeric_nonce["nonce"] = json!(7999);
+    let mut hex_contract = valid.clone();
+    hex_contract["contract"] = json!("0x04");
+    let mut missing = valid;
+    missing.as_object_mut().unwrap().remove("photo_hash");
+    for bad in &[numeric_nonce, hex_contract, missing] {
+        assert!(!prover_db.is_valid(bad), "{}", bad);
+    }
+}
+
+#[test]
+fn proof_payload() {
+    let (qr, _) = common::proof();
+    let payload: Value = serde_json::from_str(&qr.to_string()).unwrap();
+    assert!(schema("proof").is_valid(&payload));
+
+    let printed = Command::cargo_bin("harla")
+        .unwrap()
+        .args(&["schema", "proof"])
+        .output()
+        .unwrap();
+    assert!(printed.status.success());
+    let printed: Value = serde_json::from_slice(&printed.stdout).unwrap();
+    assert_eq!(json_schema("proof").unwrap(), printed);
+}
@@ -0,0 +1,185 @@
+// Runs the verifier-zk binary as a verifier's script would.
+#![cfg(not(feature = "no-embedded-vk"))]
+
+use assert_cmd::Command;
+use harla_zk::field::{field_from_dec_str, field_to_dec_string};
+
+mod common;
+
+const PHOTO_HASH: &str = "3";
+const CONTRACT: &str = "4";
+
+/// The payload of `common::REQUEST` and its prover key.
+fn valid_proof() -> (String, String) {
+    let (qr, chain) = common::proof();
+    (qr.to_string(), field_to_dec_string(&chain.prover_key))
+}
+
+fn verifier() -> Command {
+    Command::cargo_bin("verifier-zk").unwrap()
+}
+
+#[test]
+fn valid_proof_from_stdin() {
+    let (payload, prover_key) = valid_proof();
+    verifier()
+        .args(&["-", PHOTO_HASH, &prover_key])
+        .write_stdin(payload)
+        .assert()
+        .code(0)
+        .stdout("1\n");
+}
+
+#[test]
+fn invalid_proof() {
+    let (payload, _) = valid_proof();
+    verifier()
+        .args(&["-", PHOTO_HASH, "12345"])
+        .write_stdin(payload)
+        .assert()
+        .code(1)
+        .stdout("0\n");
+}
+
+#[test]
+fn hex_chain_values() {
+    let (payload, prover_key) = valid_proof();
+    let mut bytes = field_from_dec_str(&prover_key).unwrap();
+    bytes.reverse();
+    let hex_key = format!("0x{}", hex::encode(bytes));
+    verifier()
+        .args(&["-", "0x03", &hex_key])
+        .write_stdin(payload.clone())
+        .assert()
+        .code(0)
+        .stdout("1\n");
+    verifier()
+        .args(&["-", "0x03", &hex_key[2..]])
+        .write_stdin(payload)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn json_outcome() {
+    let (payload, prover_key) = valid_proof();
+    let output = verifier()
+        .args(&["--json", "-", PHOTO_HASH, &prover_key])
+        .write_stdin(payload.clone())
+        .output()
+        .unwrap();
+    assert_eq!(Some(0), output.status.code());
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(true, outcome["valid"]);
+    let inputs = &outcome["inputs"];
+    assert_eq!("2021-01-16", inputs["today"]);
+    assert_eq!("older", inputs["relation"]);
+    assert_eq!(CONTRACT, inputs["contract"]);
+    assert_eq!(PHOTO_HASH, inputs["photo_hash"]);
+    assert_eq!(prover_key.as_str(), inputs["prover_key"]);
+
+    let output = verifier()
+        .args(&["-", PHOTO_HASH, "12345", "--json"])
+        .write_stdin(payload)
+        .output()
+        .unwrap();
+    assert_eq!(Some(1), output.status.code());
+    let outcome: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(false, outcome["valid"]);
+    assert!(outcome.get("inputs").is_none());
+    assert_eq!("proof verification failed", outcome["error"]);
+}
+
+#[test]
+fn malformed_payload() {
+    verifier()
+        .args(&["-", PHOTO_HASH, "12345"])
+        .write_stdin("not a proof")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn missing_arguments() {
+    verifier().arg("-").assert().code(2);
+}
+
+#[test]
+fn verification_key_from_file() {
+    let vk = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("zokrates/verification.key");
+    let (payload, prover_key) = valid_proof();
+    verifier()
+        .args(&["--vk", vk.to_str().unwrap(), "-", PHOTO_HASH, &prover_key])
+        .write_stdin(payload.clone())
+        .assert()
+        .code(0)
+        .stdout("1\n");
+    verifier()
+        .args(&["-", PHOTO_HASH, &prover_key, "--vk", "missing.key"])
+        .write_stdin(payload)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn json_audit_line() {
+    let (payload, prover_key) = valid_proof();
+    let output = verifier()
+        .args(&["--log-format", "json", "-", PHOTO_HASH, &prover_key])
+        .write_stdin(payload)
+        .output()
+        .unwrap();
+    assert_eq!(Some(0), output.status.code());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(1, lines.len());
+
+    let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!("valid", line["outcome"]);
+    assert_eq!("older", line["relation"]);
+    assert_eq!(2459231, line["today"]);
+    assert_eq!(64, line["fingerprint"].as_str().unwrap().len());
+    assert!(chrono::DateTime::parse_from_rfc3339(line["timestamp"].as_str().unwrap()).is_ok());
+    assert!(!stderr.contains(&prover_key));
+}
+
+#[test]
+fn unknown_log_format() {
+    verifier()
+        .args(&["--log-format", "xml", "-", PHOTO_HASH, "1"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn artifacts_from_environment() {
+    let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("zokrates");
+    let dir = std::env::temp_dir().join(format!("harla-env-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut cmd = verifier();
+    for (var, file) in &[
+        ("HARLA_PROGRAM", "out"),
+        ("HARLA_ABI", "abi.json"),
+        ("HARLA_PROVING_KEY", "proving.key"),
+        ("HARLA_VERIFICATION_KEY", "verification.key"),
+    ] {
+        std::fs::copy(src.join(file), dir.join(file)).unwrap();
+        cmd.env(var, dir.join(file));
+    }
+
+    let (payload, prover_key) = valid_proof();
+    cmd.args(&["-", PHOTO_HASH, &prover_key])
+        .write_stdin(payload)
+        .assert()
+        .code(0)
+        .stdout("1\n");
+
+    verifier()
+        .env("HARLA_VERIFICATION_KEY", dir.join("missing.key"))
+        .args(&["-", PHOTO_HASH, "1"])
+        .write_stdin(valid_proof().0)
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}